@@ -0,0 +1,854 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fs,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, LazyLock, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::{
+    common::utils::{env_checked, env_list, env_or, log_message},
+    core::fee::FeeConfig,
+    engine::{exit::ExitConfig, risk::RiskConfig},
+};
+
+/// Configuration file, `CONFIG_PATH` (default `./config.toml`)
+pub static CONFIG_PATH: LazyLock<String> =
+    LazyLock::new(|| env_or("CONFIG_PATH", "./config.toml".to_string()));
+
+/// Slippage above which the configuration is accepted with a warning, in basis points
+pub const SLIPPAGE_WARN_BPS: u64 = 2_000;
+
+/// Seconds between checks of the config file for changes (`CONFIG_RELOAD_SECS`, 0 disables)
+pub static CONFIG_RELOAD_SECS: LazyLock<u64> = LazyLock::new(|| env_or("CONFIG_RELOAD_SECS", 2));
+
+static CONFIG: LazyLock<RwLock<Arc<Config>>> = LazyLock::new(|| {
+    let config = Config::load(&CONFIG_PATH).unwrap_or_else(|e| panic!("{}", e));
+    RwLock::new(Arc::new(config))
+});
+// latest configuration, for tasks that act when it changes
+static UPDATES: LazyLock<watch::Sender<Arc<Config>>> = LazyLock::new(|| watch::channel(config()).0);
+// runtime changes, e.g. from the control API, applied again on top of every reload
+static OVERLAY: LazyLock<RwLock<Vec<Change>>> = LazyLock::new(|| RwLock::new(vec![]));
+
+/// Change to the configuration made at runtime. It is replayed on every reload, so it must
+/// give the same result when applied twice.
+pub type Change = Box<dyn Fn(&mut Config) + Send + Sync>;
+
+/// Current configuration.
+pub fn config() -> Arc<Config> {
+    CONFIG
+        .read()
+        .map(|config| config.clone())
+        .unwrap_or_else(|e| e.into_inner().clone())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Endpoints {
+    /// HTTP RPC (`RPC_ENDPOINT`)
+    pub rpc: String,
+    /// Websocket RPC (`RPC_WEBSOCKET_ENDPOINT`)
+    pub websocket: String,
+    /// Jito block engine (`JITO_BLOCK_ENGINE_URL`)
+    pub jito_block_engine: String,
+    /// Jito tip stream (`JITO_TIP_STREAM_URL`)
+    pub jito_tip_stream: String,
+    /// Percentile of the tip stream to tip at (`JITO_TIP_PERCENTILE`)
+    pub jito_tip_percentile: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            rpc: String::new(),
+            websocket: String::new(),
+            jito_block_engine: "https://mainnet.block-engine.jito.wtf".to_string(),
+            jito_tip_stream: "ws://bundles-api-rest.jito.wtf/api/v1/bundles/tip_stream".to_string(),
+            jito_tip_percentile: "50".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalletConfig {
+    /// Keypair file: Solana CLI JSON, base58 or an encrypted keystore (`KEYPAIR_PATH`)
+    pub keypair_path: String,
+    /// How copies are spread over the pool (`WALLET_ALLOCATION`)
+    pub allocation: Allocation,
+    /// Execution wallets, funded from the main wallet when the allocation is not `single`
+    pub pool: Vec<PoolWalletConfig>,
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        Self {
+            keypair_path: "./key.txt".to_string(),
+            allocation: Allocation::Single,
+            pool: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Allocation {
+    /// Every copy is executed by the main wallet
+    Single,
+    /// Each target is copied by the pool wallet listing it, round-robin for the others
+    PerTarget,
+    /// Copies rotate over the pool wallets
+    RoundRobin,
+    /// Each copy is split evenly over the pool wallets
+    Split,
+}
+
+impl FromStr for Allocation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "single" => Ok(Allocation::Single),
+            "per-target" => Ok(Allocation::PerTarget),
+            "round-robin" => Ok(Allocation::RoundRobin),
+            "split" => Ok(Allocation::Split),
+            _ => Err(anyhow!("unknown wallet allocation {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoolWalletConfig {
+    pub keypair_path: String,
+    pub label: Option<String>,
+    /// Targets this wallet copies under `per-target`
+    #[serde(default)]
+    pub targets: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetConfig {
+    pub address: String,
+    pub label: Option<String>,
+    /// A paused target stays in the journal but is not copied
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Overrides `trading.size_pct` for this target
+    pub size_pct: Option<u64>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TradingConfig {
+    /// Share of the target's trade copied, in percent (`SIZE_PCT`)
+    pub size_pct: u64,
+    /// Slippage tolerance in basis points (`SLIPPAGE_BPS`)
+    pub slippage_bps: u64,
+    /// Transactions touching these accounts are not copied, e.g. Jupiter routes (`JUP_PUBKEY`)
+    pub exclude_accounts: Vec<String>,
+}
+
+impl Default for TradingConfig {
+    fn default() -> Self {
+        Self {
+            size_pct: 100,
+            slippage_bps: 300,
+            exclude_accounts: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VenueConfig {
+    /// Copy pump.fun bonding curve trades (`VENUE_PUMP`)
+    pub pump: bool,
+    /// Copy Raydium AMM trades (`VENUE_RAYDIUM`)
+    pub raydium: bool,
+}
+
+impl Default for VenueConfig {
+    fn default() -> Self {
+        Self {
+            pump: true,
+            raydium: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub endpoints: Endpoints,
+    pub wallet: WalletConfig,
+    pub targets: Vec<TargetConfig>,
+    pub trading: TradingConfig,
+    pub venues: VenueConfig,
+    pub fees: FeeConfig,
+    pub risk: RiskConfig,
+    pub exit: ExitConfig,
+    /// Environment variables that did not parse, reported by `validate`
+    #[serde(skip)]
+    pub env_errors: Vec<String>,
+}
+
+fn override_string(value: &mut String, key: &str) {
+    if let Ok(v) = env::var(key) {
+        *value = v;
+    }
+}
+
+impl Config {
+    /// Read the file if it exists and apply the environment on top, without validating.
+    pub fn read(path: &str) -> Result<Self> {
+        let mut config = if Path::new(path).exists() {
+            let text = fs::read_to_string(path)?;
+            toml::from_str::<Config>(&text).map_err(|e| anyhow!("Config: {}: {}", path, e))?
+        } else {
+            Config::default()
+        };
+        config.apply_env();
+        Ok(config)
+    }
+
+    /// Read the file if it exists, apply the environment on top and validate the result.
+    pub fn load(path: &str) -> Result<Self> {
+        Self::read(path)?.checked(path)
+    }
+
+    // validated, with the warnings printed
+    fn checked(self, path: &str) -> Result<Self> {
+        self.validate().map_err(|errors| {
+            anyhow!(
+                "Config: {} is invalid:\n  - {}",
+                path,
+                errors.join("\n  - ")
+            )
+        })?;
+        for warning in self.warnings() {
+            warn!(path, "Config: {}", warning);
+        }
+        Ok(self)
+    }
+
+    /// Environment variables take precedence over the file.
+    fn apply_env(&mut self) {
+        let mut errors = vec![];
+        override_string(&mut self.endpoints.rpc, "RPC_ENDPOINT");
+        override_string(&mut self.endpoints.websocket, "RPC_WEBSOCKET_ENDPOINT");
+        override_string(
+            &mut self.endpoints.jito_block_engine,
+            "JITO_BLOCK_ENGINE_URL",
+        );
+        override_string(&mut self.endpoints.jito_tip_stream, "JITO_TIP_STREAM_URL");
+        override_string(
+            &mut self.endpoints.jito_tip_percentile,
+            "JITO_TIP_PERCENTILE",
+        );
+        override_string(&mut self.wallet.keypair_path, "KEYPAIR_PATH");
+        self.wallet.allocation =
+            env_checked("WALLET_ALLOCATION", self.wallet.allocation, &mut errors);
+        let targets = env_list("TARGET_PUBKEY");
+        if !targets.is_empty() {
+            self.targets = targets
+                .into_iter()
+                .map(|address| TargetConfig {
+                    address,
+                    label: None,
+                    enabled: true,
+                    size_pct: None,
+                })
+                .collect();
+        }
+        self.trading.size_pct = env_checked("SIZE_PCT", self.trading.size_pct, &mut errors);
+        self.trading.slippage_bps =
+            env_checked("SLIPPAGE_BPS", self.trading.slippage_bps, &mut errors);
+        let excluded = env_list("JUP_PUBKEY");
+        if !excluded.is_empty() {
+            self.trading.exclude_accounts = excluded;
+        }
+        self.venues.pump = env_checked("VENUE_PUMP", self.venues.pump, &mut errors);
+        self.venues.raydium = env_checked("VENUE_RAYDIUM", self.venues.raydium, &mut errors);
+        self.fees = self.fees.clone().env_overrides(&mut errors);
+        self.risk = self.risk.clone().env_overrides(&mut errors);
+        self.exit = self.exit.clone().env_overrides(&mut errors);
+        self.env_errors = errors;
+    }
+
+    /// Every problem found, so they can all be fixed in one go.
+    pub fn validate(&self) -> std::result::Result<(), Vec<String>> {
+        let mut errors = self.env_errors.clone();
+        let mut url = |name: &str, value: &str, schemes: &[&str]| {
+            if value.is_empty() {
+                errors.push(format!("{} is not set", name));
+            } else if !schemes.iter().any(|scheme| value.starts_with(scheme)) {
+                errors.push(format!(
+                    "{} must start with {}: {}",
+                    name,
+                    schemes.join(" or "),
+                    value
+                ));
+            }
+        };
+        url(
+            "endpoints.rpc",
+            &self.endpoints.rpc,
+            &["http://", "https://"],
+        );
+        url(
+            "endpoints.websocket",
+            &self.endpoints.websocket,
+            &["ws://", "wss://"],
+        );
+        url(
+            "endpoints.jito_block_engine",
+            &self.endpoints.jito_block_engine,
+            &["http://", "https://"],
+        );
+
+        if !Path::new(&self.wallet.keypair_path).exists() {
+            errors.push(format!(
+                "wallet.keypair_path: {} does not exist",
+                self.wallet.keypair_path
+            ));
+        }
+
+        if self.wallet.allocation != Allocation::Single && self.wallet.pool.is_empty() {
+            errors.push(
+                "wallet.pool: an allocation other than single needs pool wallets".to_string(),
+            );
+        }
+        let mut paths = HashSet::new();
+        for (i, wallet) in self.wallet.pool.iter().enumerate() {
+            if !Path::new(&wallet.keypair_path).exists() {
+                errors.push(format!(
+                    "wallet.pool[{}].keypair_path: {} does not exist",
+                    i, wallet.keypair_path
+                ));
+            }
+            if !paths.insert(&wallet.keypair_path)
+                || wallet.keypair_path == self.wallet.keypair_path
+            {
+                errors.push(format!(
+                    "wallet.pool[{}].keypair_path: {} listed twice",
+                    i, wallet.keypair_path
+                ));
+            }
+            for target in wallet.targets.iter() {
+                if Pubkey::from_str(target).is_err() {
+                    errors.push(format!(
+                        "wallet.pool[{}].targets: invalid pubkey {}",
+                        i, target
+                    ));
+                }
+            }
+        }
+
+        if self.targets.is_empty() {
+            errors.push("targets: no target to copy".to_string());
+        }
+        let mut seen = HashSet::new();
+        for (i, target) in self.targets.iter().enumerate() {
+            if Pubkey::from_str(&target.address).is_err() {
+                errors.push(format!(
+                    "targets[{}].address: invalid pubkey {}",
+                    i, target.address
+                ));
+            }
+            if !seen.insert(&target.address) {
+                errors.push(format!(
+                    "targets[{}].address: {} listed twice",
+                    i, target.address
+                ));
+            }
+            if target.size_pct == Some(0) {
+                errors.push(format!("targets[{}].size_pct must be above 0", i));
+            }
+        }
+        for account in self.trading.exclude_accounts.iter() {
+            if Pubkey::from_str(account).is_err() {
+                errors.push(format!(
+                    "trading.exclude_accounts: invalid pubkey {}",
+                    account
+                ));
+            }
+        }
+        if self.trading.size_pct == 0 {
+            errors.push("trading.size_pct must be above 0".to_string());
+        }
+        if self.trading.slippage_bps == 0 || self.trading.slippage_bps > 10000 {
+            errors.push(format!(
+                "trading.slippage_bps must be within 1..=10000: {}",
+                self.trading.slippage_bps
+            ));
+        }
+        if !self.venues.pump && !self.venues.raydium {
+            errors.push("venues: every venue is disabled".to_string());
+        }
+
+        if self.fees.percentile > 100 {
+            errors.push(format!(
+                "fees.percentile must be within 0..=100: {}",
+                self.fees.percentile
+            ));
+        }
+        if self.fees.min_unit_price > self.fees.max_unit_price {
+            errors.push(format!(
+                "fees.min_unit_price {} is above fees.max_unit_price {}",
+                self.fees.min_unit_price, self.fees.max_unit_price
+            ));
+        }
+        if self.fees.unit_limit == 0 || self.fees.unit_limit > 1_400_000 {
+            errors.push(format!(
+                "fees.unit_limit must be within 1..=1400000: {}",
+                self.fees.unit_limit
+            ));
+        }
+
+        if self.risk.max_open_positions == 0 {
+            errors.push("risk.max_open_positions must be above 0".to_string());
+        }
+        if self.risk.max_sol_per_trade == 0 {
+            errors.push("risk.max_sol_per_trade must be above 0".to_string());
+        }
+        if self.risk.max_consecutive_failures == 0 {
+            errors.push("risk.max_consecutive_failures must be above 0".to_string());
+        }
+
+        if self.exit.slippage == 0 || self.exit.slippage > 10000 {
+            errors.push(format!(
+                "exit.slippage_bps must be within 1..=10000: {}",
+                self.exit.slippage
+            ));
+        }
+        for (name, pct) in [
+            ("exit.stop_loss_pct", self.exit.stop_loss_pct),
+            ("exit.trailing_stop_pct", self.exit.trailing_stop_pct),
+        ] {
+            if pct.is_some_and(|pct| pct >= 100.0) {
+                errors.push(format!(
+                    "{} must be below 100: {}",
+                    name,
+                    pct.unwrap_or(0.0)
+                ));
+            }
+        }
+        if self
+            .exit
+            .take_profit
+            .iter()
+            .any(|rung| rung.multiple <= 0.0 || rung.sell_pct == 0)
+        {
+            errors.push(
+                "exit.take_profit: every rung needs a multiple and a share above 0".to_string(),
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Settings that are valid but probably a mistake.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        if self.trading.slippage_bps > SLIPPAGE_WARN_BPS {
+            warnings.push(format!(
+                "trading.slippage_bps {} is above {}, copies may fill far from the quote",
+                self.trading.slippage_bps, SLIPPAGE_WARN_BPS
+            ));
+        }
+        if self.exit.slippage > SLIPPAGE_WARN_BPS {
+            warnings.push(format!(
+                "exit.slippage_bps {} is above {}, exits may fill far from the quote",
+                self.exit.slippage, SLIPPAGE_WARN_BPS
+            ));
+        }
+        warnings
+    }
+
+    /// Enabled target with this address.
+    pub fn target(&self, address: &str) -> Option<&TargetConfig> {
+        self.targets
+            .iter()
+            .find(|target| target.enabled && target.address == address)
+    }
+
+    pub fn enabled_targets(&self) -> impl Iterator<Item = &TargetConfig> {
+        self.targets.iter().filter(|target| target.enabled)
+    }
+
+    /// Share of the target's trades to copy, in percent.
+    pub fn size_pct(&self, target: &str) -> u64 {
+        self.target(target)
+            .and_then(|target| target.size_pct)
+            .unwrap_or(self.trading.size_pct)
+    }
+}
+
+/// Load and validate the configuration, to be called once on startup so a bad file stops the
+/// bot with every problem listed.
+pub fn init() -> Result<Arc<Config>> {
+    let config = Arc::new(Config::load(&CONFIG_PATH)?);
+    if let Ok(mut current) = CONFIG.write() {
+        *current = config.clone();
+    }
+    Ok(config)
+}
+
+/// Notified with the new configuration after every reload.
+pub fn subscribe() -> watch::Receiver<Arc<Config>> {
+    UPDATES.subscribe()
+}
+
+/// Reload the file and swap in the new configuration if it is valid. Runtime changes made with
+/// `update` are applied again on top of the file. Targets, sizing, risk limits, fees and venues
+/// apply from the next trade, exit rules from the next price update; endpoints and the wallet are kept until a restart since the
+/// clients are built once. Returns the changes, empty when nothing changed.
+pub fn reload() -> Result<Vec<String>> {
+    let current = config();
+    let mut next = Config::read(&CONFIG_PATH)?;
+    if let Ok(overlay) = OVERLAY.read() {
+        for change in overlay.iter() {
+            change(&mut next);
+        }
+    }
+    let mut next = next.checked(&CONFIG_PATH)?;
+    let mut changes = diff(&current, &next);
+    if next.endpoints != current.endpoints || next.wallet != current.wallet {
+        changes.push("endpoints and wallet are applied after a restart".to_string());
+        next.endpoints = current.endpoints.clone();
+        next.wallet = current.wallet.clone();
+    }
+    if changes.is_empty() {
+        return Ok(changes);
+    }
+    let next = Arc::new(next);
+    if let Ok(mut config) = CONFIG.write() {
+        *config = next.clone();
+    }
+    UPDATES.send_replace(next);
+    Ok(changes)
+}
+
+/// Change the running configuration, e.g. from the control API. The result must validate; it
+/// is published like a reload and kept over later reloads of the file, until the bot restarts.
+/// Returns the changes.
+pub fn update(change: impl Fn(&mut Config) + Send + Sync + 'static) -> Result<Vec<String>> {
+    let current = config();
+    let mut next = (*current).clone();
+    change(&mut next);
+    next.validate()
+        .map_err(|errors| anyhow!("Config: {}", errors.join(", ")))?;
+    for warning in next.warnings() {
+        warn!("Config: {}", warning);
+    }
+    if let Ok(mut overlay) = OVERLAY.write() {
+        overlay.push(Box::new(change));
+    }
+    let changes = diff(&current, &next);
+    if changes.is_empty() {
+        return Ok(changes);
+    }
+    let next = Arc::new(next);
+    if let Ok(mut config) = CONFIG.write() {
+        *config = next.clone();
+    }
+    UPDATES.send_replace(next);
+    Ok(changes)
+}
+
+/// Changes from `old` to `new`, one line per target added or removed and per setting changed.
+pub fn diff(old: &Config, new: &Config) -> Vec<String> {
+    let mut changes = vec![];
+    let known = |config: &Config, address: &str| {
+        config
+            .targets
+            .iter()
+            .any(|target| target.address == address)
+    };
+    for target in new.targets.iter() {
+        if !known(old, &target.address) {
+            changes.push(format!("target {} added", target.address));
+        }
+    }
+    for target in old.targets.iter() {
+        if !known(new, &target.address) {
+            changes.push(format!("target {} removed", target.address));
+        }
+    }
+    let before = settings(old);
+    for (key, value) in settings(new) {
+        match before.get(&key) {
+            Some(previous) if *previous != value => {
+                changes.push(format!("{}: {} -> {}", key, previous, value))
+            }
+            _ => {}
+        }
+    }
+    changes
+}
+
+// every setting by dotted path, with targets keyed by address rather than position
+fn settings(config: &Config) -> BTreeMap<String, String> {
+    let mut value = serde_json::to_value(config).unwrap_or_default();
+    value["targets"] = Value::Object(
+        config
+            .targets
+            .iter()
+            .map(|target| {
+                let settings = serde_json::to_value(target).unwrap_or_default();
+                (target.address.clone(), settings)
+            })
+            .collect(),
+    );
+    let mut settings = BTreeMap::new();
+    flatten("", &value, &mut settings);
+    settings
+}
+
+fn flatten(path: &str, value: &Value, settings: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                flatten(&path, value, settings);
+            }
+        }
+        _ => {
+            settings.insert(path.to_string(), value.to_string());
+        }
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Poll the config file every `CONFIG_RELOAD_SECS` and reload it when it is modified. An
+/// invalid file is reported and the running configuration kept.
+pub fn spawn_watcher() {
+    if *CONFIG_RELOAD_SECS == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut modified = modified_at(&CONFIG_PATH);
+        let mut ticker = tokio::time::interval(Duration::from_secs(*CONFIG_RELOAD_SECS));
+        loop {
+            ticker.tick().await;
+            let now = modified_at(&CONFIG_PATH);
+            if now == modified {
+                continue;
+            }
+            modified = now;
+            match reload() {
+                Ok(changes) if changes.is_empty() => {}
+                Ok(changes) => {
+                    let message = format!("config reloaded:\n  {}", changes.join("\n  "));
+                    info!("{}", message);
+                    log_message(&message);
+                }
+                Err(e) => {
+                    warn!(error = %e, "config reload failed, keeping the running configuration")
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("config-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // passes `validate`, with the keypair at `keypair_path`
+    fn valid(keypair_path: &str) -> Config {
+        let mut config = Config::default();
+        config.endpoints.rpc = "https://rpc.example".to_string();
+        config.endpoints.websocket = "wss://rpc.example".to_string();
+        config.wallet.keypair_path = keypair_path.to_string();
+        config.targets.push(TargetConfig {
+            address: Pubkey::new_unique().to_string(),
+            label: None,
+            enabled: true,
+            size_pct: None,
+        });
+        config
+    }
+
+    fn keypair_file(dir: &Path, name: &str) -> String {
+        let path = dir.join(name);
+        fs::write(&path, "[]").unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn errors(config: &Config) -> Vec<String> {
+        config.validate().err().unwrap_or_default()
+    }
+
+    // one test, the environment is process wide
+    #[test]
+    fn environment_overrides_the_file_and_reports_what_does_not_parse() {
+        let dir = temp_dir("env");
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            "[trading]\nsize_pct = 50\nslippage_bps = 500\n\n[venues]\nraydium = false\n",
+        )
+        .unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        env::set_var("SLIPPAGE_BPS", "700");
+        env::set_var("VENUE_RAYDIUM", "sometimes");
+        let read = Config::read(&path);
+        env::remove_var("SLIPPAGE_BPS");
+        env::remove_var("VENUE_RAYDIUM");
+        let config = read.unwrap();
+
+        assert_eq!(config.trading.slippage_bps, 700);
+        assert_eq!(config.trading.size_pct, 50);
+        // the file value is kept when the variable does not parse
+        assert!(!config.venues.raydium);
+        assert_eq!(
+            config.env_errors,
+            vec!["VENUE_RAYDIUM: cannot parse \"sometimes\"".to_string()]
+        );
+        let keypair = keypair_file(&dir, "key.json");
+        let config = Config {
+            endpoints: valid(&keypair).endpoints,
+            wallet: valid(&keypair).wallet,
+            targets: valid(&keypair).targets,
+            ..config
+        };
+        assert_eq!(
+            errors(&config),
+            vec!["VENUE_RAYDIUM: cannot parse \"sometimes\"".to_string()]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_in_the_file_are_rejected() {
+        let dir = temp_dir("unknown");
+        let path = dir.join("config.toml");
+        fs::write(&path, "[trading]\nslipage_bps = 500\n").unwrap();
+        let err = Config::read(&path.to_string_lossy()).unwrap_err();
+        assert!(err.to_string().contains("slipage_bps"), "{}", err);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn slippage_is_bounded_and_warned_about_when_high() {
+        let dir = temp_dir("slippage");
+        let mut config = valid(&keypair_file(&dir, "key.json"));
+        assert!(config.validate().is_ok());
+        assert!(config.warnings().is_empty());
+
+        for slippage in [0, 10_001] {
+            config.trading.slippage_bps = slippage;
+            assert_eq!(
+                errors(&config),
+                vec![format!(
+                    "trading.slippage_bps must be within 1..=10000: {}",
+                    slippage
+                )]
+            );
+        }
+        config.trading.slippage_bps = SLIPPAGE_WARN_BPS;
+        assert!(config.validate().is_ok());
+        assert!(config.warnings().is_empty());
+        config.trading.slippage_bps = 10_000;
+        assert!(config.validate().is_ok());
+        assert_eq!(config.warnings().len(), 1);
+
+        config.trading.slippage_bps = 300;
+        config.exit.slippage = 0;
+        assert_eq!(errors(&config).len(), 1);
+        config.exit.slippage = SLIPPAGE_WARN_BPS + 1;
+        assert!(config.validate().is_ok());
+        assert!(config.warnings()[0].starts_with("exit.slippage_bps"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pool_allocations_need_distinct_existing_wallets() {
+        let dir = temp_dir("pool");
+        let main = keypair_file(&dir, "main.json");
+        let mut config = valid(&main);
+
+        config.wallet.allocation = Allocation::Split;
+        assert_eq!(
+            errors(&config),
+            vec!["wallet.pool: an allocation other than single needs pool wallets".to_string()]
+        );
+
+        let pool_wallet = |keypair_path: &str| PoolWalletConfig {
+            keypair_path: keypair_path.to_string(),
+            label: None,
+            targets: vec![],
+        };
+        let first = keypair_file(&dir, "first.json");
+        config.wallet.pool = vec![pool_wallet(&first)];
+        assert!(config.validate().is_ok());
+
+        config.wallet.pool.push(pool_wallet(&first));
+        config.wallet.pool.push(pool_wallet(&main));
+        let missing = dir.join("missing.json").to_string_lossy().into_owned();
+        config.wallet.pool.push(pool_wallet(&missing));
+        assert_eq!(
+            errors(&config),
+            vec![
+                format!("wallet.pool[1].keypair_path: {} listed twice", first),
+                format!("wallet.pool[2].keypair_path: {} listed twice", main),
+                format!("wallet.pool[3].keypair_path: {} does not exist", missing),
+            ]
+        );
+
+        config.wallet.pool.truncate(1);
+        config.wallet.pool[0].targets = vec!["not-a-pubkey".to_string()];
+        assert_eq!(
+            errors(&config),
+            vec!["wallet.pool[0].targets: invalid pubkey not-a-pubkey".to_string()]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn allocations_parse_from_their_names() {
+        assert_eq!(
+            "per-target".parse::<Allocation>().unwrap(),
+            Allocation::PerTarget
+        );
+        assert_eq!(
+            "round-robin".parse::<Allocation>().unwrap(),
+            Allocation::RoundRobin
+        );
+        assert!("roundrobin".parse::<Allocation>().is_err());
+    }
+}
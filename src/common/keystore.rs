@@ -0,0 +1,426 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, IsTerminal, Write},
+    path::Path,
+};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use clap::ValueEnum;
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::{
+    bs58,
+    signature::{Keypair, Signer},
+};
+use thiserror::Error;
+
+const KEYSTORE_VERSION: u32 = 1;
+// OWASP's argon2id baseline: 19 MiB, 2 passes, 1 lane
+const ARGON2_M_COST: u32 = 19_456;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("Keystore: cannot access {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Keystore: {0} already exists, pass --force to overwrite it")]
+    Exists(String),
+    #[error("Keystore: {0} is empty")]
+    Empty(String),
+    #[error("Keystore: {path} is not a JSON keypair, a base58 key or a keystore: {reason}")]
+    Malformed { path: String, reason: String },
+    #[error("Keystore: unsupported keystore version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Keystore: wrong passphrase or corrupted keystore {0}")]
+    Decrypt(String),
+    #[error("Keystore: {0} holds the key of another public key")]
+    PubkeyMismatch(String),
+    #[error("Keystore: no passphrase, set KEYSTORE_PASSPHRASE or run from a terminal")]
+    NoPassphrase,
+    #[error("Keystore: passphrases do not match")]
+    PassphraseMismatch,
+    #[error("Keystore: key derivation failed: {0}")]
+    Kdf(String),
+}
+
+type Result<T> = std::result::Result<T, KeystoreError>;
+
+/// How a private key is stored on disk
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// Passphrase-encrypted keystore (argon2id + ChaCha20-Poly1305)
+    Keystore,
+    /// Solana CLI keypair file, a JSON array of the 64 key bytes
+    Json,
+    /// Base58 encoded key bytes, as exported by wallets
+    Base58,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub name: String,
+    /// Base64
+    pub salt: String,
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+/// Encrypted key file. The public key is stored in the clear and authenticated with the
+/// ciphertext, so the file can be identified without the passphrase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub pubkey: String,
+    pub kdf: KdfParams,
+    pub cipher: String,
+    /// Base64
+    pub nonce: String,
+    /// Base64
+    pub ciphertext: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], kdf: &KdfParams) -> Result<[u8; 32]> {
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32))
+        .map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+    Ok(key)
+}
+
+impl Keystore {
+    pub fn encrypt(keypair: &Keypair, passphrase: &str) -> Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        thread_rng().fill_bytes(&mut salt);
+        thread_rng().fill_bytes(&mut nonce);
+        let kdf = KdfParams {
+            name: "argon2id".to_string(),
+            salt: base64::encode(salt),
+            m_cost: ARGON2_M_COST,
+            t_cost: ARGON2_T_COST,
+            p_cost: ARGON2_P_COST,
+        };
+        let key = derive_key(passphrase, &salt, &kdf)?;
+        let pubkey = keypair.pubkey().to_string();
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &keypair.to_bytes(),
+                    aad: pubkey.as_bytes(),
+                },
+            )
+            .map_err(|_| KeystoreError::Kdf("encryption failed".to_string()))?;
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            pubkey,
+            kdf,
+            cipher: "chacha20poly1305".to_string(),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        })
+    }
+
+    /// `path` only names the keystore in errors.
+    pub fn decrypt(&self, passphrase: &str, path: &str) -> Result<Keypair> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(self.version));
+        }
+        let malformed = |reason: &str| KeystoreError::Malformed {
+            path: path.to_string(),
+            reason: reason.to_string(),
+        };
+        if self.kdf.name != "argon2id" || self.cipher != "chacha20poly1305" {
+            return Err(malformed("unknown kdf or cipher"));
+        }
+        let salt = base64::decode(&self.kdf.salt).map_err(|_| malformed("bad salt"))?;
+        let nonce = base64::decode(&self.nonce).map_err(|_| malformed("bad nonce"))?;
+        let ciphertext =
+            base64::decode(&self.ciphertext).map_err(|_| malformed("bad ciphertext"))?;
+        if nonce.len() != NONCE_LEN {
+            return Err(malformed("bad nonce"));
+        }
+        let key = derive_key(passphrase, &salt, &self.kdf)?;
+        let bytes = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: self.pubkey.as_bytes(),
+                },
+            )
+            .map_err(|_| KeystoreError::Decrypt(path.to_string()))?;
+        let keypair = Keypair::from_bytes(&bytes).map_err(|e| malformed(&e.to_string()))?;
+        if keypair.pubkey().to_string() != self.pubkey {
+            return Err(KeystoreError::PubkeyMismatch(path.to_string()));
+        }
+        Ok(keypair)
+    }
+}
+
+/// Passphrase from `KEYSTORE_PASSPHRASE`, or asked on the terminal. A new passphrase is asked
+/// twice.
+pub fn passphrase(prompt: &str, confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("KEYSTORE_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(KeystoreError::NoPassphrase);
+    }
+    let io_error = |source| KeystoreError::Io {
+        path: "terminal".to_string(),
+        source,
+    };
+    let passphrase = rpassword::prompt_password(prompt).map_err(io_error)?;
+    if confirm && rpassword::prompt_password("Repeat passphrase: ").map_err(io_error)? != passphrase
+    {
+        return Err(KeystoreError::PassphraseMismatch);
+    }
+    Ok(passphrase)
+}
+
+/// Read a keypair in any of the supported formats, asking for the passphrase of a keystore.
+pub fn load_keypair(path: &str) -> Result<Keypair> {
+    let contents = fs::read_to_string(path).map_err(|source| KeystoreError::Io {
+        path: path.to_string(),
+        source,
+    })?;
+    let contents = contents.trim();
+    if contents.is_empty() {
+        return Err(KeystoreError::Empty(path.to_string()));
+    }
+    let malformed = |reason: String| KeystoreError::Malformed {
+        path: path.to_string(),
+        reason,
+    };
+    match serde_json::from_str::<Value>(contents) {
+        Ok(Value::Array(_)) => {
+            let bytes: Vec<u8> =
+                serde_json::from_str(contents).map_err(|e| malformed(e.to_string()))?;
+            Keypair::from_bytes(&bytes).map_err(|e| malformed(e.to_string()))
+        }
+        Ok(Value::Object(_)) => {
+            let keystore: Keystore =
+                serde_json::from_str(contents).map_err(|e| malformed(e.to_string()))?;
+            let passphrase = passphrase(
+                &format!("Passphrase for {} ({}): ", path, keystore.pubkey),
+                false,
+            )?;
+            keystore.decrypt(&passphrase, path)
+        }
+        _ => {
+            let bytes = bs58::decode(contents)
+                .into_vec()
+                .map_err(|e| malformed(e.to_string()))?;
+            Keypair::from_bytes(&bytes).map_err(|e| malformed(e.to_string()))
+        }
+    }
+}
+
+/// Write the keypair to `path` in `format`, readable by the owner only. An existing file is
+/// only replaced with `overwrite`, and only once the new one is safely on disk: the key is
+/// written to a temporary file next to it and moved over it.
+pub fn save_keypair(
+    path: &str,
+    keypair: &Keypair,
+    format: KeyFormat,
+    overwrite: bool,
+) -> Result<()> {
+    if !overwrite && Path::new(path).exists() {
+        return Err(KeystoreError::Exists(path.to_string()));
+    }
+    let contents = match format {
+        KeyFormat::Keystore => {
+            let passphrase = passphrase("New keystore passphrase: ", true)?;
+            let keystore = Keystore::encrypt(keypair, &passphrase)?;
+            serde_json::to_string_pretty(&keystore).unwrap_or_default()
+        }
+        KeyFormat::Json => serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap_or_default(),
+        KeyFormat::Base58 => keypair.to_base58_string(),
+    };
+    let io_error = |source| KeystoreError::Io {
+        path: path.to_string(),
+        source,
+    };
+    if let Some(dir) = Path::new(path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    let temp = format!(
+        "{}.tmp-{}-{:x}",
+        path,
+        std::process::id(),
+        thread_rng().next_u64()
+    );
+    let written = write_new(&temp, contents.as_bytes()).and_then(|()| {
+        if overwrite {
+            fs::rename(&temp, path)
+        } else {
+            // linking fails if the file appeared meanwhile, where a rename would replace it
+            fs::hard_link(&temp, path).and_then(|()| fs::remove_file(&temp))
+        }
+    });
+    if let Err(source) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(match source.kind() {
+            io::ErrorKind::AlreadyExists => KeystoreError::Exists(path.to_string()),
+            _ => io_error(source),
+        });
+    }
+    // the rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    {
+        let dir = Path::new(path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        fs::File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(io_error)?;
+    }
+    Ok(())
+}
+
+// created with the final permissions, the key is never readable by others
+fn write_new(path: &str, contents: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("keystore-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn existing_files_are_only_replaced_with_overwrite() {
+        let path = temp_path("overwrite.json");
+        let _ = fs::remove_file(&path);
+        let first = Keypair::new();
+        save_keypair(&path, &first, KeyFormat::Json, false).unwrap();
+        let second = Keypair::new();
+        assert!(matches!(
+            save_keypair(&path, &second, KeyFormat::Json, false),
+            Err(KeystoreError::Exists(_))
+        ));
+        assert_eq!(load_keypair(&path).unwrap().pubkey(), first.pubkey());
+        save_keypair(&path, &second, KeyFormat::Json, true).unwrap();
+        assert_eq!(load_keypair(&path).unwrap().pubkey(), second.pubkey());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn overwriting_leaves_no_temporary_file_behind() {
+        let dir = std::env::temp_dir().join(format!("keystore-temp-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("key.json").to_string_lossy().to_string();
+        save_keypair(&path, &Keypair::new(), KeyFormat::Json, false).unwrap();
+        save_keypair(&path, &Keypair::new(), KeyFormat::Json, true).unwrap();
+        assert!(save_keypair(&path, &Keypair::new(), KeyFormat::Json, false).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keystores_decrypt_with_their_passphrase_only() {
+        let keypair = Keypair::new();
+        let keystore = Keystore::encrypt(&keypair, "correct horse").unwrap();
+        assert_eq!(keystore.pubkey, keypair.pubkey().to_string());
+        let decrypted = keystore.decrypt("correct horse", "key.json").unwrap();
+        assert_eq!(decrypted.to_bytes(), keypair.to_bytes());
+        assert!(matches!(
+            keystore.decrypt("battery staple", "key.json"),
+            Err(KeystoreError::Decrypt(_))
+        ));
+    }
+
+    #[test]
+    fn tampered_keystores_are_rejected() {
+        let mut keystore = Keystore::encrypt(&Keypair::new(), "passphrase").unwrap();
+        // the public key is authenticated with the ciphertext
+        keystore.pubkey = Keypair::new().pubkey().to_string();
+        assert!(matches!(
+            keystore.decrypt("passphrase", "key.json"),
+            Err(KeystoreError::Decrypt(_))
+        ));
+        keystore.version = 2;
+        assert!(matches!(
+            keystore.decrypt("passphrase", "key.json"),
+            Err(KeystoreError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn json_and_base58_keys_are_read() {
+        let keypair = Keypair::new();
+        let json = temp_path("parse.json");
+        fs::write(
+            &json,
+            serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(load_keypair(&json).unwrap().pubkey(), keypair.pubkey());
+        let base58 = temp_path("parse.txt");
+        fs::write(&base58, format!("{}\n", keypair.to_base58_string())).unwrap();
+        assert_eq!(load_keypair(&base58).unwrap().pubkey(), keypair.pubkey());
+        fs::remove_file(&json).unwrap();
+        fs::remove_file(&base58).unwrap();
+    }
+
+    #[test]
+    fn malformed_key_files_are_reported() {
+        let path = temp_path("malformed.json");
+        for contents in ["[1, 2, 3]", "not-base58-0OIl", "\"string\""] {
+            fs::write(&path, contents).unwrap();
+            assert!(matches!(
+                load_keypair(&path),
+                Err(KeystoreError::Malformed { .. })
+            ));
+        }
+        fs::write(&path, "  \n").unwrap();
+        assert!(matches!(load_keypair(&path), Err(KeystoreError::Empty(_))));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_files_are_readable_by_the_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_path("mode.json");
+        let _ = fs::remove_file(&path);
+        save_keypair(&path, &Keypair::new(), KeyFormat::Base58, false).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1 +1,4 @@
+pub mod config;
+pub mod keystore;
+pub mod telemetry;
 pub mod utils;
@@ -0,0 +1,234 @@
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use tracing::{span, Subscriber};
+use tracing_subscriber::{
+    fmt::format::FmtSpan,
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use crate::common::utils::env_or;
+
+/// Stages of a copy, each timed by the span of the same name:
+/// - `receive`: stream message decoded and matched to a target
+/// - `parse`: target's trade decoded from the transaction
+/// - `size`: balances read to size the copy
+/// - `risk`: screening and risk limits
+/// - `build`: quote and swap instructions
+/// - `sign`: simulation, compute budget, blockhash and signature
+/// - `send`: bundle sent until it is confirmed
+/// - `land`: from the target's trade being received to the copy landing
+pub const STAGES: [&str; 8] = [
+    "receive", "parse", "size", "risk", "build", "sign", "send", "land",
+];
+
+/// Upper bounds of the latency buckets, in milliseconds
+pub const BUCKETS_MS: [u64; 14] = [
+    1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000,
+];
+
+pub static TRACE_CONFIG: LazyLock<TraceConfig> = LazyLock::new(TraceConfig::from_env);
+static LATENCY: LazyLock<Mutex<BTreeMap<&'static str, Histogram>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Human-readable lines
+    Pretty,
+    /// One JSON object per line, with the fields of the enclosing spans
+    Json,
+}
+
+impl FromStr for TraceFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pretty" => Ok(TraceFormat::Pretty),
+            "json" => Ok(TraceFormat::Json),
+            _ => Err(anyhow!("unknown log format {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceConfig {
+    pub format: TraceFormat,
+    /// `EnvFilter` directives, e.g. `info` or `temp=debug`
+    pub filter: String,
+    /// Log every stage span as it closes, with its duration
+    pub span_events: bool,
+    /// How often the stage latencies are logged, never when zero
+    pub latency_every: Duration,
+}
+
+impl TraceConfig {
+    pub fn from_env() -> Self {
+        Self {
+            format: env_or("LOG_FORMAT", TraceFormat::Pretty),
+            filter: env_or("LOG_FILTER", "info".to_string()),
+            span_events: env_or("LOG_SPANS", false),
+            latency_every: Duration::from_secs(env_or("LOG_LATENCY_SECS", 300)),
+        }
+    }
+}
+
+/// Latency distribution of a stage
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    /// Observations at or under each of `BUCKETS_MS`, not cumulative
+    pub buckets: [u64; BUCKETS_MS.len()],
+    /// Observations over the last bucket
+    pub overflow: u64,
+    pub count: u64,
+    pub sum: Duration,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        match BUCKETS_MS.iter().position(|bound| ms <= *bound) {
+            Some(i) => self.buckets[i] += 1,
+            None => self.overflow += 1,
+        }
+        self.count += 1;
+        self.sum += elapsed;
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile, in milliseconds.
+    pub fn quantile_ms(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (self.count as f64 * q).ceil() as u64;
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(BUCKETS_MS[i]);
+            }
+        }
+        // beyond the last bucket
+        None
+    }
+}
+
+/// Record the latency of a stage that has no span of its own.
+pub fn observe(stage: &'static str, elapsed: Duration) {
+    if let Ok(mut latency) = LATENCY.lock() {
+        latency.entry(stage).or_default().observe(elapsed);
+    }
+}
+
+/// Latency histograms of the stages observed so far, in pipeline order.
+pub fn latency() -> Vec<(&'static str, Histogram)> {
+    let Ok(latency) = LATENCY.lock() else {
+        return vec![];
+    };
+    STAGES
+        .iter()
+        .filter_map(|stage| latency.get(stage).map(|h| (*stage, h.clone())))
+        .collect()
+}
+
+// when a stage span was opened
+struct Opened(Instant);
+
+/// Times the stage spans from creation to close, so a span that is never entered still measures
+/// the wall time of its stage.
+struct LatencyLayer;
+
+impl<S> Layer<S> for LatencyLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if STAGES.contains(&span.name()) {
+            span.extensions_mut().insert(Opened(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let opened = span.extensions().get::<Opened>().map(|opened| opened.0);
+        if let Some(opened) = opened {
+            observe(span.name(), opened.elapsed());
+        }
+    }
+}
+
+/// Install the global subscriber: `LOG_FORMAT` output filtered by `LOG_FILTER`, and the stage
+/// latency histograms.
+pub fn init() -> Result<()> {
+    let config = &*TRACE_CONFIG;
+    let filter = EnvFilter::try_new(&config.filter)
+        .map_err(|e| anyhow!("Telemetry: bad LOG_FILTER {}: {}", config.filter, e))?;
+    let span_events = if config.span_events {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+    let output = match config.format {
+        TraceFormat::Pretty => tracing_subscriber::fmt::layer()
+            .with_span_events(span_events)
+            .boxed(),
+        TraceFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_span_events(span_events)
+            .boxed(),
+    };
+    // the filter is on the output only, the histograms see every stage
+    tracing_subscriber::registry()
+        .with(output.with_filter(filter))
+        .with(LatencyLayer)
+        .try_init()
+        .map_err(|e| anyhow!("Telemetry: {}", e))
+}
+
+/// Log the latency quantiles of every stage.
+pub fn log_latency() {
+    for (stage, histogram) in latency() {
+        let mean = histogram.sum.as_millis() as u64 / histogram.count.max(1);
+        tracing::info!(
+            stage,
+            count = histogram.count,
+            mean_ms = mean,
+            p50_ms = histogram.quantile_ms(0.5),
+            p90_ms = histogram.quantile_ms(0.9),
+            p99_ms = histogram.quantile_ms(0.99),
+            "latency"
+        );
+    }
+}
+
+/// Log the stage latencies every `LOG_LATENCY_SECS`.
+pub fn spawn_latency_log() {
+    let every = TRACE_CONFIG.latency_every;
+    if every.is_zero() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(every);
+        // the first tick is immediate
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            log_latency();
+        }
+    });
+}
@@ -1,9 +1,14 @@
 use anyhow::Result;
-use chrono::Local;
 use solana_sdk::{commitment_config::CommitmentConfig, signature::Keypair};
 use std::process;
+use std::str::FromStr;
 use std::{env, sync::Arc};
 
+use crate::{
+    common::{config::config, keystore::load_keypair},
+    services::trade_log::{self, TRADE_LOG_CONFIG},
+};
+
 #[derive(Clone)]
 pub struct AppState {
     pub rpc_client: Arc<solana_client::rpc_client::RpcClient>,
@@ -19,44 +24,57 @@ pub struct ParseTx {
     pub mint: String,
 }
 
-use std::fs::{File, OpenOptions};
-use std::io::{self, Read, Write};
-use std::time::SystemTime;
-
-pub async fn log_message(message: &str) -> io::Result<()> {
-    // Open the file in append mode. If the file doesn't exist, it will be created.
-    let mut file = OpenOptions::new()
-        .append(true) // Ensure the log is appended, not overwritten
-        .create(true) // Create the file if it doesn't exist
-        .open("./src/log.txt")?;
-
-    // Get the current timestamp
-    let now = Local::now();
-
-    // Format the time as "HH:MM:SS"
-    now.format("%H:%M:%S").to_string();
+use std::fs;
+use std::io;
 
-    // Write the log message with a timestamp
-    writeln!(file, "[{:#?}] {}", now.clone(), message)?;
-
-    Ok(())
+/// Add a message to the trade log, see `services::trade_log`. Queued, never blocks.
+pub fn log_message(message: &str) {
+    trade_log::note(message);
 }
 
+/// Contents of the active trade log file.
 pub fn read_log() -> io::Result<String> {
-    // Read the entire content of the log file
-    let mut file = File::open("log.txt")?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-
-    Ok(contents)
+    fs::read_to_string(TRADE_LOG_CONFIG.active_path())
 }
 
 pub fn import_env_var(key: &str) -> String {
     env::var(key).unwrap_or_else(|_| panic!("Environment variable {} is not set", key))
 }
 
+/// Optional environment variable, falling back to `default` when unset or unparsable.
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| T::from_str(&v).ok())
+        .unwrap_or(default)
+}
+
+/// Like `env_or`, but a value that does not parse is reported in `errors` instead of being
+/// ignored, so a typo does not leave a setting at its default unnoticed.
+pub fn env_checked<T: FromStr>(key: &str, default: T, errors: &mut Vec<String>) -> T {
+    match env::var(key) {
+        Ok(v) => T::from_str(&v).unwrap_or_else(|_| {
+            errors.push(format!("{}: cannot parse {:?}", key, v));
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Comma separated environment variable, empty when unset.
+pub fn env_list(key: &str) -> Vec<String> {
+    env::var(key)
+        .map(|v| {
+            v.split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 pub fn create_rpc_client() -> Result<solana_client::rpc_client::RpcClient> {
-    let rpc_https = import_env_var("RPC_ENDPOINT");
+    let rpc_https = config().endpoints.rpc.clone();
     let rpc_client = solana_client::rpc_client::RpcClient::new_with_commitment(
         rpc_https,
         CommitmentConfig::processed(),
@@ -64,7 +82,7 @@ pub fn create_rpc_client() -> Result<solana_client::rpc_client::RpcClient> {
     Ok(rpc_client)
 }
 pub fn create_arc_rpc_client() -> Result<Arc<solana_client::rpc_client::RpcClient>> {
-    let rpc_https = import_env_var("RPC_ENDPOINT");
+    let rpc_https = config().endpoints.rpc.clone();
     let rpc_client = solana_client::rpc_client::RpcClient::new_with_commitment(
         rpc_https,
         CommitmentConfig::processed(),
@@ -74,7 +92,7 @@ pub fn create_arc_rpc_client() -> Result<Arc<solana_client::rpc_client::RpcClien
 
 pub async fn create_nonblocking_rpc_client(
 ) -> Result<Arc<solana_client::nonblocking::rpc_client::RpcClient>> {
-    let rpc_https = import_env_var("RPC_ENDPOINT");
+    let rpc_https = config().endpoints.rpc.clone();
     let rpc_client = solana_client::nonblocking::rpc_client::RpcClient::new_with_commitment(
         rpc_https,
         CommitmentConfig::processed(),
@@ -82,22 +100,10 @@ pub async fn create_nonblocking_rpc_client(
     Ok(Arc::new(rpc_client))
 }
 
+/// Trading wallet from `wallet.keypair_path`: a JSON keypair, a base58 key or a keystore.
 pub fn import_wallet() -> Result<Keypair> {
-    let mut file = File::open("./key.txt")?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    if contents == "" {
-        println!("Not set Private Key");
-    }
-    let wallet: Keypair = Keypair::from_base58_string(&contents);
-
-    Ok(wallet)
+    Ok(load_keypair(&config().wallet.keypair_path)?)
 }
 pub fn import_arc_wallet() -> Result<Arc<Keypair>> {
-    let mut file = File::open("./key.txt")?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let wallet: Keypair = Keypair::from_base58_string(contents.as_str());
-
-    Ok(Arc::new(wallet))
+    Ok(Arc::new(import_wallet()?))
 }
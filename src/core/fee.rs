@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction, pubkey::Pubkey,
+    signature::Keypair,
+};
+use tracing::warn;
+
+use crate::{
+    common::utils::env_checked,
+    core::sim::{self, SIMULATION_UNIT_LIMIT},
+};
+
+// getRecentPrioritizationFees accepts at most 128 accounts
+const MAX_FEE_ACCOUNTS: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeConfig {
+    /// Percentile of recent prioritization fees to pay (0-100)
+    pub percentile: u8,
+    /// Lower bound of the unit price in micro-lamports
+    pub min_unit_price: u64,
+    /// Upper bound of the unit price in micro-lamports
+    pub max_unit_price: u64,
+    /// Fixed compute unit limit, used when simulation is disabled or fails
+    pub unit_limit: u32,
+    /// Derive the compute unit limit from a simulation of the transaction
+    pub simulate_unit_limit: bool,
+    /// Extra compute units added on top of the simulated consumption, in percent
+    pub unit_limit_margin: u32,
+}
+
+impl Default for FeeConfig {
+    fn default() -> Self {
+        Self {
+            percentile: 75,
+            min_unit_price: 1,
+            max_unit_price: 5_000_000,
+            unit_limit: 300_000,
+            simulate_unit_limit: false,
+            unit_limit_margin: 20,
+        }
+    }
+}
+
+impl FeeConfig {
+    pub fn from_env() -> Self {
+        Self::default().env_overrides(&mut vec![])
+    }
+
+    /// Replace the settings given in the environment.
+    pub fn env_overrides(self, errors: &mut Vec<String>) -> Self {
+        Self {
+            percentile: env_checked("FEE_PERCENTILE", self.percentile, errors),
+            min_unit_price: env_checked("UNIT_PRICE", self.min_unit_price, errors),
+            max_unit_price: env_checked("MAX_UNIT_PRICE", self.max_unit_price, errors),
+            unit_limit: env_checked("UNIT_LIMIT", self.unit_limit, errors),
+            simulate_unit_limit: env_checked(
+                "SIMULATE_UNIT_LIMIT",
+                self.simulate_unit_limit,
+                errors,
+            ),
+            unit_limit_margin: env_checked("UNIT_LIMIT_MARGIN", self.unit_limit_margin, errors),
+        }
+    }
+}
+
+/// Writable, non-signer accounts of the instructions (bonding curve, AMM pool, vaults, ...),
+/// which are the accounts whose local fee market decides our priority.
+pub fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut seen = HashSet::new();
+    instructions
+        .iter()
+        .flat_map(|ix| ix.accounts.iter())
+        .filter(|meta| meta.is_writable && !meta.is_signer)
+        .map(|meta| meta.pubkey)
+        .filter(|pubkey| seen.insert(*pubkey))
+        .take(MAX_FEE_ACCOUNTS)
+        .collect()
+}
+
+/// Value at `pct` percent of the sorted fees (nearest-rank).
+pub fn percentile(fees: &mut [u64], pct: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+    let rank = (fees.len() * pct.min(100) as usize).div_ceil(100);
+    fees[rank.saturating_sub(1)]
+}
+
+// prioritization fee = unit price * unit limit
+pub fn estimate_unit_price(client: &RpcClient, accounts: &[Pubkey], config: &FeeConfig) -> u64 {
+    let mut fees: Vec<u64> = match client.get_recent_prioritization_fees(accounts) {
+        Ok(fees) => fees.into_iter().map(|f| f.prioritization_fee).collect(),
+        Err(e) => {
+            warn!(error = %e, "recent prioritization fees unavailable");
+            return config.min_unit_price;
+        }
+    };
+    percentile(&mut fees, config.percentile).clamp(config.min_unit_price, config.max_unit_price)
+}
+
+pub fn simulate_unit_limit(
+    client: &RpcClient,
+    keypair: &Keypair,
+    instructions: &[Instruction],
+    config: &FeeConfig,
+) -> Result<u32> {
+    let consumed = sim::simulate(client, keypair, instructions, None)?
+        .units_consumed
+        .ok_or(anyhow!("simulation returned no units consumed"))?;
+    Ok(unit_limit_with_margin(consumed, config.unit_limit_margin))
+}
+
+pub fn unit_limit_with_margin(consumed: u64, margin: u32) -> u32 {
+    let limit = consumed.saturating_mul(100 + margin as u64) / 100;
+    limit.min(SIMULATION_UNIT_LIMIT as u64) as u32
+}
+
+/// Compute budget instructions for the given trade instructions, priced off the local fee
+/// market of the accounts they write to. `simulated_units` comes from a simulation that
+/// already ran (e.g. the pre-trade guard) and saves a second round trip.
+pub fn compute_budget_instructions(
+    client: &RpcClient,
+    keypair: &Keypair,
+    instructions: &[Instruction],
+    simulated_units: Option<u64>,
+    config: &FeeConfig,
+) -> Vec<Instruction> {
+    let unit_price = estimate_unit_price(client, &writable_accounts(instructions), config);
+    let unit_limit = if let Some(consumed) = simulated_units {
+        unit_limit_with_margin(consumed, config.unit_limit_margin)
+    } else if config.simulate_unit_limit {
+        simulate_unit_limit(client, keypair, instructions, config)
+            .inspect_err(|e| warn!(error = %e, "unit limit simulation failed"))
+            .unwrap_or(config.unit_limit)
+    } else {
+        config.unit_limit
+    };
+    vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(unit_limit),
+        ComputeBudgetInstruction::set_compute_unit_price(unit_price),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_of_no_fees_is_zero() {
+        assert_eq!(percentile(&mut [], 75), 0);
+    }
+
+    #[test]
+    fn percentile_is_nearest_rank() {
+        let mut fees = [50, 10, 40, 20, 30];
+        assert_eq!(percentile(&mut fees, 0), 10);
+        assert_eq!(percentile(&mut fees, 20), 10);
+        assert_eq!(percentile(&mut fees, 21), 20);
+        assert_eq!(percentile(&mut fees, 50), 30);
+        assert_eq!(percentile(&mut fees, 100), 50);
+    }
+
+    #[test]
+    fn percentile_above_100_is_the_maximum() {
+        assert_eq!(percentile(&mut [3, 1, 2], 255), 3);
+    }
+
+    #[test]
+    fn percentile_of_one_fee() {
+        assert_eq!(percentile(&mut [7], 0), 7);
+        assert_eq!(percentile(&mut [7], 100), 7);
+    }
+
+    #[test]
+    fn unit_limit_adds_the_margin() {
+        assert_eq!(unit_limit_with_margin(100_000, 20), 120_000);
+        assert_eq!(unit_limit_with_margin(100_000, 0), 100_000);
+        assert_eq!(unit_limit_with_margin(0, 20), 0);
+    }
+
+    #[test]
+    fn unit_limit_is_capped() {
+        assert_eq!(unit_limit_with_margin(1_300_000, 20), SIMULATION_UNIT_LIMIT);
+        assert_eq!(
+            unit_limit_with_margin(u64::MAX, u32::MAX),
+            SIMULATION_UNIT_LIMIT
+        );
+    }
+
+    #[test]
+    fn writable_accounts_are_deduplicated_without_signers() {
+        use solana_sdk::instruction::AccountMeta;
+
+        let (signer, pool, vault, program) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let ix = |accounts| Instruction {
+            program_id: program,
+            accounts,
+            data: vec![],
+        };
+        let instructions = [
+            ix(vec![
+                AccountMeta::new(signer, true),
+                AccountMeta::new(pool, false),
+                AccountMeta::new_readonly(vault, false),
+            ]),
+            ix(vec![
+                AccountMeta::new(vault, false),
+                AccountMeta::new(pool, false),
+            ]),
+        ];
+        assert_eq!(writable_accounts(&instructions), vec![pool, vault]);
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::HashSet,
+    env,
+    str::FromStr,
+    sync::{Arc, LazyLock},
+};
+
+use anyhow::{anyhow, Result};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    address_lookup_table::{
+        instruction::{
+            close_lookup_table, create_lookup_table, deactivate_lookup_table, extend_lookup_table,
+        },
+        state::{AddressLookupTable, LOOKUP_TABLE_MAX_ADDRESSES},
+        AddressLookupTableAccount,
+    },
+    commitment_config::CommitmentConfig,
+    compute_budget,
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, VersionedMessage},
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    system_program,
+    transaction::{Transaction, VersionedTransaction},
+};
+use spl_associated_token_account::get_associated_token_address;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::{
+    common::utils::AppState,
+    core::token,
+    dex::{
+        pump::{
+            ASSOCIATED_TOKEN_PROGRAM, PUMP_ACCOUNT, PUMP_FEE_RECIPIENT, PUMP_GLOBAL, PUMP_PROGRAM,
+            RENT_PROGRAM, TOKEN_2022_PROGRAM, TOKEN_PROGRAM,
+        },
+        raydium::{AMM_PROGRAM, RAYDIUM_AUTHORITY_V4},
+    },
+    services::jito::TIP_ACCOUNTS,
+};
+
+// addresses per extend instruction, keeps each extend transaction under the size limit
+const EXTEND_CHUNK: usize = 20;
+
+/// Address lookup table used to compile our copy transactions, from `LOOKUP_TABLE`
+pub static LOOKUP_TABLE: LazyLock<Option<Pubkey>> = LazyLock::new(|| {
+    env::var("LOOKUP_TABLE")
+        .ok()
+        .and_then(|v| Pubkey::from_str(&v).ok())
+});
+
+// fetched once and refreshed after every extension
+static LOOKUP_TABLE_ACCOUNT: LazyLock<RwLock<Option<CachedTable>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+/// Lookup table as last fetched
+#[derive(Debug, Clone)]
+struct CachedTable {
+    account: AddressLookupTableAccount,
+    /// Slot of the last extension and the index its addresses start at. They can only be
+    /// looked up from the next slot on.
+    pending: Option<(u64, usize)>,
+}
+
+impl CachedTable {
+    /// The addresses transactions can look up in `slot`.
+    fn usable(&self, slot: u64) -> AddressLookupTableAccount {
+        let mut account = self.account.clone();
+        if let Some((extended_slot, start)) = self.pending {
+            if slot <= extended_slot {
+                account.addresses.truncate(start);
+            }
+        }
+        account
+    }
+}
+
+/// Static accounts shared by our trades: pump and Raydium programs and globals, token programs,
+/// Jito tip accounts and the wallet's wSOL ATA, plus any extra ATAs of open positions.
+pub async fn static_accounts(wallet: &Pubkey, extra: &[Pubkey]) -> Vec<Pubkey> {
+    let mut accounts: Vec<Pubkey> = [
+        PUMP_PROGRAM,
+        PUMP_GLOBAL,
+        PUMP_FEE_RECIPIENT,
+        PUMP_ACCOUNT,
+        TOKEN_PROGRAM,
+        TOKEN_2022_PROGRAM,
+        ASSOCIATED_TOKEN_PROGRAM,
+        RENT_PROGRAM,
+        AMM_PROGRAM,
+        RAYDIUM_AUTHORITY_V4,
+    ]
+    .iter()
+    .filter_map(|key| Pubkey::from_str(key).ok())
+    .collect();
+    accounts.push(system_program::id());
+    accounts.push(compute_budget::id());
+    accounts.push(spl_token::native_mint::ID);
+    accounts.push(get_associated_token_address(
+        wallet,
+        &spl_token::native_mint::ID,
+    ));
+    accounts.extend(
+        TIP_ACCOUNTS
+            .read()
+            .await
+            .iter()
+            .filter_map(|key| Pubkey::from_str(key).ok()),
+    );
+    accounts.extend_from_slice(extra);
+    accounts
+}
+
+/// Token accounts the wallets hold the mints in, under each mint's token program.
+pub async fn position_accounts(
+    client: &Arc<solana_client::nonblocking::rpc_client::RpcClient>,
+    wallets: &[Pubkey],
+    mints: &[String],
+) -> Vec<Pubkey> {
+    let mut accounts = vec![];
+    for mint in mints {
+        let Ok(mint) = Pubkey::from_str(mint) else {
+            continue;
+        };
+        match token::get_token_mint_info(client.clone(), &mint).await {
+            Ok(info) => accounts.extend(
+                wallets
+                    .iter()
+                    .map(|wallet| info.associated_token_address(wallet)),
+            ),
+            Err(e) => {
+                warn!(mint = %mint, error = %e, "position accounts not added to the lookup table")
+            }
+        }
+    }
+    accounts
+}
+
+/// Add the token accounts of a position just opened to the table, in the background, so its
+/// later swaps compile against it too.
+pub fn extend_with_position(state: &AppState, wallets: Vec<Pubkey>, mint: String) {
+    let Some(table) = *LOOKUP_TABLE else {
+        return;
+    };
+    let state = state.clone();
+    tokio::spawn(async move {
+        let accounts = position_accounts(&state.rpc_nonblocking_client, &wallets, &[mint]).await;
+        if let Err(e) = extend(&state.rpc_client, &state.wallet, &table, &accounts).await {
+            warn!(%table, error = %e, "lookup table not extended");
+        }
+    });
+}
+
+fn send(client: &RpcClient, payer: &Keypair, instruction: Instruction) -> Result<()> {
+    let recent_blockhash = client.get_latest_blockhash()?;
+    let tx = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &[payer],
+        recent_blockhash,
+    );
+    client.send_and_confirm_transaction(&tx)?;
+    Ok(())
+}
+
+/// Create an empty lookup table with the payer as authority.
+pub fn create(client: &RpcClient, payer: &Keypair) -> Result<Pubkey> {
+    let recent_slot = client.get_slot_with_commitment(CommitmentConfig::finalized())?;
+    let (instruction, table) = create_lookup_table(payer.pubkey(), payer.pubkey(), recent_slot);
+    send(client, payer, instruction)?;
+    Ok(table)
+}
+
+/// Build a new table holding `addresses` to replace `old`, which is deactivated so its rent
+/// can be reclaimed with `close` once the deactivation cool-down is over. Lookup tables can't
+/// drop single addresses, this is how the accounts of closed positions are cleared out.
+/// Returns the new table.
+pub async fn rebuild(
+    client: &RpcClient,
+    payer: &Keypair,
+    old: Option<&Pubkey>,
+    addresses: &[Pubkey],
+) -> Result<Pubkey> {
+    let table = create(client, payer)?;
+    extend(client, payer, &table, addresses).await?;
+    if let Some(old) = old {
+        send(client, payer, deactivate_lookup_table(*old, payer.pubkey()))?;
+    }
+    Ok(table)
+}
+
+/// Close a deactivated table and send its rent back to the payer.
+pub fn close(client: &RpcClient, payer: &Keypair, table: &Pubkey) -> Result<()> {
+    send(
+        client,
+        payer,
+        close_lookup_table(*table, payer.pubkey(), payer.pubkey()),
+    )
+}
+
+pub fn fetch(client: &RpcClient, table: &Pubkey) -> Result<AddressLookupTableAccount> {
+    Ok(fetch_cached(client, table)?.account)
+}
+
+fn fetch_cached(client: &RpcClient, table: &Pubkey) -> Result<CachedTable> {
+    let account = client.get_account(table)?;
+    let lookup_table = AddressLookupTable::deserialize(&account.data)?;
+    let start = lookup_table.meta.last_extended_slot_start_index as usize;
+    Ok(CachedTable {
+        account: AddressLookupTableAccount {
+            key: *table,
+            addresses: lookup_table.addresses.to_vec(),
+        },
+        pending: (start < lookup_table.addresses.len())
+            .then_some((lookup_table.meta.last_extended_slot, start)),
+    })
+}
+
+/// Addresses to add to a table holding `existing`, without duplicates, and how many of them
+/// did not fit under the table size limit. The first ones are kept.
+fn missing(existing: &[Pubkey], addresses: &[Pubkey]) -> (Vec<Pubkey>, usize) {
+    let existing: HashSet<&Pubkey> = existing.iter().collect();
+    let mut seen = HashSet::new();
+    let mut missing: Vec<Pubkey> = addresses
+        .iter()
+        .filter(|address| !existing.contains(address) && seen.insert(**address))
+        .copied()
+        .collect();
+    let room = LOOKUP_TABLE_MAX_ADDRESSES.saturating_sub(existing.len());
+    let left_out = missing.len().saturating_sub(room);
+    missing.truncate(room);
+    (missing, left_out)
+}
+
+/// Add the addresses the table does not hold yet, as many as fit in it. Returns how many were
+/// added; a full table is an error once the others are added.
+pub async fn extend(
+    client: &RpcClient,
+    payer: &Keypair,
+    table: &Pubkey,
+    addresses: &[Pubkey],
+) -> Result<usize> {
+    let (missing, left_out) = missing(&fetch(client, table)?.addresses, addresses);
+    for chunk in missing.chunks(EXTEND_CHUNK) {
+        let instruction =
+            extend_lookup_table(*table, payer.pubkey(), Some(payer.pubkey()), chunk.to_vec());
+        send(client, payer, instruction)?;
+    }
+    *LOOKUP_TABLE_ACCOUNT.write().await = Some(fetch_cached(client, table)?);
+    if left_out > 0 {
+        return Err(anyhow!(
+            "LookupTableFull: {} added, {} left out, rebuild it with `lut rebuild`",
+            missing.len(),
+            left_out
+        ));
+    }
+    Ok(missing.len())
+}
+
+/// Lookup tables to compile our transactions against, empty when `LOOKUP_TABLE` is not set.
+/// Addresses added in the current slot are left out until they can be looked up.
+pub async fn lookup_table_accounts(client: &RpcClient) -> Vec<AddressLookupTableAccount> {
+    let Some(table) = *LOOKUP_TABLE else {
+        return vec![];
+    };
+    let cached = LOOKUP_TABLE_ACCOUNT.read().await.clone();
+    let cached = match cached {
+        Some(cached) => cached,
+        None => match fetch_cached(client, &table) {
+            Ok(cached) => {
+                *LOOKUP_TABLE_ACCOUNT.write().await = Some(cached.clone());
+                cached
+            }
+            Err(e) => {
+                warn!(%table, error = %e, "lookup table not loaded");
+                return vec![];
+            }
+        },
+    };
+    let Some((extended_slot, _)) = cached.pending else {
+        return vec![cached.account];
+    };
+    // the slot is only asked for until the last extension is usable
+    let slot = client.get_slot().unwrap_or(extended_slot);
+    if slot > extended_slot {
+        if let Some(cached) = LOOKUP_TABLE_ACCOUNT.write().await.as_mut() {
+            cached.pending = None;
+        }
+    }
+    vec![cached.usable(slot)]
+}
+
+pub fn compile_v0(
+    keypair: &Keypair,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+    recent_blockhash: Hash,
+) -> Result<VersionedTransaction> {
+    let message = v0::Message::try_compile(
+        &keypair.pubkey(),
+        instructions,
+        lookup_tables,
+        recent_blockhash,
+    )?;
+    Ok(VersionedTransaction::try_new(
+        VersionedMessage::V0(message),
+        &[keypair],
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_missing_addresses_that_fit_are_added() {
+        let existing: Vec<Pubkey> = (0..250).map(|_| Pubkey::new_unique()).collect();
+        let new: Vec<Pubkey> = (0..10).map(|_| Pubkey::new_unique()).collect();
+        let mut addresses = vec![existing[0], new[0], new[0]];
+        addresses.extend(&new[1..]);
+        let (added, left_out) = missing(&existing, &addresses);
+        assert_eq!(added, new[..6].to_vec());
+        assert_eq!(left_out, 4);
+
+        let (added, left_out) = missing(&existing[..10], &addresses);
+        assert_eq!(added, new);
+        assert_eq!(left_out, 0);
+    }
+
+    #[test]
+    fn addresses_of_the_last_extension_are_usable_from_the_next_slot() {
+        let addresses: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let table = CachedTable {
+            account: AddressLookupTableAccount {
+                key: Pubkey::new_unique(),
+                addresses: addresses.clone(),
+            },
+            pending: Some((100, 3)),
+        };
+        assert_eq!(table.usable(100).addresses, addresses[..3].to_vec());
+        assert_eq!(table.usable(101).addresses, addresses);
+        let settled = CachedTable {
+            pending: None,
+            ..table
+        };
+        assert_eq!(settled.usable(0).addresses, addresses);
+    }
+}
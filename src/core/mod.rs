@@ -1,2 +1,6 @@
+pub mod fee;
+pub mod lut;
+pub mod nonce;
+pub mod sim;
 pub mod token;
 pub mod tx;
@@ -0,0 +1,99 @@
+use std::{collections::HashSet, str::FromStr, sync::LazyLock};
+
+use anyhow::{anyhow, Result};
+use solana_client::{nonce_utils, rpc_client::RpcClient};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    nonce::State,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_instruction,
+    transaction::Transaction,
+};
+use tokio::sync::Mutex;
+
+use crate::common::utils::env_list;
+
+/// Nonce accounts owned by our wallet, from `NONCE_ACCOUNTS` (comma separated)
+pub static NONCE_ACCOUNTS: LazyLock<Vec<Pubkey>> = LazyLock::new(|| {
+    env_list("NONCE_ACCOUNTS")
+        .iter()
+        .filter_map(|key| Pubkey::from_str(key).ok())
+        .collect()
+});
+
+// nonce accounts currently backing a pre-signed transaction
+static LEASED: LazyLock<Mutex<HashSet<Pubkey>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Create a rent-exempt nonce account whose authority is the payer.
+pub fn create_nonce_account(client: &RpcClient, payer: &Keypair) -> Result<Pubkey> {
+    let nonce_account = Keypair::new();
+    let lamports = client.get_minimum_balance_for_rent_exemption(State::size())?;
+    let instructions = system_instruction::create_nonce_account(
+        &payer.pubkey(),
+        &nonce_account.pubkey(),
+        &payer.pubkey(),
+        lamports,
+    );
+    let recent_blockhash = client.get_latest_blockhash()?;
+    let tx = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&payer.pubkey()),
+        &[payer, &nonce_account],
+        recent_blockhash,
+    );
+    client.send_and_confirm_transaction(&tx)?;
+    Ok(nonce_account.pubkey())
+}
+
+/// Current durable blockhash stored in the nonce account.
+pub fn fetch_nonce(client: &RpcClient, nonce_account: &Pubkey, authority: &Pubkey) -> Result<Hash> {
+    let account = nonce_utils::get_account_with_commitment(
+        client,
+        nonce_account,
+        CommitmentConfig::processed(),
+    )?;
+    let data = nonce_utils::data_from_account(&account)?;
+    if data.authority != *authority {
+        return Err(anyhow!(
+            "InvalidNonceAuthority: nonce account {} is owned by {}",
+            nonce_account,
+            data.authority
+        ));
+    }
+    Ok(data.blockhash())
+}
+
+/// Advance the nonce, invalidating every transaction signed against its current value.
+pub fn advance_nonce(
+    client: &RpcClient,
+    payer: &Keypair,
+    nonce_account: &Pubkey,
+) -> Result<Signature> {
+    let instruction = system_instruction::advance_nonce_account(nonce_account, &payer.pubkey());
+    let recent_blockhash = client.get_latest_blockhash()?;
+    let tx = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &[payer],
+        recent_blockhash,
+    );
+    Ok(client.send_and_confirm_transaction(&tx)?)
+}
+
+/// Take a nonce account that is not backing any pre-signed transaction.
+pub async fn lease() -> Option<Pubkey> {
+    let mut leased = LEASED.lock().await;
+    let nonce_account = NONCE_ACCOUNTS
+        .iter()
+        .find(|nonce_account| !leased.contains(*nonce_account))
+        .copied()?;
+    leased.insert(nonce_account);
+    Some(nonce_account)
+}
+
+pub async fn release(nonce_account: &Pubkey) {
+    LEASED.lock().await.remove(nonce_account);
+}
@@ -0,0 +1,410 @@
+use std::sync::LazyLock;
+
+use anyhow::{anyhow, Result};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig},
+};
+use solana_sdk::{
+    account::Account,
+    commitment_config::CommitmentConfig,
+    compute_budget::{self, ComputeBudgetInstruction},
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    transaction::{Transaction, TransactionError},
+};
+use spl_token_2022::{extension::StateWithExtensions, state::Account as TokenAccount};
+
+use crate::{
+    common::utils::{env_list, env_or},
+    engine::swap::SwapDirection,
+};
+
+// compute unit limit used while simulating, so the simulation itself is never capped
+pub const SIMULATION_UNIT_LIMIT: u32 = 1_400_000;
+// what the token programs log when a transfer can never succeed, as opposed to a sell that
+// failed on slippage, a stale blockhash or funds
+const FROZEN_LOGS: [&str; 1] = ["Account is frozen"];
+const NON_TRANSFERABLE_LOGS: [&str; 2] = ["NonTransferable", "Transfer is disabled for this mint"];
+const TRANSFER_HOOK_LOGS: [&str; 1] = ["TransferHook"];
+// the simulation charges the base signature fee to the payer, which would understate lamport outputs
+pub const SIGNATURE_FEE: u64 = 5_000;
+
+pub static SIMULATION_CONFIG: LazyLock<SimulationConfig> =
+    LazyLock::new(SimulationConfig::from_env);
+
+#[derive(Debug, Clone, Default)]
+pub struct SimulationConfig {
+    /// Simulate every copy trade before it is broadcast
+    pub enabled: bool,
+    /// Targets whose trades are sent blind, for latency-sensitive wallets
+    pub skip_targets: Vec<String>,
+}
+
+impl SimulationConfig {
+    pub fn from_env() -> Self {
+        Self {
+            enabled: env_or("SIMULATE", false),
+            skip_targets: env_list("SIMULATE_SKIP_TARGETS"),
+        }
+    }
+
+    pub fn enabled_for(&self, target: &str) -> bool {
+        self.enabled && !self.skip_targets.iter().any(|t| t == target)
+    }
+}
+
+/// Where the output of a trade lands
+#[derive(Debug, Clone, Copy)]
+pub enum OutputAccount {
+    /// SPL token account, balance read from the token amount
+    Token(Pubkey),
+    /// System account, balance read from the lamports
+    Lamports(Pubkey),
+}
+
+impl OutputAccount {
+    pub fn pubkey(&self) -> Pubkey {
+        match self {
+            OutputAccount::Token(pubkey) | OutputAccount::Lamports(pubkey) => *pubkey,
+        }
+    }
+
+    fn balance(&self, account: &Account) -> Result<u64> {
+        match self {
+            OutputAccount::Token(_) => {
+                Ok(StateWithExtensions::<TokenAccount>::unpack(&account.data)?
+                    .base
+                    .amount)
+            }
+            OutputAccount::Lamports(_) => Ok(account.lamports),
+        }
+    }
+}
+
+/// Current balance of the output account, zero if it does not exist yet (e.g. a fresh ATA).
+pub async fn output_balance(
+    client: &solana_client::nonblocking::rpc_client::RpcClient,
+    output: OutputAccount,
+) -> Result<u64> {
+    let account = client
+        .get_account_with_commitment(&output.pubkey(), CommitmentConfig::processed())
+        .await?
+        .value;
+    match account {
+        Some(account) => output.balance(&account),
+        None => Ok(0),
+    }
+}
+
+/// Expected outcome of a trade, checked against its simulation before broadcast
+#[derive(Debug, Clone)]
+pub struct TradeCheck {
+    pub direction: SwapDirection,
+    pub output: OutputAccount,
+    /// Balance of the output account before the trade
+    pub pre_balance: u64,
+    /// Minimum output the trade must produce
+    pub min_out: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationReport {
+    pub units_consumed: Option<u64>,
+    pub output: Option<u64>,
+    pub logs: Vec<String>,
+}
+
+pub fn simulate(
+    client: &RpcClient,
+    keypair: &Keypair,
+    instructions: &[Instruction],
+    check: Option<&TradeCheck>,
+) -> Result<SimulationReport> {
+    let mut ixs = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        SIMULATION_UNIT_LIMIT,
+    )];
+    ixs.extend(
+        instructions
+            .iter()
+            .filter(|ix| ix.program_id != compute_budget::id())
+            .cloned(),
+    );
+    let tx = Transaction::new_with_payer(&ixs, Some(&keypair.pubkey()));
+    let accounts = check.map(|check| RpcSimulateTransactionAccountsConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        addresses: vec![check.output.pubkey().to_string()],
+    });
+    let result = client
+        .simulate_transaction_with_config(
+            &tx,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(CommitmentConfig::processed()),
+                accounts,
+                ..Default::default()
+            },
+        )?
+        .value;
+    let logs = result.logs.unwrap_or_default();
+
+    if let Some(err) = result.err {
+        let honeypot = check
+            .filter(|check| check.direction == SwapDirection::Sell)
+            .and_then(|_| honeypot_reason(&err, &logs));
+        return Err(match honeypot {
+            Some(reason) => anyhow!("Honeypot: {}", reason),
+            None => anyhow!("SimulationFailed: {}", err),
+        });
+    }
+
+    let output = match check {
+        Some(check) => {
+            let account = result
+                .accounts
+                .and_then(|accounts| accounts.into_iter().next().flatten())
+                .and_then(|account| account.decode::<Account>())
+                .ok_or(anyhow!("SimulationFailed: output account not returned"))?;
+            let balance = match check.output {
+                OutputAccount::Token(_) => check.output.balance(&account)?,
+                OutputAccount::Lamports(_) => check.output.balance(&account)? + SIGNATURE_FEE,
+            };
+            Some(balance.saturating_sub(check.pre_balance))
+        }
+        None => None,
+    };
+
+    Ok(SimulationReport {
+        units_consumed: result.units_consumed,
+        output,
+        logs,
+    })
+}
+
+/// Simulate the trade and reject it if its output is below the expected minimum.
+pub fn guard(
+    client: &RpcClient,
+    keypair: &Keypair,
+    instructions: &[Instruction],
+    check: &TradeCheck,
+) -> Result<SimulationReport> {
+    let report = simulate(client, keypair, instructions, Some(check))?;
+    let output = report.output.unwrap_or_default();
+    if output < check.min_out {
+        return Err(anyhow!(
+            "SimulationRejected: output {} below minimum {}",
+            output,
+            check.min_out
+        ));
+    }
+    Ok(report)
+}
+
+/// Why a failed sell can never succeed, if it can't: a frozen token account, a
+/// non-transferable mint or a transfer hook rejecting it. Other failures (slippage, a stale
+/// blockhash, funds) are not a honeypot.
+fn honeypot_reason(err: &TransactionError, logs: &[String]) -> Option<String> {
+    let logged = |needles: &[&str]| {
+        logs.iter()
+            .any(|log| needles.iter().any(|needle| log.contains(needle)))
+    };
+    if !matches!(err, TransactionError::InstructionError(..)) {
+        return None;
+    }
+    if logged(&FROZEN_LOGS) {
+        return Some("token account is frozen".to_string());
+    }
+    if logged(&NON_TRANSFERABLE_LOGS) {
+        return Some("token is non-transferable".to_string());
+    }
+    if logged(&TRANSFER_HOOK_LOGS) {
+        return Some(format!("transfer hook rejected the sell: {}", err));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::instruction::InstructionError;
+
+    use super::*;
+
+    fn instruction_error() -> TransactionError {
+        TransactionError::InstructionError(2, InstructionError::Custom(17))
+    }
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn frozen_account_is_a_honeypot() {
+        let logs = logs(&["Program log: Error: Account is frozen"]);
+        assert_eq!(
+            honeypot_reason(&instruction_error(), &logs).as_deref(),
+            Some("token account is frozen")
+        );
+    }
+
+    #[test]
+    fn non_transferable_mint_is_a_honeypot() {
+        let logs = logs(&["Program log: Error: Transfer is disabled for this mint"]);
+        assert_eq!(
+            honeypot_reason(&instruction_error(), &logs).as_deref(),
+            Some("token is non-transferable")
+        );
+    }
+
+    #[test]
+    fn transfer_hook_rejection_is_a_honeypot() {
+        let logs = logs(&["Program log: TransferHook: sender is blacklisted"]);
+        assert!(honeypot_reason(&instruction_error(), &logs).is_some());
+    }
+
+    #[test]
+    fn slippage_is_not_a_honeypot() {
+        // raydium's custom 17 is not the token program's frozen account
+        let logs = logs(&["Program log: Error: exceeds desired slippage limit"]);
+        assert_eq!(honeypot_reason(&instruction_error(), &logs), None);
+    }
+
+    #[test]
+    fn transaction_errors_are_not_a_honeypot() {
+        for err in [
+            TransactionError::BlockhashNotFound,
+            TransactionError::InsufficientFundsForFee,
+            TransactionError::AccountNotFound,
+        ] {
+            assert_eq!(honeypot_reason(&err, &logs(&["Account is frozen"])), None);
+        }
+    }
+
+    // Against a local validator: `solana-test-validator`, then
+    // `SIM_TEST_RPC=http://127.0.0.1:8899 cargo test -- --ignored sim::tests`
+    fn validator() -> RpcClient {
+        let url = std::env::var("SIM_TEST_RPC").unwrap_or("http://127.0.0.1:8899".to_string());
+        RpcClient::new_with_commitment(url, CommitmentConfig::confirmed())
+    }
+
+    fn funded(client: &RpcClient) -> Keypair {
+        let keypair = Keypair::new();
+        let signature = client
+            .request_airdrop(&keypair.pubkey(), 1_000_000_000)
+            .expect("airdrop");
+        while !client.confirm_transaction(&signature).unwrap_or(false) {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+        }
+        keypair
+    }
+
+    fn send(client: &RpcClient, payer: &Keypair, signers: &[&Keypair], ixs: &[Instruction]) {
+        let blockhash = client.get_latest_blockhash().expect("blockhash");
+        let mut all = vec![payer];
+        all.extend_from_slice(signers);
+        let tx = Transaction::new_signed_with_payer(ixs, Some(&payer.pubkey()), &all, blockhash);
+        client
+            .send_and_confirm_transaction(&tx)
+            .expect("setup transaction");
+    }
+
+    #[test]
+    #[ignore = "needs solana-test-validator"]
+    fn validator_frozen_sell_is_a_honeypot() {
+        use solana_sdk::{program_pack::Pack, system_instruction};
+        use spl_associated_token_account::{
+            get_associated_token_address, instruction::create_associated_token_account,
+        };
+
+        let client = validator();
+        let payer = funded(&client);
+        let mint = Keypair::new();
+        let owner = payer.pubkey();
+        let rent = client
+            .get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN)
+            .expect("rent");
+        send(
+            &client,
+            &payer,
+            &[&mint],
+            &[
+                system_instruction::create_account(
+                    &owner,
+                    &mint.pubkey(),
+                    rent,
+                    spl_token::state::Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint2(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &owner,
+                    Some(&owner),
+                    6,
+                )
+                .unwrap(),
+            ],
+        );
+        let ata = get_associated_token_address(&owner, &mint.pubkey());
+        send(
+            &client,
+            &payer,
+            &[],
+            &[
+                create_associated_token_account(&owner, &owner, &mint.pubkey(), &spl_token::id()),
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &ata,
+                    &owner,
+                    &[],
+                    1_000,
+                )
+                .unwrap(),
+                spl_token::instruction::freeze_account(
+                    &spl_token::id(),
+                    &ata,
+                    &mint.pubkey(),
+                    &owner,
+                    &[],
+                )
+                .unwrap(),
+            ],
+        );
+
+        let check = TradeCheck {
+            direction: SwapDirection::Sell,
+            output: OutputAccount::Lamports(owner),
+            pre_balance: 0,
+            min_out: 0,
+        };
+        let burn =
+            spl_token::instruction::burn(&spl_token::id(), &ata, &mint.pubkey(), &owner, &[], 1)
+                .unwrap();
+        let err = simulate(&client, &payer, &[burn], Some(&check)).unwrap_err();
+        assert!(err.to_string().starts_with("Honeypot:"), "{}", err);
+    }
+
+    #[test]
+    #[ignore = "needs solana-test-validator"]
+    fn validator_unfunded_sell_is_not_a_honeypot() {
+        let client = validator();
+        let payer = Keypair::new();
+        let check = TradeCheck {
+            direction: SwapDirection::Sell,
+            output: OutputAccount::Lamports(payer.pubkey()),
+            pre_balance: 0,
+            min_out: 0,
+        };
+        let transfer = solana_sdk::system_instruction::transfer(
+            &payer.pubkey(),
+            &Pubkey::new_unique(),
+            1_000_000,
+        );
+        let err = simulate(&client, &payer, &[transfer], Some(&check)).unwrap_err();
+        assert!(err.to_string().starts_with("SimulationFailed:"), "{}", err);
+    }
+}
@@ -1,26 +1,83 @@
-use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use anyhow::{anyhow, Result};
+use solana_account_decoder::UiAccountData;
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer, transaction::Transaction};
 use spl_token_2022::{
-    extension::StateWithExtensionsOwned,
+    extension::{
+        transfer_fee::TransferFeeConfig, transfer_hook, BaseStateWithExtensions,
+        StateWithExtensionsOwned,
+    },
     state::{Account, Mint},
 };
 use spl_token_client::{
     client::{ProgramClient, ProgramRpcClient, ProgramRpcClientSendTransaction},
     token::{Token, TokenError, TokenResult},
 };
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, LazyLock},
+};
+use tokio::sync::RwLock;
+use tracing::warn;
+
+// close instructions packed into one transaction
+const CLOSE_BATCH: usize = 20;
+
+// the owning program and extensions of a mint are fixed at creation, so they are fetched once
+static MINT_INFOS: LazyLock<RwLock<HashMap<Pubkey, TokenMintInfo>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Mint with the token program that owns it and the Token-2022 extensions that change how
+/// its tokens move.
+#[derive(Debug, Clone)]
+pub struct TokenMintInfo {
+    pub mint: Pubkey,
+    pub program_id: Pubkey,
+    pub decimals: u8,
+    pub transfer_fee: Option<TransferFeeConfig>,
+    pub transfer_hook_program: Option<Pubkey>,
+}
+
+impl TokenMintInfo {
+    pub fn associated_token_address(&self, owner: &Pubkey) -> Pubkey {
+        spl_associated_token_account::get_associated_token_address_with_program_id(
+            owner,
+            &self.mint,
+            &self.program_id,
+        )
+    }
+
+    /// Fee withheld when `amount` is transferred during `epoch`.
+    pub fn transfer_fee(&self, epoch: u64, amount: u64) -> u64 {
+        self.transfer_fee
+            .and_then(|config| config.calculate_epoch_fee(epoch, amount))
+            .unwrap_or(0)
+    }
+
+    /// What the receiving side actually gets out of a transfer of `amount`.
+    pub fn amount_after_fee(&self, epoch: u64, amount: u64) -> u64 {
+        amount.saturating_sub(self.transfer_fee(epoch, amount))
+    }
+}
+
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == spl_token::ID || *program_id == spl_token_2022::ID
+}
 
 pub fn get_associated_token_address(
     client: Arc<solana_client::nonblocking::rpc_client::RpcClient>,
     keypair: Arc<Keypair>,
     address: &Pubkey,
     owner: &Pubkey,
+    program_id: &Pubkey,
 ) -> Pubkey {
     let token_client = Token::new(
         Arc::new(ProgramRpcClient::new(
             client.clone(),
             ProgramRpcClientSendTransaction,
         )),
-        &spl_token::ID,
+        program_id,
         address,
         None,
         Arc::new(Keypair::from_bytes(&keypair.to_bytes()).expect("failed to copy keypair")),
@@ -44,7 +101,7 @@ pub async fn get_account_info(
         .ok_or(TokenError::AccountNotFound)
         .inspect_err(|err| println!("get_account_info: {} {}: mint {}", account, err, address))?;
 
-    if account.owner != spl_token::ID {
+    if !is_token_program(&account.owner) {
         return Err(TokenError::AccountInvalidOwner);
     }
     let account = StateWithExtensionsOwned::<Account>::unpack(account.data)?;
@@ -71,7 +128,7 @@ pub async fn get_mint_info(
         .ok_or(TokenError::AccountNotFound)
         .inspect_err(|err| println!("{} {}: mint {}", address, err, address))?;
 
-    if account.owner != spl_token::ID {
+    if !is_token_program(&account.owner) {
         return Err(TokenError::AccountInvalidOwner);
     }
 
@@ -85,3 +142,134 @@ pub async fn get_mint_info(
 
     mint_result
 }
+
+pub async fn get_token_mint_info(
+    client: Arc<solana_client::nonblocking::rpc_client::RpcClient>,
+    address: &Pubkey,
+) -> TokenResult<TokenMintInfo> {
+    if let Some(info) = MINT_INFOS.read().await.get(address) {
+        return Ok(info.clone());
+    }
+    let program_client = Arc::new(ProgramRpcClient::new(
+        client.clone(),
+        ProgramRpcClientSendTransaction,
+    ));
+    let account = program_client
+        .get_account(*address)
+        .await
+        .map_err(TokenError::Client)?
+        .ok_or(TokenError::AccountNotFound)?;
+    if !is_token_program(&account.owner) {
+        return Err(TokenError::AccountInvalidOwner);
+    }
+    let program_id = account.owner;
+    let mint = StateWithExtensionsOwned::<Mint>::unpack(account.data)?;
+
+    let info = TokenMintInfo {
+        mint: *address,
+        program_id,
+        decimals: mint.base.decimals,
+        transfer_fee: mint.get_extension::<TransferFeeConfig>().ok().copied(),
+        transfer_hook_program: transfer_hook::get_program_id(&mint),
+    };
+    MINT_INFOS.write().await.insert(*address, info.clone());
+    Ok(info)
+}
+
+/// Transfer fees withheld in a parsed token-2022 account, which must be harvested to the mint
+/// before the account can be closed.
+fn withheld_amount(parsed: &serde_json::Value) -> u64 {
+    parsed["info"]["extensions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|extension| extension["extension"] == "transferFeeAmount")
+        .filter_map(|extension| extension["state"]["withheldAmount"].as_u64())
+        .sum()
+}
+
+/// Close every token account of the wallet that holds nothing and reclaim its rent. Accounts
+/// with withheld transfer fees are skipped, and a failed batch does not stop the others.
+/// Returns the signatures of the closing transactions.
+pub async fn close_empty_accounts(
+    client: &solana_client::nonblocking::rpc_client::RpcClient,
+    keypair: &Keypair,
+) -> Result<Vec<String>> {
+    let owner = keypair.pubkey();
+    let mut instructions = vec![];
+    for program_id in [spl_token::ID, spl_token_2022::ID] {
+        let accounts = client
+            .get_token_accounts_by_owner(&owner, TokenAccountsFilter::ProgramId(program_id))
+            .await?;
+        for keyed in accounts {
+            let UiAccountData::Json(parsed) = keyed.account.data else {
+                return Err(anyhow!("unparsed token account {}", keyed.pubkey));
+            };
+            if parsed.parsed["info"]["tokenAmount"]["amount"].as_str() != Some("0") {
+                continue;
+            }
+            let withheld = withheld_amount(&parsed.parsed);
+            if withheld > 0 {
+                warn!(
+                    account = %keyed.pubkey,
+                    withheld,
+                    "account withholds transfer fees, not closed"
+                );
+                continue;
+            }
+            let account = Pubkey::from_str(&keyed.pubkey)?;
+            instructions.push(spl_token_2022::instruction::close_account(
+                &program_id,
+                &account,
+                &owner,
+                &owner,
+                &[],
+            )?);
+        }
+    }
+
+    let mut signatures = vec![];
+    let mut failure: Option<anyhow::Error> = None;
+    for batch in instructions.chunks(CLOSE_BATCH) {
+        let blockhash = match client.get_latest_blockhash().await {
+            Ok(blockhash) => blockhash,
+            Err(e) => {
+                warn!(error = %e, "close batch not sent");
+                failure = Some(e.into());
+                continue;
+            }
+        };
+        let tx = Transaction::new_signed_with_payer(batch, Some(&owner), &[keypair], blockhash);
+        match client.send_and_confirm_transaction(&tx).await {
+            Ok(signature) => signatures.push(signature.to_string()),
+            Err(e) => {
+                warn!(error = %e, "close batch failed");
+                failure = Some(e.into());
+            }
+        }
+    }
+    match failure {
+        Some(e) if signatures.is_empty() => Err(e),
+        _ => Ok(signatures),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn withheld_fees_are_read_from_the_extensions() {
+        let parsed = serde_json::json!({
+            "info": {
+                "tokenAmount": { "amount": "0" },
+                "extensions": [
+                    { "extension": "immutableOwner" },
+                    { "extension": "transferFeeAmount", "state": { "withheldAmount": 42 } }
+                ]
+            }
+        });
+        assert_eq!(withheld_amount(&parsed), 42);
+        assert_eq!(withheld_amount(&serde_json::json!({ "info": {} })), 0);
+    }
+}
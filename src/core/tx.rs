@@ -1,42 +1,46 @@
 use std::{env, sync::Arc, time::Duration};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use jito_json_rpc_client::jsonrpc_client::rpc_client::RpcClient as JitoRpcClient;
-use solana_client::rpc_client::RpcClient;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcSendTransactionConfig, RpcTransactionConfig},
+};
 use solana_sdk::{
+    commitment_config::CommitmentConfig,
     hash::Hash,
     instruction::Instruction,
-    signature::Keypair,
+    message::Message,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
     signer::Signer,
     system_transaction,
     transaction::{Transaction, VersionedTransaction},
 };
+use solana_transaction_status::{
+    UiLoadedAddresses, UiTransactionEncoding, UiTransactionTokenBalance,
+};
 use spl_token::ui_amount_to_amount;
 
 use std::str::FromStr;
 use tokio::time::Instant;
+use tracing::{info_span, Instrument};
 
 use crate::{
-    common::utils::log_message,
+    common::{config::config, utils::log_message},
+    core::{
+        fee, lut, nonce,
+        sim::{self, TradeCheck, SIGNATURE_FEE},
+    },
     services::jito::{
         self, get_tip_account, get_tip_value, init_tip_accounts, wait_for_bundle_confirmation,
+        TIP_ACCOUNTS,
     },
 };
 
-// prioritization fee = UNIT_PRICE * UNIT_LIMIT
-fn get_unit_price() -> u64 {
-    env::var("UNIT_PRICE")
-        .ok()
-        .and_then(|v| u64::from_str(&v).ok())
-        .unwrap_or(1)
-}
+// how long a pre-signed exit is given to confirm before a fresh sell is sent instead
+const PRESIGNED_CONFIRM_TIMEOUT: Duration = Duration::from_secs(20);
 
-fn get_unit_limit() -> u32 {
-    env::var("UNIT_LIMIT")
-        .ok()
-        .and_then(|v| u32::from_str(&v).ok())
-        .unwrap_or(300_000)
-}
 pub async fn jito_confirm(
     keypair: &Keypair,
     version_tx: VersionedTransaction,
@@ -49,8 +53,194 @@ pub async fn new_signed_and_send(
     client: &RpcClient,
     keypair: &Keypair,
     mut instructions: Vec<Instruction>,
+    check: Option<TradeCheck>,
     jito_client: Arc<JitoRpcClient>,
     timestamp: Instant,
 ) -> Result<Vec<String>> {
+    let sign = info_span!("sign");
+    // reject the trade before broadcast if its simulation does not hold up
+    let simulated_units = match &check {
+        Some(check) => sim::guard(client, keypair, &instructions, check)?.units_consumed,
+        None => None,
+    };
+    let compute_budget = fee::compute_budget_instructions(
+        client,
+        keypair,
+        &instructions,
+        simulated_units,
+        &config().fees,
+    );
+    instructions.splice(0..0, compute_budget);
+
+    let recent_blockhash = client.get_latest_blockhash()?;
+    let lookup_tables = lut::lookup_table_accounts(client)
+        .instrument(sign.clone())
+        .await;
+    let version_tx = sign
+        .in_scope(|| lut::compile_v0(keypair, &instructions, &lookup_tables, recent_blockhash))?;
+    drop(sign);
+    // open until the bundle is confirmed
+    let _send = info_span!("send", signature = %version_tx.signatures[0]);
+
     // jito confirm
 }
+
+/// Sign against a durable nonce instead of a recent blockhash. The message starts with
+/// `advance_nonce_account`, so the transaction stays valid until the nonce is advanced and can
+/// be prepared long before it is sent.
+pub fn new_signed_with_nonce(
+    client: &RpcClient,
+    keypair: &Keypair,
+    mut instructions: Vec<Instruction>,
+    nonce_account: &Pubkey,
+) -> Result<Transaction> {
+    let compute_budget =
+        fee::compute_budget_instructions(client, keypair, &instructions, None, &config().fees);
+    instructions.splice(0..0, compute_budget);
+
+    let nonce_hash = nonce::fetch_nonce(client, nonce_account, &keypair.pubkey())?;
+    let message = Message::new_with_nonce(
+        instructions,
+        Some(&keypair.pubkey()),
+        nonce_account,
+        &keypair.pubkey(),
+    );
+    let mut tx = Transaction::new_unsigned(message);
+    tx.try_sign(&[keypair], nonce_hash)?;
+    Ok(tx)
+}
+
+/// Broadcast a pre-signed transaction as-is, skipping preflight to save the round trip, and
+/// wait until it is confirmed so the trade can be settled.
+pub async fn send_presigned(
+    client: &solana_client::nonblocking::rpc_client::RpcClient,
+    tx: &Transaction,
+) -> Result<Signature> {
+    let signature = client
+        .send_transaction_with_config(
+            tx,
+            RpcSendTransactionConfig {
+                skip_preflight: true,
+                max_retries: Some(0),
+                ..Default::default()
+            },
+        )
+        .await?;
+    let sent = Instant::now();
+    while sent.elapsed() < PRESIGNED_CONFIRM_TIMEOUT {
+        let status = client
+            .get_signature_status_with_commitment(&signature, CommitmentConfig::confirmed())
+            .await?;
+        match status {
+            Some(Ok(())) => return Ok(signature),
+            Some(Err(e)) => return Err(anyhow!("Presigned: {} failed: {}", signature, e)),
+            None => tokio::time::sleep(Duration::from_millis(400)).await,
+        }
+    }
+    Err(anyhow!(
+        "Presigned: {} not confirmed within {:?}",
+        signature,
+        PRESIGNED_CONFIRM_TIMEOUT
+    ))
+}
+
+/// What landing a trade cost on top of the swap itself, in lamports
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TradeCosts {
+    /// Base fee of the signatures
+    pub network_fee: u64,
+    pub priority_fee: u64,
+    /// Lamports paid to the Jito tip accounts
+    pub tip: u64,
+}
+
+/// What a landed trade did to the wallet that sent it, read from its own transactions so
+/// trades landing at the same time are not mixed up
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TradeOutcome {
+    pub costs: TradeCosts,
+    /// Change of the wallet's lamports, fees and tips included
+    pub sol_change: i64,
+    /// Change of the wallet's raw token balance in the mint
+    pub token_change: i64,
+}
+
+/// Break down the fees and tips paid by landed transactions, and the wallet's SOL and token
+/// balance changes, from their metadata.
+pub async fn trade_outcome(
+    client: &solana_client::nonblocking::rpc_client::RpcClient,
+    signatures: &[String],
+    wallet: &Pubkey,
+    mint: &str,
+) -> Result<TradeOutcome> {
+    let tip_accounts: Vec<Pubkey> = TIP_ACCOUNTS
+        .read()
+        .await
+        .iter()
+        .filter_map(|key| Pubkey::from_str(key).ok())
+        .collect();
+    let owner = wallet.to_string();
+    let mut outcome = TradeOutcome::default();
+    for signature in signatures {
+        let tx = client
+            .get_transaction_with_config(
+                &Signature::from_str(signature)?,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await?;
+        let meta = tx
+            .transaction
+            .meta
+            .ok_or(anyhow!("no metadata for {}", signature))?;
+        let decoded = tx
+            .transaction
+            .transaction
+            .decode()
+            .ok_or(anyhow!("undecodable transaction {}", signature))?;
+        let network_fee = decoded.signatures.len() as u64 * SIGNATURE_FEE;
+        outcome.costs.network_fee += network_fee.min(meta.fee);
+        outcome.costs.priority_fee += meta.fee.saturating_sub(network_fee);
+
+        // balances follow the static keys, then the writable and readonly table lookups
+        let mut keys = decoded.message.static_account_keys().to_vec();
+        if let Some(loaded) = Option::<UiLoadedAddresses>::from(meta.loaded_addresses) {
+            keys.extend(
+                loaded
+                    .writable
+                    .iter()
+                    .chain(loaded.readonly.iter())
+                    .filter_map(|key| Pubkey::from_str(key).ok()),
+            );
+        }
+        for (i, key) in keys.iter().enumerate() {
+            let pre = meta.pre_balances.get(i).copied().unwrap_or_default();
+            let post = meta.post_balances.get(i).copied().unwrap_or_default();
+            if tip_accounts.contains(key) {
+                outcome.costs.tip += post.saturating_sub(pre);
+            }
+            if key == wallet {
+                outcome.sol_change += post as i64 - pre as i64;
+            }
+        }
+
+        // token accounts of the wallet in the mint, whatever their address
+        let held = |balances: Option<Vec<UiTransactionTokenBalance>>| -> i64 {
+            balances
+                .unwrap_or_default()
+                .iter()
+                .filter(|balance| balance.mint == mint)
+                .filter(|balance| {
+                    Option::<String>::from(balance.owner.clone()) == Some(owner.clone())
+                })
+                .filter_map(|balance| i64::from_str(&balance.ui_token_amount.amount).ok())
+                .sum()
+        };
+        outcome.token_change +=
+            held(meta.post_token_balances.into()) - held(meta.pre_token_balances.into());
+    }
+    Ok(outcome)
+}
@@ -2,6 +2,7 @@ use std::{str::FromStr, sync::Arc};
 
 use crate::{
     core::{
+        sim::{self, OutputAccount, TradeCheck},
         token::{self, get_account_info},
         tx,
     },
@@ -21,11 +22,14 @@ use solana_sdk::{
     system_program,
 };
 use spl_associated_token_account::{
-    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+    get_associated_token_address, get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
 };
 use tokio::time::Instant;
+use tracing::{info_span, Instrument};
 pub const TEN_THOUSAND: u64 = 10000;
 pub const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const TOKEN_2022_PROGRAM: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PjnqsyLSvRK9u7Yf";
 pub const RENT_PROGRAM: &str = "SysvarRent111111111111111111111111111111111";
 pub const ASSOCIATED_TOKEN_PROGRAM: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
 pub const PUMP_GLOBAL: &str = "4wTV1YmiEkRvAtNtsSGPtUrqRYQMe5SKy2uB4Jjaxnjf";
@@ -35,6 +39,10 @@ pub const PUMP_PROGRAM: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
 pub const PUMP_ACCOUNT: &str = "Ce6TQqeHC9p8KetsN6JsjHK7UTZk7nasjjnr7XxXp9F1";
 pub const PUMP_BUY_METHOD: u64 = 16927863322537952870;
 pub const PUMP_SELL_METHOD: u64 = 12502976635542562355;
+pub const PUMP_FEE_BPS: u64 = 100;
+// virtual token reserves left on the curve once every real token has been sold
+pub const PUMP_FINAL_VIRTUAL_TOKEN_RESERVES: u64 = 279_900_000_000_000;
+pub const PUMP_INITIAL_REAL_TOKEN_RESERVES: u64 = 793_100_000_000_000;
 
 pub struct Pump {
     pub rpc_nonblocking_client: Arc<solana_client::nonblocking::rpc_client::RpcClient>,
@@ -61,20 +69,178 @@ impl Pump {
         amount_in: u64,
         swap_direction: SwapDirection,
         slippage: u64,
+        simulate: bool,
         jito_client: Arc<JitoRpcClient>,
         timestamp: Instant,
     ) -> Result<Vec<String>> {
-        // make instruction on pumpfun
+        let build = info_span!("build", mint);
+        let (instructions, min_out) = self
+            .swap_instructions(mint, amount_in, swap_direction.clone(), slippage)
+            .instrument(build.clone())
+            .await?;
 
+        let check = if simulate {
+            Some(
+                self.trade_check(mint, swap_direction, min_out)
+                    .instrument(build.clone())
+                    .await?,
+            )
+        } else {
+            None
+        };
+        drop(build);
+        let client = self.rpc_client.clone().unwrap();
         tx::new_signed_and_send(
             &client,
             &self.keypair,
             instructions,
+            check,
             jito_client.clone(),
             timestamp.clone(),
         )
         .await
     }
+
+    /// Swap instructions on the bonding curve, with the minimum output allowed by the slippage.
+    pub async fn swap_instructions(
+        &self,
+        mint: &str,
+        amount_in: u64,
+        swap_direction: SwapDirection,
+        slippage: u64,
+    ) -> Result<(Vec<Instruction>, u64)> {
+        let mint = Pubkey::from_str(mint)?;
+        let owner = self.keypair.pubkey();
+        let program_id = Pubkey::from_str(PUMP_PROGRAM)?;
+        let mint_info =
+            token::get_token_mint_info(self.rpc_nonblocking_client.clone(), &mint).await?;
+        // the hook's extra accounts are not resolved, so the transfer would fail on chain
+        if let Some(hook_program) = mint_info.transfer_hook_program {
+            return Err(anyhow!(
+                "UnsupportedMint: {} runs transfer hook {}, which is not supported",
+                mint,
+                hook_program
+            ));
+        }
+        let token_program = mint_info.program_id;
+        let ata = mint_info.associated_token_address(&owner);
+        let client = self
+            .rpc_client
+            .clone()
+            .context("Pump: no blocking rpc client")?;
+        let (bonding_curve, _, curve) =
+            get_bonding_curve_account(client, &mint, &program_id).await?;
+        if curve.complete {
+            return Err(anyhow!("Pump: {} has left the bonding curve", mint));
+        }
+        // the curve holds the mint under its own token program
+        let associated_bonding_curve =
+            get_associated_token_address_with_program_id(&bonding_curve, &mint, &token_program);
+        let quote = PumpInfo {
+            virtual_sol_reserves: curve.virtual_sol_reserves,
+            virtual_token_reserves: curve.virtual_token_reserves,
+            ..Default::default()
+        };
+        // a transfer fee is withheld from the tokens on their way in or out of the curve, the
+        // epoch it is charged at is only needed for mints that have one
+        let epoch = match mint_info.transfer_fee {
+            Some(_) => self.rpc_nonblocking_client.get_epoch_info().await?.epoch,
+            None => 0,
+        };
+
+        let mut instructions = vec![];
+        let (data, min_out, tail) = match swap_direction {
+            SwapDirection::Buy => {
+                instructions.push(create_associated_token_account_idempotent(
+                    &owner,
+                    &owner,
+                    &mint,
+                    &token_program,
+                ));
+                let tokens_out = quote.buy_quote(amount_in);
+                let max_sol_cost = max_amount_with_slippage(amount_in, slippage);
+                let received = mint_info.amount_after_fee(epoch, tokens_out);
+                (
+                    pump_data(PUMP_BUY_METHOD, tokens_out, max_sol_cost),
+                    min_amount_with_slippage(received, slippage),
+                    Pubkey::from_str(RENT_PROGRAM)?,
+                )
+            }
+            SwapDirection::Sell => {
+                let delivered = mint_info.amount_after_fee(epoch, amount_in);
+                let min_sol_output =
+                    min_amount_with_slippage(quote.sell_quote(delivered), slippage);
+                (
+                    pump_data(PUMP_SELL_METHOD, amount_in, min_sol_output),
+                    min_sol_output,
+                    Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM)?,
+                )
+            }
+        };
+        // buys take the rent sysvar after the token program, sells the associated token
+        // program before it
+        let (first, second) = match swap_direction {
+            SwapDirection::Buy => (token_program, tail),
+            SwapDirection::Sell => (tail, token_program),
+        };
+        let accounts = vec![
+            AccountMeta::new_readonly(Pubkey::from_str(PUMP_GLOBAL)?, false),
+            AccountMeta::new(Pubkey::from_str(PUMP_FEE_RECIPIENT)?, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new(bonding_curve, false),
+            AccountMeta::new(associated_bonding_curve, false),
+            AccountMeta::new(ata, false),
+            AccountMeta::new(owner, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(first, false),
+            AccountMeta::new_readonly(second, false),
+            AccountMeta::new_readonly(Pubkey::from_str(PUMP_ACCOUNT)?, false),
+            AccountMeta::new_readonly(program_id, false),
+        ];
+        instructions.push(Instruction {
+            program_id,
+            accounts,
+            data,
+        });
+
+        Ok((instructions, min_out))
+    }
+
+    /// Buys land in our ATA; sells on the bonding curve pay lamports straight to the wallet.
+    pub async fn trade_check(
+        &self,
+        mint: &str,
+        swap_direction: SwapDirection,
+        min_out: u64,
+    ) -> Result<TradeCheck> {
+        let owner = self.keypair.pubkey();
+        let output = match swap_direction {
+            SwapDirection::Buy => {
+                let mint = Pubkey::from_str(mint)?;
+                let mint_info =
+                    token::get_token_mint_info(self.rpc_nonblocking_client.clone(), &mint).await?;
+                OutputAccount::Token(mint_info.associated_token_address(&owner))
+            }
+            SwapDirection::Sell => OutputAccount::Lamports(owner),
+        };
+        let pre_balance = sim::output_balance(&self.rpc_nonblocking_client, output).await?;
+        Ok(TradeCheck {
+            direction: swap_direction,
+            output,
+            pre_balance,
+            min_out,
+        })
+    }
+}
+
+// anchor discriminator, then the token amount and the SOL bound
+fn pump_data(method: u64, token_amount: u64, sol_bound: u64) -> Vec<u8> {
+    [
+        method.to_le_bytes(),
+        token_amount.to_le_bytes(),
+        sol_bound.to_le_bytes(),
+    ]
+    .concat()
 }
 
 fn min_amount_with_slippage(input_amount: u64, slippage_bps: u64) -> u64 {
@@ -110,6 +276,44 @@ pub struct PumpInfo {
     pub total_supply: u64,
 }
 
+impl PumpInfo {
+    /// Tokens out of the bonding curve for `sol_in` lamports, after the pump fee.
+    pub fn buy_quote(&self, sol_in: u64) -> u64 {
+        let sol_in = sol_in as u128 * (TEN_THOUSAND - PUMP_FEE_BPS) as u128 / TEN_THOUSAND as u128;
+        let virtual_sol = self.virtual_sol_reserves as u128;
+        let virtual_token = self.virtual_token_reserves as u128;
+        (virtual_token * sol_in / (virtual_sol + sol_in)) as u64
+    }
+
+    /// Lamports out of the bonding curve for `token_in` tokens, after the pump fee.
+    pub fn sell_quote(&self, token_in: u64) -> u64 {
+        let token_in = token_in as u128;
+        let virtual_sol = self.virtual_sol_reserves as u128;
+        let virtual_token = self.virtual_token_reserves as u128;
+        let sol_out = virtual_sol * token_in / (virtual_token + token_in);
+        (sol_out * (TEN_THOUSAND - PUMP_FEE_BPS) as u128 / TEN_THOUSAND as u128) as u64
+    }
+
+    /// Share of the curve's real tokens already sold, in percent.
+    pub fn curve_progress(&self) -> f64 {
+        if self.complete {
+            return 100.0;
+        }
+        let remaining = self
+            .virtual_token_reserves
+            .saturating_sub(PUMP_FINAL_VIRTUAL_TOKEN_RESERVES);
+        100.0 - remaining as f64 * 100.0 / PUMP_INITIAL_REAL_TOKEN_RESERVES as f64
+    }
+
+    /// Spot price in lamports per raw token unit.
+    pub fn price(&self) -> f64 {
+        if self.virtual_token_reserves == 0 {
+            return 0.0;
+        }
+        self.virtual_sol_reserves as f64 / self.virtual_token_reserves as f64
+    }
+}
+
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct BondingCurveAccount {
     pub discriminator: u64,
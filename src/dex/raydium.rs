@@ -1,6 +1,7 @@
 use crate::{
     core::{
-        token::{get_account_info, get_mint_info},
+        sim::{self, OutputAccount, TradeCheck},
+        token::{self, get_account_info, get_mint_info},
         tx,
     },
     engine::swap::{SwapDirection, SwapInType},
@@ -25,9 +26,11 @@ use spl_token::{amount_to_ui_amount, state::Account, ui_amount_to_amount};
 use spl_token_client::token::TokenError;
 use std::{str::FromStr, sync::Arc, time::Duration};
 use tokio::time::Instant;
+use tracing::{info_span, Instrument};
 
 pub const AMM_PROGRAM: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
 pub const RAYDIUM_AUTHORITY_V4: &str = "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1";
+pub const AMM_FEE_BPS: u64 = 25;
 
 #[derive(Serialize)]
 struct SwapRequest {
@@ -105,20 +108,91 @@ impl Raydium {
         amount_in: u64,
         pool_id: String,
         slippage: u64,
+        simulate: bool,
         start_time: Instant,
         jito_client: Arc<JitoRpcClient>,
     ) -> Result<Vec<String>> {
-        // make instructions on raydium
+        let build = info_span!("build", mint = mint_str);
+        let (instructions, min_out) = self
+            .swap_instructions(
+                mint_str,
+                swap_direction.clone(),
+                amount_in,
+                pool_id,
+                slippage,
+            )
+            .instrument(build.clone())
+            .await?;
 
+        let check = if simulate {
+            Some(
+                self.trade_check(mint_str, swap_direction, min_out)
+                    .instrument(build.clone())
+                    .await?,
+            )
+        } else {
+            None
+        };
+        drop(build);
         tx::new_signed_and_send(
             &self.rpc_client.clone().unwrap(),
             &self.keypair,
             instructions,
+            check,
             jito_client.clone(),
             start_time.clone(),
         )
         .await
     }
+
+    /// Swap instructions on the AMM pool, with the minimum output allowed by the slippage.
+    pub async fn swap_instructions(
+        &self,
+        mint_str: &str,
+        swap_direction: SwapDirection,
+        amount_in: u64,
+        pool_id: String,
+        slippage: u64,
+    ) -> Result<(Vec<Instruction>, u64)> {
+        let mint = Pubkey::from_str(mint_str)?;
+        let mint_info =
+            token::get_token_mint_info(self.rpc_nonblocking_client.clone(), &mint).await?;
+        // AMM v4 vaults are plain SPL Token accounts, Token-2022 mints never get a v4 pool
+        if mint_info.program_id != spl_token::ID {
+            return Err(anyhow!(
+                "UnsupportedMint: {} is owned by {}, Raydium AMM v4 only trades SPL Token mints",
+                mint_str,
+                mint_info.program_id
+            ));
+        }
+        // make instructions on raydium
+
+        Ok((instructions, min_out))
+    }
+
+    /// Both legs settle into ATAs: the token ATA on buys, the wSOL ATA on sells.
+    pub async fn trade_check(
+        &self,
+        mint: &str,
+        swap_direction: SwapDirection,
+        min_out: u64,
+    ) -> Result<TradeCheck> {
+        let owner = self.keypair.pubkey();
+        let output_mint = match swap_direction {
+            SwapDirection::Buy => Pubkey::from_str(mint)?,
+            SwapDirection::Sell => spl_token::native_mint::ID,
+        };
+        let mint_info =
+            token::get_token_mint_info(self.rpc_nonblocking_client.clone(), &output_mint).await?;
+        let output = OutputAccount::Token(mint_info.associated_token_address(&owner));
+        let pre_balance = sim::output_balance(&self.rpc_nonblocking_client, output).await?;
+        Ok(TradeCheck {
+            direction: swap_direction,
+            output,
+            pre_balance,
+            min_out,
+        })
+    }
 }
 pub fn amm_swap(
     amm_program: &Pubkey,
@@ -133,6 +207,12 @@ pub fn amm_swap(
     Ok(swap_instruction)
 }
 
+/// Constant product output for `amount_in`, after the AMM fee.
+pub fn amm_quote(reserve_in: u64, reserve_out: u64, amount_in: u64) -> u64 {
+    let amount_in = amount_in as u128 * (10_000 - AMM_FEE_BPS) as u128 / 10_000;
+    (reserve_out as u128 * amount_in / (reserve_in as u128 + amount_in)) as u64
+}
+
 pub async fn get_pool_state(
     rpc_client: Arc<solana_client::rpc_client::RpcClient>,
     pool_id: Option<&str>,
@@ -189,8 +269,7 @@ pub async fn get_pool_state_by_mint(
             ]),
         };
         let pools =
-            common::rpc::get_program_accounts_with_filters(&rpc_client, amm_program, filters)
-                .unwrap();
+            common::rpc::get_program_accounts_with_filters(&rpc_client, amm_program, filters)?;
         if !pools.is_empty() {
             found_pools = Some(pools);
             break;
@@ -0,0 +1,379 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::{
+    common::utils::env_or,
+    engine::{
+        backtest::{load_events, MarketEvent},
+        swap::SwapDirection,
+    },
+};
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+
+#[derive(Debug, Clone)]
+pub struct AnalyticsConfig {
+    /// Slots after a buy over which the price move is measured
+    pub copy_slots: u64,
+    /// Closed trades a wallet needs to be ranked
+    pub min_trades: usize,
+}
+
+impl AnalyticsConfig {
+    pub fn from_env() -> Self {
+        Self {
+            copy_slots: env_or("ANALYZE_SLOTS", 2),
+            min_trades: env_or("ANALYZE_MIN_TRADES", 5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WalletStats {
+    pub wallet: String,
+    pub buys: usize,
+    pub sells: usize,
+    /// Sells matched against earlier buys in the history
+    pub closed_trades: usize,
+    pub win_rate: f64,
+    /// Mean return of closed trades, in percent of their cost
+    pub avg_return_pct: f64,
+    pub avg_hold_secs: f64,
+    /// Median SOL spent per buy, in lamports
+    pub typical_size: u64,
+    /// Share of trades per venue, in percent
+    pub venues: BTreeMap<String, f64>,
+    /// Mean price move in the first `copy_slots` slots after a buy, in percent; what a copy
+    /// landing that late pays on top of the target. Only measured on mints whose history has
+    /// other traders' swaps, `None` on target-only recordings.
+    pub avg_move_pct: Option<f64>,
+    /// Average return left for a copy once the price move is paid, in percent (the return
+    /// alone when the move is unavailable)
+    pub score: f64,
+}
+
+// open holding of the wallet in a mint
+#[derive(Default)]
+struct Holding {
+    tokens: u64,
+    cost: u64,
+    opened_at: i64,
+}
+
+/// Price history of a mint
+#[derive(Debug, Default)]
+pub struct Timeline<'a> {
+    /// Lamports per token after each event
+    prices: Vec<(u64, f64)>,
+    /// Wallets that traded the mint
+    traders: HashSet<&'a str>,
+}
+
+impl Timeline<'_> {
+    /// Whether swaps of wallets other than `wallet` moved the price, without them the
+    /// history is flat between the wallet's own trades.
+    fn market_wide(&self, wallet: &str) -> bool {
+        self.traders.iter().any(|trader| *trader != wallet)
+    }
+}
+
+/// Price history of every mint in the events
+pub fn price_timelines(events: &[MarketEvent]) -> HashMap<&str, Timeline<'_>> {
+    let mut timelines: HashMap<&str, Timeline> = HashMap::new();
+    for event in events {
+        if event.token_reserve == 0 {
+            continue;
+        }
+        let timeline = timelines.entry(event.mint.as_str()).or_default();
+        timeline.traders.insert(event.trader.as_str());
+        timeline.prices.push((
+            event.slot,
+            event.sol_reserve as f64 / event.token_reserve as f64,
+        ));
+    }
+    timelines
+}
+
+/// Last price of the timeline at or before `slot`.
+fn price_at(timeline: &[(u64, f64)], slot: u64) -> Option<f64> {
+    let index = timeline.partition_point(|(at, _)| *at <= slot);
+    index.checked_sub(1).map(|index| timeline[index].1)
+}
+
+/// Trading statistics of `wallet` over the events, which must be ordered by slot.
+pub fn analyze_wallet(
+    events: &[MarketEvent],
+    timelines: &HashMap<&str, Timeline>,
+    wallet: &str,
+    config: &AnalyticsConfig,
+) -> WalletStats {
+    let mut stats = WalletStats {
+        wallet: wallet.to_string(),
+        ..Default::default()
+    };
+    let mut holdings: HashMap<&str, Holding> = HashMap::new();
+    let mut returns = vec![];
+    let mut holds = vec![];
+    let mut sizes = vec![];
+    let mut moves = vec![];
+    let mut venues: BTreeMap<String, usize> = BTreeMap::new();
+
+    for event in events.iter().filter(|event| event.trader == wallet) {
+        *venues.entry(event.venue.to_string()).or_default() += 1;
+        let holding = holdings.entry(event.mint.as_str()).or_default();
+        match event.direction {
+            SwapDirection::Buy => {
+                stats.buys += 1;
+                sizes.push(event.sol_amount);
+                if holding.tokens == 0 {
+                    holding.opened_at = event.timestamp;
+                }
+                holding.tokens += event.token_amount;
+                holding.cost += event.sol_amount;
+
+                let timeline = timelines
+                    .get(event.mint.as_str())
+                    .filter(|timeline| timeline.market_wide(wallet));
+                if let Some(timeline) = timeline {
+                    let entry = price_at(&timeline.prices, event.slot);
+                    let later = price_at(&timeline.prices, event.slot + config.copy_slots);
+                    if let (Some(entry), Some(later)) = (entry, later) {
+                        moves.push((later / entry - 1.0) * 100.0);
+                    }
+                }
+            }
+            SwapDirection::Sell => {
+                stats.sells += 1;
+                // tokens bought before the history started have no cost to measure against
+                if holding.tokens == 0 {
+                    continue;
+                }
+                let sold = event.token_amount.min(holding.tokens);
+                let cost = (holding.cost as u128 * sold as u128 / holding.tokens as u128) as u64;
+                if cost > 0 {
+                    returns.push((event.sol_amount as f64 / cost as f64 - 1.0) * 100.0);
+                    holds.push((event.timestamp - holding.opened_at) as f64);
+                }
+                holding.tokens -= sold;
+                holding.cost -= cost;
+            }
+        }
+    }
+
+    let mean = |values: &[f64]| {
+        if values.is_empty() {
+            0.0
+        } else {
+            values.iter().sum::<f64>() / values.len() as f64
+        }
+    };
+    stats.closed_trades = returns.len();
+    if !returns.is_empty() {
+        let wins = returns.iter().filter(|r| **r > 0.0).count();
+        stats.win_rate = wins as f64 * 100.0 / returns.len() as f64;
+    }
+    stats.avg_return_pct = mean(&returns);
+    stats.avg_hold_secs = mean(&holds);
+    stats.avg_move_pct = Some(mean(&moves)).filter(|_| !moves.is_empty());
+    sizes.sort_unstable();
+    stats.typical_size = sizes.get(sizes.len() / 2).copied().unwrap_or(0);
+    let trades = (stats.buys + stats.sells).max(1) as f64;
+    stats.venues = venues
+        .into_iter()
+        .map(|(venue, count)| (venue, count as f64 * 100.0 / trades))
+        .collect();
+    stats.score = stats.avg_return_pct - stats.avg_move_pct.unwrap_or(0.0);
+    stats
+}
+
+/// Rank `wallets` (every trader in the history when empty) by score, best first. Wallets with
+/// fewer than `min_trades` closed trades are left out.
+pub fn rank(
+    events: &[MarketEvent],
+    wallets: &[String],
+    config: &AnalyticsConfig,
+) -> Vec<WalletStats> {
+    let timelines = price_timelines(events);
+    let candidates: Vec<String> = if wallets.is_empty() {
+        let traders: HashSet<&str> = events.iter().map(|event| event.trader.as_str()).collect();
+        traders.into_iter().map(str::to_string).collect()
+    } else {
+        wallets.to_vec()
+    };
+    let mut ranked: Vec<WalletStats> = candidates
+        .iter()
+        .map(|wallet| analyze_wallet(events, &timelines, wallet, config))
+        .filter(|stats| stats.closed_trades >= config.min_trades)
+        .collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    ranked
+}
+
+pub fn print_ranking(ranked: &[WalletStats]) {
+    println!(
+        "{:<44} {:>6} {:>7} {:>9} {:>9} {:>9} {:>9} {:>8} {}",
+        "wallet", "trades", "win %", "return %", "move %", "score", "hold s", "size", "venues"
+    );
+    for stats in ranked {
+        let venues: Vec<String> = stats
+            .venues
+            .iter()
+            .map(|(venue, share)| format!("{} {:.0}%", venue, share))
+            .collect();
+        let move_pct = stats
+            .avg_move_pct
+            .map(|move_pct| format!("{:.1}", move_pct))
+            .unwrap_or_else(|| "n/a".to_string());
+        println!(
+            "{:<44} {:>6} {:>7.1} {:>9.1} {:>9} {:>9.1} {:>9.0} {:>8.3} {}",
+            stats.wallet,
+            stats.closed_trades,
+            stats.win_rate,
+            stats.avg_return_pct,
+            move_pct,
+            stats.score,
+            stats.avg_hold_secs,
+            stats.typical_size as f64 / LAMPORTS_PER_SOL,
+            venues.join(", ")
+        );
+    }
+}
+
+/// Rank candidate wallets over the recordings or exports in `paths`, with the configuration
+/// from the environment.
+pub fn analyze(wallets: &[String], paths: &[String]) -> Result<Vec<WalletStats>> {
+    if paths.is_empty() {
+        return Err(anyhow!("no recordings or exports to analyze"));
+    }
+    let events = load_events(paths)?;
+    Ok(rank(&events, wallets, &AnalyticsConfig::from_env()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::position::Venue;
+
+    const WALLET: &str = "wallet";
+    const SOL: u64 = 1_000_000_000;
+
+    fn config() -> AnalyticsConfig {
+        AnalyticsConfig {
+            copy_slots: 2,
+            min_trades: 1,
+        }
+    }
+
+    // a swap of `trader` leaving the price at `price` lamports per token
+    fn swap(
+        slot: u64,
+        trader: &str,
+        mint: &str,
+        direction: SwapDirection,
+        sol_amount: u64,
+        token_amount: u64,
+        price: u64,
+    ) -> MarketEvent {
+        MarketEvent {
+            slot,
+            timestamp: slot as i64 * 10,
+            signature: format!("sig-{}-{}", trader, slot),
+            trader: trader.to_string(),
+            mint: mint.to_string(),
+            venue: Venue::Pump,
+            direction,
+            sol_amount,
+            token_amount,
+            sol_reserve: 1_000 * price,
+            token_reserve: 1_000,
+        }
+    }
+
+    fn analyze(events: &[MarketEvent], wallet: &str) -> WalletStats {
+        analyze_wallet(events, &price_timelines(events), wallet, &config())
+    }
+
+    #[test]
+    fn closed_trades_are_measured_against_their_cost() {
+        let events = [
+            swap(10, WALLET, "a", SwapDirection::Buy, SOL, 1_000, 100),
+            swap(12, WALLET, "a", SwapDirection::Buy, 3 * SOL, 1_000, 100),
+            // half the holding at an average cost of 2 SOL per 1_000 tokens
+            swap(16, WALLET, "a", SwapDirection::Sell, 3 * SOL, 1_000, 100),
+            swap(20, WALLET, "b", SwapDirection::Buy, 2 * SOL, 500, 100),
+            swap(26, WALLET, "b", SwapDirection::Sell, SOL, 500, 100),
+        ];
+        let stats = analyze(&events, WALLET);
+        assert_eq!(stats.buys, 3);
+        assert_eq!(stats.sells, 2);
+        assert_eq!(stats.closed_trades, 2);
+        assert_eq!(stats.win_rate, 50.0);
+        // +50% and -50%
+        assert_eq!(stats.avg_return_pct, 0.0);
+        // held 60s and 60s
+        assert_eq!(stats.avg_hold_secs, 60.0);
+        assert_eq!(stats.typical_size, 2 * SOL);
+        assert_eq!(stats.venues["pump"], 100.0);
+    }
+
+    #[test]
+    fn sells_of_tokens_bought_before_the_history_are_not_closed_trades() {
+        let events = [swap(10, WALLET, "a", SwapDirection::Sell, SOL, 1_000, 100)];
+        let stats = analyze(&events, WALLET);
+        assert_eq!(stats.sells, 1);
+        assert_eq!(stats.closed_trades, 0);
+    }
+
+    #[test]
+    fn move_is_measured_on_market_wide_events() {
+        let events = [
+            swap(10, WALLET, "a", SwapDirection::Buy, SOL, 1_000, 100),
+            swap(11, "other", "a", SwapDirection::Buy, SOL, 1_000, 110),
+            // beyond copy_slots
+            swap(13, "other", "a", SwapDirection::Buy, SOL, 1_000, 200),
+            swap(20, WALLET, "a", SwapDirection::Sell, 2 * SOL, 1_000, 200),
+        ];
+        let stats = analyze(&events, WALLET);
+        let move_pct = stats.avg_move_pct.unwrap();
+        assert!((move_pct - 10.0).abs() < 1e-9);
+        assert!((stats.score - (100.0 - 10.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn move_is_unavailable_on_target_only_events() {
+        let events = [
+            swap(10, WALLET, "a", SwapDirection::Buy, SOL, 1_000, 100),
+            swap(11, WALLET, "a", SwapDirection::Buy, SOL, 1_000, 150),
+            swap(20, WALLET, "a", SwapDirection::Sell, 3 * SOL, 2_000, 150),
+        ];
+        let stats = analyze(&events, WALLET);
+        assert_eq!(stats.avg_move_pct, None);
+        assert_eq!(stats.score, stats.avg_return_pct);
+    }
+
+    #[test]
+    fn rank_orders_by_score_and_drops_thin_histories() {
+        let events = [
+            swap(10, "loser", "a", SwapDirection::Buy, SOL, 1_000, 100),
+            swap(11, "winner", "a", SwapDirection::Buy, SOL, 1_000, 100),
+            swap(12, "thin", "a", SwapDirection::Buy, SOL, 1_000, 100),
+            swap(20, "loser", "a", SwapDirection::Sell, SOL / 2, 1_000, 100),
+            swap(21, "winner", "a", SwapDirection::Sell, 2 * SOL, 1_000, 100),
+        ];
+        let ranked = rank(&events, &[], &config());
+        let wallets: Vec<&str> = ranked.iter().map(|stats| stats.wallet.as_str()).collect();
+        assert_eq!(wallets, ["winner", "loser"]);
+
+        let ranked = rank(&events, &["loser".to_string()], &config());
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].wallet, "loser");
+
+        let strict = AnalyticsConfig {
+            min_trades: 2,
+            ..config()
+        };
+        assert!(rank(&events, &[], &strict).is_empty());
+    }
+}
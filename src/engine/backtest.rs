@@ -0,0 +1,1125 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use borsh::BorshDeserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_client::{
+    rpc_client::GetConfirmedSignaturesForAddress2Config, rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::UiTransactionEncoding;
+use tracing::warn;
+
+use crate::{
+    common::{
+        config::{Config, CONFIG_PATH},
+        utils::{env_or, AppState},
+    },
+    dex::{
+        pump::{get_pda, PumpInfo, PUMP_PROGRAM},
+        raydium::{amm_quote, get_pool_state_by_mint, RAYDIUM_AUTHORITY_V4},
+    },
+    engine::{
+        exit::{evaluate, ExitConfig, ExitState},
+        position::{Position, Venue},
+        risk::RiskConfig,
+        swap::SwapDirection,
+    },
+    services::recorder::read_recording,
+};
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+// anchor discriminator of the pump program's TradeEvent
+const PUMP_TRADE_EVENT: [u8; 8] = [189, 219, 127, 211, 78, 230, 97, 238];
+
+/// A swap seen on chain, with the reserves it left behind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketEvent {
+    pub slot: u64,
+    /// Unix seconds
+    pub timestamp: i64,
+    pub signature: String,
+    pub trader: String,
+    pub mint: String,
+    pub venue: Venue,
+    pub direction: SwapDirection,
+    pub sol_amount: u64,
+    pub token_amount: u64,
+    /// Virtual curve reserves on pump, vault balances on Raydium, after the trade
+    pub sol_reserve: u64,
+    pub token_reserve: u64,
+}
+
+#[derive(BorshDeserialize)]
+struct PumpTradeEvent {
+    mint: [u8; 32],
+    sol_amount: u64,
+    token_amount: u64,
+    is_buy: bool,
+    user: [u8; 32],
+    timestamp: i64,
+    virtual_sol_reserves: u64,
+    virtual_token_reserves: u64,
+}
+
+/// Token balances of `owner` in a transaction's `preTokenBalances` or `postTokenBalances`, by
+/// mint.
+pub fn token_balances(meta: &Value, key: &str, owner: &str) -> HashMap<String, u64> {
+    meta[key]
+        .as_array()
+        .map(|balances| {
+            balances
+                .iter()
+                .filter(|balance| balance["owner"].as_str() == Some(owner))
+                .filter_map(|balance| {
+                    let amount = balance["uiTokenAmount"]["amount"].as_str()?;
+                    Some((
+                        balance["mint"].as_str()?.to_string(),
+                        u64::from_str(amount).ok()?,
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Swaps in one raw stream message: pump trades from their TradeEvent, Raydium swaps from
+/// the pool vault balances. `fallback_time` stands in for a missing block time.
+pub fn events_from_message(text: &str, fallback_time: i64) -> Result<Vec<MarketEvent>> {
+    let json: Value = serde_json::from_str(text)?;
+    let result = &json["params"]["result"];
+    let signature = result["signature"].as_str().unwrap_or_default().to_string();
+    let slot = result["slot"].as_u64().unwrap_or_default();
+    let tx = &result["transaction"];
+    let meta = &tx["meta"];
+    let timestamp = tx["blockTime"].as_i64().unwrap_or(fallback_time);
+    let trader = tx["transaction"]["message"]["accountKeys"][0]["pubkey"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let logs: Vec<&str> = meta["logMessages"]
+        .as_array()
+        .map(|logs| logs.iter().filter_map(|log| log.as_str()).collect())
+        .unwrap_or_default();
+
+    let mut events = vec![];
+    if logs.iter().any(|log| log.contains(PUMP_PROGRAM)) {
+        for log in logs.iter() {
+            let Some(data) = log.strip_prefix("Program data: ") else {
+                continue;
+            };
+            let Ok(data) = base64::decode(data) else {
+                continue;
+            };
+            if data.len() < 8 || data[..8] != PUMP_TRADE_EVENT {
+                continue;
+            }
+            let event = PumpTradeEvent::deserialize(&mut &data[8..])?;
+            events.push(MarketEvent {
+                slot,
+                timestamp: event.timestamp,
+                signature: signature.clone(),
+                trader: Pubkey::new_from_array(event.user).to_string(),
+                mint: Pubkey::new_from_array(event.mint).to_string(),
+                venue: Venue::Pump,
+                direction: if event.is_buy {
+                    SwapDirection::Buy
+                } else {
+                    SwapDirection::Sell
+                },
+                sol_amount: event.sol_amount,
+                token_amount: event.token_amount,
+                sol_reserve: event.virtual_sol_reserves,
+                token_reserve: event.virtual_token_reserves,
+            });
+        }
+    }
+
+    if logs.iter().any(|log| log.contains("ray_log")) {
+        let pre = token_balances(meta, "preTokenBalances", RAYDIUM_AUTHORITY_V4);
+        let post = token_balances(meta, "postTokenBalances", RAYDIUM_AUTHORITY_V4);
+        let wsol = spl_token::native_mint::ID.to_string();
+        // routed swaps touch several pools, only single pool swaps are taken
+        let mints: Vec<&String> = post.keys().filter(|mint| **mint != wsol).collect();
+        if let ([mint], Some(sol_after)) = (mints.as_slice(), post.get(&wsol)) {
+            let sol_before = pre.get(&wsol).copied().unwrap_or(*sol_after);
+            let token_after = post[*mint];
+            let token_before = pre.get(*mint).copied().unwrap_or(token_after);
+            events.push(MarketEvent {
+                slot,
+                timestamp,
+                signature: signature.clone(),
+                trader,
+                mint: mint.to_string(),
+                venue: Venue::Raydium,
+                // the pool paying out tokens means the trader bought
+                direction: if token_after < token_before {
+                    SwapDirection::Buy
+                } else {
+                    SwapDirection::Sell
+                },
+                sol_amount: sol_after.abs_diff(sol_before),
+                token_amount: token_after.abs_diff(token_before),
+                sol_reserve: *sol_after,
+                token_reserve: token_after,
+            });
+        }
+    }
+    Ok(events)
+}
+
+/// Fetch a confirmed transaction and run it through the same parsers as the stream.
+pub async fn events_from_transaction(
+    client: &solana_client::nonblocking::rpc_client::RpcClient,
+    signature: &str,
+) -> Result<Vec<MarketEvent>> {
+    let tx = client
+        .get_transaction_with_config(
+            &Signature::from_str(signature)?,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::JsonParsed),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            },
+        )
+        .await?;
+    // shaped like a transactionNotification
+    let mut transaction = serde_json::to_value(&tx.transaction)?;
+    transaction["blockTime"] = serde_json::json!(tx.block_time);
+    let message = serde_json::json!({
+        "params": {
+            "result": {
+                "signature": signature,
+                "slot": tx.slot,
+                "transaction": transaction,
+            }
+        }
+    });
+    events_from_message(&message.to_string(), tx.block_time.unwrap_or_default())
+}
+
+// getSignaturesForAddress returns at most 1000 signatures per call
+const SIGNATURES_PAGE: usize = 1000;
+
+/// Signatures of the successful transactions that touched `address`, newest first, up to
+/// `limit`.
+async fn signatures_of(
+    client: &solana_client::nonblocking::rpc_client::RpcClient,
+    address: &Pubkey,
+    limit: usize,
+) -> Result<Vec<String>> {
+    let mut signatures = vec![];
+    let mut before = None;
+    while signatures.len() < limit {
+        let page = client
+            .get_signatures_for_address_with_config(
+                address,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: None,
+                    limit: Some(SIGNATURES_PAGE.min(limit - signatures.len())),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .await?;
+        let Some(last) = page.last() else {
+            break;
+        };
+        before = Some(Signature::from_str(&last.signature)?);
+        let full = page.len() == SIGNATURES_PAGE;
+        signatures.extend(
+            page.into_iter()
+                .filter(|status| status.err.is_none())
+                .map(|status| status.signature),
+        );
+        if !full {
+            break;
+        }
+    }
+    Ok(signatures)
+}
+
+/// Fetch the swaps of every trader on the pump curves and Raydium pools of `mints` (the
+/// latest `limit` transactions of each) and write them to `out` as JSON lines of
+/// `MarketEvent`, ordered by slot. Returns how many events were written.
+///
+/// Stream recordings only hold the targets' own transactions, so nothing moves the reserves
+/// between a target's trade and our copy and `latency_slots` changes nothing; an export has
+/// the other traders' swaps in between.
+pub async fn export_events(
+    state: &AppState,
+    mints: &[String],
+    out: &Path,
+    limit: usize,
+) -> Result<usize> {
+    let client = &state.rpc_nonblocking_client;
+    let pump_program = Pubkey::from_str(PUMP_PROGRAM)?;
+    let wanted: HashSet<&String> = mints.iter().collect();
+    let mut seen = HashSet::new();
+    let mut events = vec![];
+    for mint in mints {
+        let mut accounts = vec![get_pda(&Pubkey::from_str(mint)?, &pump_program)?];
+        // a mint still on its curve has no pool yet
+        if let Ok((pool_id, _)) = get_pool_state_by_mint(state.rpc_client.clone(), mint).await {
+            accounts.push(pool_id);
+        }
+        for account in accounts {
+            for signature in signatures_of(client, &account, limit).await? {
+                // a migration touches both the curve and the pool
+                if !seen.insert(signature.clone()) {
+                    continue;
+                }
+                match events_from_transaction(client, &signature).await {
+                    Ok(parsed) => events.extend(
+                        parsed
+                            .into_iter()
+                            .filter(|event| wanted.contains(&event.mint)),
+                    ),
+                    Err(e) => warn!(signature = %signature, error = %e, "transaction not decoded"),
+                }
+            }
+        }
+    }
+    events.sort_by_key(|event| event.slot);
+
+    let mut writer = BufWriter::new(File::create(out)?);
+    for event in events.iter() {
+        serde_json::to_writer(&mut writer, event)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(events.len())
+}
+
+/// Load market events from stream recordings (`.gz`) or exported JSON lines of
+/// `MarketEvent`, ordered by slot.
+pub fn load_events(paths: &[String]) -> Result<Vec<MarketEvent>> {
+    let mut events = vec![];
+    for path in paths {
+        if path.ends_with(".gz") {
+            for recorded in read_recording(Path::new(path))? {
+                match events_from_message(&recorded.message, recorded.received_at / 1000) {
+                    Ok(parsed) => events.extend(parsed),
+                    Err(e) => warn!(path = %path, error = %e, "recorded message not decoded"),
+                }
+            }
+        } else {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    events.push(serde_json::from_str(&line)?);
+                }
+            }
+        }
+    }
+    events.sort_by_key(|event| event.slot);
+    Ok(events)
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktestConfig {
+    /// Slots between the target's trade and our copy landing. Only other traders' swaps in
+    /// those slots move the price, which target-only stream recordings do not have.
+    pub latency_slots: u64,
+    /// Share of the target's SOL amount copied on a buy, in percent
+    pub size_pct: u64,
+    /// Fixed buy size in lamports, overrides `size_pct`
+    pub fixed_size: Option<u64>,
+    pub starting_balance: u64,
+    /// Signature fee, priority fee and tip paid on every trade, in lamports
+    pub fee_per_trade: u64,
+    /// Copy the target's sells, scaled to the share of its holding sold
+    pub follow_sells: bool,
+    pub risk: RiskConfig,
+    /// Applied when `exit.enabled`
+    pub exit: ExitConfig,
+}
+
+impl BacktestConfig {
+    pub fn from_env() -> Self {
+        let sol = |key: &str, default: f64| (env_or(key, default) * LAMPORTS_PER_SOL) as u64;
+        let file = Config::read(&CONFIG_PATH).ok();
+        Self {
+            latency_slots: env_or("BACKTEST_LATENCY_SLOTS", 1),
+            size_pct: env_or("BACKTEST_SIZE_PCT", 100),
+            fixed_size: Some(sol("BACKTEST_FIXED_SOL", 0.0)).filter(|size| *size > 0),
+            starting_balance: sol("BACKTEST_STARTING_SOL", 10.0),
+            fee_per_trade: sol("BACKTEST_FEE_SOL", 0.0002),
+            follow_sells: env_or("BACKTEST_FOLLOW_SELLS", true),
+            // risk limits and exit rules only, a backtest needs no endpoints or wallet
+            risk: file
+                .as_ref()
+                .map_or_else(RiskConfig::from_env, |config| config.risk.clone()),
+            exit: file
+                .as_ref()
+                .map_or_else(ExitConfig::from_env, |config| config.exit.clone()),
+        }
+    }
+}
+
+/// Pool state replayed from market events
+#[derive(Debug, Clone)]
+struct Market {
+    venue: Venue,
+    sol_reserve: u64,
+    token_reserve: u64,
+}
+
+impl Market {
+    fn curve(&self) -> PumpInfo {
+        PumpInfo {
+            virtual_sol_reserves: self.sol_reserve,
+            virtual_token_reserves: self.token_reserve,
+            ..Default::default()
+        }
+    }
+
+    fn buy(&self, lamports: u64) -> u64 {
+        match self.venue {
+            Venue::Pump => self.curve().buy_quote(lamports),
+            Venue::Raydium => amm_quote(self.sol_reserve, self.token_reserve, lamports),
+        }
+    }
+
+    fn sell(&self, tokens: u64) -> u64 {
+        match self.venue {
+            Venue::Pump => self.curve().sell_quote(tokens),
+            Venue::Raydium => amm_quote(self.token_reserve, self.sol_reserve, tokens),
+        }
+    }
+
+    /// Move the reserves by a trade of our own.
+    fn apply(&mut self, direction: SwapDirection, lamports: u64, tokens: u64) {
+        match direction {
+            SwapDirection::Buy => {
+                self.sol_reserve += lamports;
+                self.token_reserve = self.token_reserve.saturating_sub(tokens);
+            }
+            SwapDirection::Sell => {
+                self.sol_reserve = self.sol_reserve.saturating_sub(lamports);
+                self.token_reserve += tokens;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestTrade {
+    pub slot: u64,
+    pub timestamp: i64,
+    pub mint: String,
+    pub venue: Venue,
+    pub direction: SwapDirection,
+    pub reason: String,
+    /// Lamports spent on a buy or received from a sell, fees included
+    pub lamports: u64,
+    pub token_amount: u64,
+    pub realized: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BacktestSummary {
+    pub target_trades: usize,
+    pub buys: usize,
+    pub sells: usize,
+    /// Target trades not copied: risk limits, no known pool, or nothing to sell
+    pub skipped: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub win_rate: f64,
+    pub realized: i64,
+    /// Holdings left at the end, marked at the last known reserves
+    pub unrealized: i64,
+    pub fees: u64,
+    /// Largest drop of equity from its peak, in lamports and percent
+    pub max_drawdown: u64,
+    pub max_drawdown_pct: f64,
+    pub ending_equity: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestResult {
+    pub trades: Vec<BacktestTrade>,
+    pub summary: BacktestSummary,
+}
+
+struct Holding {
+    position: Position,
+    exit: ExitState,
+}
+
+struct Backtest<'a> {
+    config: &'a BacktestConfig,
+    cash: u64,
+    markets: HashMap<String, Market>,
+    holdings: HashMap<String, Holding>,
+    /// Tokens the target holds per mint, to scale its sells
+    target_holdings: HashMap<String, u64>,
+    /// Copies waiting to land: slot, target trade, share of the holding to sell
+    pending: VecDeque<(u64, MarketEvent, u64)>,
+    trades: Vec<BacktestTrade>,
+    summary: BacktestSummary,
+    equity_peak: u64,
+}
+
+impl Backtest<'_> {
+    fn equity(&self) -> u64 {
+        self.cash
+            + self
+                .holdings
+                .values()
+                .filter_map(|holding| {
+                    let market = self.markets.get(&holding.position.mint)?;
+                    Some(market.sell(holding.position.token_amount))
+                })
+                .sum::<u64>()
+    }
+
+    fn track_drawdown(&mut self) {
+        let equity = self.equity();
+        self.equity_peak = self.equity_peak.max(equity);
+        let drawdown = self.equity_peak - equity;
+        if drawdown > self.summary.max_drawdown {
+            self.summary.max_drawdown = drawdown;
+            self.summary.max_drawdown_pct = drawdown as f64 * 100.0 / self.equity_peak as f64;
+        }
+    }
+
+    fn buy(&mut self, event: &MarketEvent, slot: u64) {
+        let config = self.config;
+        let requested = config
+            .fixed_size
+            .unwrap_or(event.sol_amount * config.size_pct / 100);
+        let exposure = self
+            .holdings
+            .get(&event.mint)
+            .map(|holding| holding.position.cost_lamports)
+            .unwrap_or(0);
+        let open_limit_hit = !self.holdings.contains_key(&event.mint)
+            && self.holdings.len() >= config.risk.max_open_positions;
+        let amount = requested
+            .min(config.risk.max_sol_per_trade)
+            .min(config.risk.max_exposure_per_mint.saturating_sub(exposure))
+            .min(
+                self.cash
+                    .saturating_sub(config.risk.min_sol_reserve)
+                    .saturating_sub(config.fee_per_trade),
+            );
+        let Some(market) = self.markets.get_mut(&event.mint) else {
+            self.summary.skipped += 1;
+            return;
+        };
+        if amount == 0 || open_limit_hit {
+            self.summary.skipped += 1;
+            return;
+        }
+        let tokens = market.buy(amount);
+        market.apply(SwapDirection::Buy, amount, tokens);
+        let lamports = amount + config.fee_per_trade;
+        self.cash -= lamports;
+        self.summary.fees += config.fee_per_trade;
+
+        let holding = self
+            .holdings
+            .entry(event.mint.clone())
+            .or_insert_with(|| Holding {
+                position: Position {
+                    mint: event.mint.clone(),
+                    target: event.trader.clone(),
+                    venue: event.venue,
+                    pool_id: None,
+                    token_amount: 0,
+                    cost_lamports: 0,
+                    opened_at: event.timestamp,
+                },
+                exit: ExitState::default(),
+            });
+        holding.position.token_amount += tokens;
+        holding.position.cost_lamports += lamports;
+        self.summary.buys += 1;
+        self.trades.push(BacktestTrade {
+            slot,
+            timestamp: event.timestamp,
+            mint: event.mint.clone(),
+            venue: market.venue,
+            direction: SwapDirection::Buy,
+            reason: "copy".to_string(),
+            lamports,
+            token_amount: tokens,
+            realized: None,
+        });
+        self.track_drawdown();
+    }
+
+    /// Sell `pct` percent of the holding at the current reserves, realizing against its
+    /// average cost.
+    fn sell(&mut self, mint: &str, pct: u64, reason: String, slot: u64, timestamp: i64) -> bool {
+        let fee = self.config.fee_per_trade;
+        let (Some(holding), Some(market)) =
+            (self.holdings.get_mut(mint), self.markets.get_mut(mint))
+        else {
+            return false;
+        };
+        let position = &mut holding.position;
+        let tokens = if pct >= 100 {
+            position.token_amount
+        } else {
+            position.token_amount * pct / 100
+        };
+        if tokens == 0 {
+            return false;
+        }
+        let out = market.sell(tokens);
+        market.apply(SwapDirection::Sell, out, tokens);
+        let lamports = out.saturating_sub(fee);
+        let cost = (position.cost_lamports as u128 * tokens as u128 / position.token_amount as u128)
+            as u64;
+        let realized = lamports as i64 - cost as i64;
+        position.token_amount -= tokens;
+        position.cost_lamports -= cost;
+        let venue = market.venue;
+        if position.token_amount == 0 {
+            self.holdings.remove(mint);
+        }
+        self.cash += lamports;
+        self.summary.fees += fee;
+        self.summary.sells += 1;
+        self.summary.realized += realized;
+        if realized > 0 {
+            self.summary.wins += 1;
+        } else {
+            self.summary.losses += 1;
+        }
+        self.trades.push(BacktestTrade {
+            slot,
+            timestamp,
+            mint: mint.to_string(),
+            venue,
+            direction: SwapDirection::Sell,
+            reason,
+            lamports,
+            token_amount: tokens,
+            realized: Some(realized),
+        });
+        self.track_drawdown();
+        true
+    }
+
+    /// Land the copies due by `slot` against the reserves as they are now.
+    fn fill_pending(&mut self, slot: u64) {
+        while self
+            .pending
+            .front()
+            .is_some_and(|(fill_slot, _, _)| *fill_slot <= slot)
+        {
+            let Some((fill_slot, event, pct)) = self.pending.pop_front() else {
+                break;
+            };
+            match event.direction {
+                SwapDirection::Buy => self.buy(&event, fill_slot),
+                SwapDirection::Sell => {
+                    let reason = format!("copy sell of {}%", pct);
+                    if !self.sell(&event.mint, pct, reason, fill_slot, event.timestamp) {
+                        self.summary.skipped += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_exit(&mut self, mint: &str, slot: u64, timestamp: i64) {
+        if !self.config.exit.enabled {
+            return;
+        }
+        let (Some(holding), Some(market)) = (self.holdings.get_mut(mint), self.markets.get(mint))
+        else {
+            return;
+        };
+        let value = market.sell(holding.position.token_amount);
+        if let Some(signal) = evaluate(
+            &self.config.exit,
+            &holding.position,
+            value,
+            timestamp,
+            &mut holding.exit,
+        ) {
+            let sold = self.sell(
+                mint,
+                signal.sell_pct,
+                signal.reason.clone(),
+                slot,
+                timestamp,
+            );
+            if sold {
+                if let Some(holding) = self.holdings.get_mut(mint) {
+                    holding.exit.sold(&signal);
+                }
+            }
+        }
+    }
+}
+
+/// Replay market events as if every trade of `target` had been copied under `config`.
+pub fn run(events: &[MarketEvent], target: &str, config: &BacktestConfig) -> BacktestResult {
+    let mut backtest = Backtest {
+        config,
+        cash: config.starting_balance,
+        markets: HashMap::new(),
+        holdings: HashMap::new(),
+        target_holdings: HashMap::new(),
+        pending: VecDeque::new(),
+        trades: vec![],
+        summary: BacktestSummary::default(),
+        equity_peak: config.starting_balance,
+    };
+
+    for event in events {
+        // copies landing before this slot see the reserves left by earlier slots
+        backtest.fill_pending(event.slot.saturating_sub(1));
+        backtest.markets.insert(
+            event.mint.clone(),
+            Market {
+                venue: event.venue,
+                sol_reserve: event.sol_reserve,
+                token_reserve: event.token_reserve,
+            },
+        );
+        if event.trader == target {
+            backtest.summary.target_trades += 1;
+            let held = backtest
+                .target_holdings
+                .entry(event.mint.clone())
+                .or_insert(0);
+            // a sell of tokens bought before the recording started sells everything
+            let sell_pct = if *held == 0 {
+                100
+            } else {
+                (event.token_amount as u128 * 100 / *held as u128).min(100) as u64
+            };
+            match event.direction {
+                SwapDirection::Buy => *held += event.token_amount,
+                SwapDirection::Sell => *held = held.saturating_sub(event.token_amount),
+            }
+            if event.direction == SwapDirection::Buy || config.follow_sells {
+                backtest.pending.push_back((
+                    event.slot + config.latency_slots,
+                    event.clone(),
+                    sell_pct,
+                ));
+            }
+            // without latency the copy lands right behind the target
+            backtest.fill_pending(event.slot);
+        }
+        backtest.check_exit(&event.mint, event.slot, event.timestamp);
+    }
+    backtest.fill_pending(u64::MAX);
+
+    let mut summary = backtest.summary.clone();
+    summary.ending_equity = backtest.equity();
+    summary.unrealized = backtest
+        .holdings
+        .values()
+        .filter_map(|holding| {
+            let market = backtest.markets.get(&holding.position.mint)?;
+            Some(
+                market.sell(holding.position.token_amount) as i64
+                    - holding.position.cost_lamports as i64,
+            )
+        })
+        .sum();
+    let closed = summary.wins + summary.losses;
+    if closed > 0 {
+        summary.win_rate = summary.wins as f64 * 100.0 / closed as f64;
+    }
+    BacktestResult {
+        trades: backtest.trades,
+        summary,
+    }
+}
+
+fn sol(lamports: i64) -> String {
+    format!("{:.4}", lamports as f64 / LAMPORTS_PER_SOL)
+}
+
+impl BacktestResult {
+    pub fn print(&self) {
+        for trade in self.trades.iter() {
+            println!(
+                "{:>10} {:<44} {:<7} {:<4} {:>12} SOL {:>16} tokens {:>10} {}",
+                trade.slot,
+                trade.mint,
+                trade.venue,
+                if trade.direction == SwapDirection::Buy {
+                    "buy"
+                } else {
+                    "sell"
+                },
+                sol(trade.lamports as i64),
+                trade.token_amount,
+                trade.realized.map(sol).unwrap_or_default(),
+                trade.reason
+            );
+        }
+        let summary = &self.summary;
+        println!(
+            "\n{} target trades, {} buys, {} sells, {} skipped",
+            summary.target_trades, summary.buys, summary.sells, summary.skipped
+        );
+        println!(
+            "PnL {} SOL (realized {}, unrealized {}), fees {} SOL",
+            sol(summary.realized + summary.unrealized),
+            sol(summary.realized),
+            sol(summary.unrealized),
+            sol(summary.fees as i64)
+        );
+        println!(
+            "Win rate {:.1}% ({} / {}), max drawdown {} SOL ({:.1}%), ending equity {} SOL",
+            summary.win_rate,
+            summary.wins,
+            summary.wins + summary.losses,
+            sol(summary.max_drawdown as i64),
+            summary.max_drawdown_pct,
+            sol(summary.ending_equity as i64)
+        );
+    }
+}
+
+/// Backtest `target` over the events in `paths` with the configuration from the environment.
+pub fn backtest(target: &str, paths: &[String]) -> Result<BacktestResult> {
+    if paths.is_empty() {
+        return Err(anyhow!("no recordings or exports to backtest"));
+    }
+    let events = load_events(paths)?;
+    let config = BacktestConfig::from_env();
+    if config.latency_slots > 0 && events.iter().all(|event| event.trader == target) {
+        println!(
+            "backtest: only the target's own trades were loaded, so latency has no effect; \
+             export every swap of its mints with `backtest-export`"
+        );
+    }
+    Ok(run(&events, target, &config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "target";
+    const SOL: u64 = 1_000_000_000;
+
+    fn config() -> BacktestConfig {
+        BacktestConfig {
+            latency_slots: 0,
+            size_pct: 100,
+            fixed_size: None,
+            starting_balance: 10 * SOL,
+            fee_per_trade: 0,
+            follow_sells: true,
+            risk: RiskConfig::default(),
+            exit: ExitConfig::default(),
+        }
+    }
+
+    // a Raydium swap leaving the pool at `sol_reserve` / `token_reserve`
+    fn swap(
+        slot: u64,
+        trader: &str,
+        direction: SwapDirection,
+        token_amount: u64,
+        sol_reserve: u64,
+        token_reserve: u64,
+    ) -> MarketEvent {
+        MarketEvent {
+            slot,
+            timestamp: slot as i64,
+            signature: format!("sig-{}", slot),
+            trader: trader.to_string(),
+            mint: "mint".to_string(),
+            venue: Venue::Raydium,
+            direction,
+            sol_amount: SOL / 2,
+            token_amount,
+            sol_reserve,
+            token_reserve,
+        }
+    }
+
+    fn bought(result: &BacktestResult) -> u64 {
+        result.trades[0].token_amount
+    }
+
+    #[test]
+    fn copies_buys_and_scales_sells_to_the_target() {
+        let events = [
+            swap(
+                10,
+                TARGET,
+                SwapDirection::Buy,
+                1_000,
+                100 * SOL,
+                1_000 * SOL,
+            ),
+            swap(20, TARGET, SwapDirection::Sell, 250, 100 * SOL, 1_000 * SOL),
+        ];
+        let result = run(&events, TARGET, &config());
+        assert_eq!(result.summary.target_trades, 2);
+        assert_eq!(result.summary.buys, 1);
+        assert_eq!(result.summary.sells, 1);
+        assert_eq!(result.trades[1].reason, "copy sell of 25%");
+        assert_eq!(result.trades[1].token_amount, bought(&result) * 25 / 100);
+    }
+
+    #[test]
+    fn latency_fills_after_the_swaps_in_between() {
+        let events = [
+            swap(
+                10,
+                TARGET,
+                SwapDirection::Buy,
+                1_000,
+                100 * SOL,
+                1_000 * SOL,
+            ),
+            // another trader buys hard before our copy lands
+            swap(11, "other", SwapDirection::Buy, 1_000, 150 * SOL, 667 * SOL),
+        ];
+        let instant = run(&events, TARGET, &config());
+        let late = run(
+            &events,
+            TARGET,
+            &BacktestConfig {
+                latency_slots: 2,
+                ..config()
+            },
+        );
+        assert_eq!(instant.summary.target_trades, 1);
+        assert!(bought(&late) < bought(&instant));
+    }
+
+    #[test]
+    fn latency_changes_nothing_on_target_only_events() {
+        let events = [swap(
+            10,
+            TARGET,
+            SwapDirection::Buy,
+            1_000,
+            100 * SOL,
+            1_000 * SOL,
+        )];
+        let instant = run(&events, TARGET, &config());
+        let late = run(
+            &events,
+            TARGET,
+            &BacktestConfig {
+                latency_slots: 5,
+                ..config()
+            },
+        );
+        assert_eq!(bought(&late), bought(&instant));
+    }
+
+    #[test]
+    fn sells_are_skipped_without_a_holding_or_when_not_followed() {
+        let sell = swap(
+            10,
+            TARGET,
+            SwapDirection::Sell,
+            1_000,
+            100 * SOL,
+            1_000 * SOL,
+        );
+        let result = run(&[sell.clone()], TARGET, &config());
+        assert_eq!(result.summary.skipped, 1);
+        assert!(result.trades.is_empty());
+
+        let events = [
+            swap(5, TARGET, SwapDirection::Buy, 1_000, 100 * SOL, 1_000 * SOL),
+            sell,
+        ];
+        let config = BacktestConfig {
+            follow_sells: false,
+            ..config()
+        };
+        let result = run(&events, TARGET, &config);
+        assert_eq!(result.summary.sells, 0);
+        assert_eq!(
+            result.summary.unrealized,
+            result.summary.ending_equity as i64 - 10 * SOL as i64
+        );
+    }
+
+    #[test]
+    fn buys_respect_the_risk_limits() {
+        let buy = MarketEvent {
+            sol_amount: 2 * SOL,
+            ..swap(
+                10,
+                TARGET,
+                SwapDirection::Buy,
+                1_000,
+                100 * SOL,
+                1_000 * SOL,
+            )
+        };
+        let result = run(&[buy.clone()], TARGET, &config());
+        // two SOL asked for, capped at max_sol_per_trade
+        assert_eq!(
+            result.trades[0].lamports,
+            RiskConfig::default().max_sol_per_trade
+        );
+
+        let other_mint = MarketEvent {
+            mint: "other".to_string(),
+            slot: 11,
+            ..buy.clone()
+        };
+        let mut config = config();
+        config.risk.max_open_positions = 1;
+        let result = run(&[buy, other_mint], TARGET, &config);
+        assert_eq!(result.summary.buys, 1);
+        assert_eq!(result.summary.skipped, 1);
+    }
+
+    #[test]
+    fn fees_are_charged_on_every_trade() {
+        let events = [
+            swap(
+                10,
+                TARGET,
+                SwapDirection::Buy,
+                1_000,
+                100 * SOL,
+                1_000 * SOL,
+            ),
+            swap(
+                20,
+                TARGET,
+                SwapDirection::Sell,
+                1_000,
+                100 * SOL,
+                1_000 * SOL,
+            ),
+        ];
+        let config = BacktestConfig {
+            fee_per_trade: 1_000,
+            ..config()
+        };
+        let result = run(&events, TARGET, &config);
+        assert_eq!(result.summary.fees, 2_000);
+        assert_eq!(result.summary.losses, 1);
+        assert!(result.summary.realized < 0);
+    }
+
+    // a transactionNotification as the stream sends it, and a replay reads it back
+    fn notification(logs: Vec<String>, pre: Value, post: Value) -> String {
+        serde_json::json!({
+            "params": {
+                "result": {
+                    "signature": "sig",
+                    "slot": 42,
+                    "transaction": {
+                        "blockTime": 1_700_000_000,
+                        "transaction": { "message": { "accountKeys": [{ "pubkey": TARGET }] } },
+                        "meta": {
+                            "logMessages": logs,
+                            "preTokenBalances": pre,
+                            "postTokenBalances": post,
+                        },
+                    },
+                },
+            },
+        })
+        .to_string()
+    }
+
+    fn vaults(mint: &str, tokens: u64, lamports: u64) -> Value {
+        let balance = |mint: String, amount: u64| {
+            serde_json::json!({
+                "mint": mint,
+                "owner": RAYDIUM_AUTHORITY_V4,
+                "uiTokenAmount": { "amount": amount.to_string() },
+            })
+        };
+        serde_json::json!([
+            balance(mint.to_string(), tokens),
+            balance(spl_token::native_mint::ID.to_string(), lamports),
+        ])
+    }
+
+    #[test]
+    fn pump_trades_are_decoded_from_their_event() {
+        let mint = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let mut data = PUMP_TRADE_EVENT.to_vec();
+        data.extend(mint.to_bytes());
+        data.extend(SOL.to_le_bytes());
+        data.extend(5_000u64.to_le_bytes());
+        data.push(0);
+        data.extend(user.to_bytes());
+        data.extend(1_700_000_000i64.to_le_bytes());
+        data.extend((30 * SOL).to_le_bytes());
+        data.extend(900_000u64.to_le_bytes());
+        let logs = vec![
+            format!("Program {} invoke [1]", PUMP_PROGRAM),
+            format!("Program data: {}", base64::encode(&data)),
+        ];
+        let events = events_from_message(&notification(logs, Value::Null, Value::Null), 0).unwrap();
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.venue, Venue::Pump);
+        assert_eq!(event.direction, SwapDirection::Sell);
+        assert_eq!(event.mint, mint.to_string());
+        assert_eq!(event.trader, user.to_string());
+        assert_eq!((event.sol_amount, event.token_amount), (SOL, 5_000));
+        assert_eq!(
+            (event.sol_reserve, event.token_reserve),
+            (30 * SOL, 900_000)
+        );
+        assert_eq!(event.slot, 42);
+    }
+
+    #[test]
+    fn raydium_swaps_are_decoded_from_the_vault_balances() {
+        let logs = vec!["Program log: ray_log: AAAA".to_string()];
+        let message = notification(
+            logs,
+            vaults("mint", 1_000, 10 * SOL),
+            vaults("mint", 900, 11 * SOL),
+        );
+        let events = events_from_message(&message, 0).unwrap();
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.venue, Venue::Raydium);
+        // the pool paid out tokens, so the target bought
+        assert_eq!(event.direction, SwapDirection::Buy);
+        assert_eq!(event.trader, TARGET);
+        assert_eq!((event.sol_amount, event.token_amount), (SOL, 100));
+        assert_eq!((event.sol_reserve, event.token_reserve), (11 * SOL, 900));
+        assert_eq!(event.timestamp, 1_700_000_000);
+    }
+
+    #[test]
+    fn routed_and_unrelated_transactions_decode_to_nothing() {
+        let logs = vec!["Program log: ray_log: AAAA".to_string()];
+        let mut post = vaults("mint", 900, 11 * SOL);
+        post.as_array_mut().unwrap().push(serde_json::json!({
+            "mint": "other",
+            "owner": RAYDIUM_AUTHORITY_V4,
+            "uiTokenAmount": { "amount": "5" },
+        }));
+        let message = notification(logs, vaults("mint", 1_000, 10 * SOL), post);
+        assert!(events_from_message(&message, 0).unwrap().is_empty());
+
+        let logs = vec!["Program 11111111111111111111111111111111 invoke [1]".to_string()];
+        let message = notification(logs, Value::Null, Value::Null);
+        assert!(events_from_message(&message, 0).unwrap().is_empty());
+        assert!(events_from_message("not json", 0).is_err());
+    }
+}
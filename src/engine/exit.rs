@@ -0,0 +1,721 @@
+use std::{
+    collections::HashSet,
+    env,
+    str::FromStr,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use borsh::BorshDeserialize;
+use chrono::Utc;
+use futures_util::{stream, StreamExt};
+use jito_json_rpc_client::jsonrpc_client::rpc_client::RpcClient as JitoRpcClient;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{nonblocking::pubsub_client::PubsubClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{
+    account::Account, commitment_config::CommitmentConfig, program_pack::Pack, pubkey::Pubkey,
+    signer::Signer,
+};
+use tokio::{
+    sync::Mutex,
+    time::{interval, Instant},
+};
+use tracing::warn;
+
+use crate::{
+    common::{
+        config,
+        utils::{env_checked, log_message, AppState},
+    },
+    dex::{
+        pump::{get_pump_info, BondingCurveAccount, PumpInfo},
+        raydium::{amm_quote, get_pool_state},
+    },
+    engine::{
+        position::{self, Position, Venue},
+        presign::{discard_exit, holding, sol_balance},
+        reconcile::{MANAGE_ORPHANS, ORPHAN_TARGET},
+        risk,
+        swap::{pump_swap, raydium_swap, SwapDirection, SwapInType},
+        wallets,
+    },
+    services::{
+        control,
+        trade_log::{self, Event},
+    },
+};
+
+// wait before retrying a failed exit sell, doubled on every failure up to `RETRY_MAX`
+const RETRY_MIN: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+
+// mints with a running exit watcher
+static WATCHING: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// One rung of the take-profit ladder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TakeProfit {
+    /// Position value over cost that triggers the rung, e.g. 2.0 for a 2x
+    pub multiple: f64,
+    /// Share of the remaining holding to sell, in percent
+    pub sell_pct: u64,
+}
+
+// The config file gives the ladder like `EXIT_TAKE_PROFIT` and the holding time in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExitConfig {
+    pub enabled: bool,
+    /// Take-profit ladder, ascending by multiple
+    #[serde(with = "ladder")]
+    pub take_profit: Vec<TakeProfit>,
+    /// Loss from cost, in percent, that sells everything
+    pub stop_loss_pct: Option<f64>,
+    /// Drop from the peak value, in percent, that sells everything
+    pub trailing_stop_pct: Option<f64>,
+    /// Holding time after which the position is sold
+    #[serde(rename = "max_hold_secs", with = "secs")]
+    pub max_hold: Option<Duration>,
+    /// Slippage tolerance of exit sells in basis points
+    #[serde(rename = "slippage_bps")]
+    pub slippage: u64,
+}
+
+impl Default for ExitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            take_profit: vec![],
+            stop_loss_pct: None,
+            trailing_stop_pct: None,
+            max_hold: None,
+            slippage: 300,
+        }
+    }
+}
+
+mod ladder {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        rungs: &[TakeProfit],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let ladder: Vec<String> = rungs
+            .iter()
+            .map(|rung| format!("{}:{}", rung.multiple, rung.sell_pct))
+            .collect();
+        serializer.serialize_str(&ladder.join(","))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<TakeProfit>, D::Error> {
+        Ok(parse_ladder(&String::deserialize(deserializer)?))
+    }
+}
+
+mod secs {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.map(|d| d.as_secs()).unwrap_or(0))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Some(u64::deserialize(deserializer)?)
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs))
+    }
+}
+
+impl ExitConfig {
+    pub fn from_env() -> Self {
+        Self::default().env_overrides(&mut vec![])
+    }
+
+    /// Replace the settings given in the environment.
+    pub fn env_overrides(self, errors: &mut Vec<String>) -> Self {
+        let mut positive = |key: &str, value: Option<f64>| {
+            Some(env_checked(key, value.unwrap_or(0.0), errors)).filter(|v| *v > 0.0)
+        };
+        let stop_loss_pct = positive("EXIT_STOP_LOSS_PCT", self.stop_loss_pct);
+        let trailing_stop_pct = positive("EXIT_TRAILING_STOP_PCT", self.trailing_stop_pct);
+        let take_profit = match env::var("EXIT_TAKE_PROFIT") {
+            Ok(ladder) => parse_ladder(&ladder),
+            Err(_) => self.take_profit,
+        };
+        let max_hold = self.max_hold.map(|d| d.as_secs()).unwrap_or(0);
+        Self {
+            enabled: env_checked("EXIT_ENGINE", self.enabled, errors),
+            take_profit,
+            stop_loss_pct,
+            trailing_stop_pct,
+            max_hold: Some(env_checked("EXIT_MAX_HOLD_SECS", max_hold, errors))
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            slippage: env_checked("EXIT_SLIPPAGE", self.slippage, errors),
+        }
+    }
+}
+
+/// Parse a ladder such as `2:50,3:50,5:100` (multiple:percent to sell).
+pub fn parse_ladder(ladder: &str) -> Vec<TakeProfit> {
+    let mut rungs: Vec<TakeProfit> = ladder
+        .split(',')
+        .filter_map(|rung| {
+            let (multiple, sell_pct) = rung.trim().split_once(':')?;
+            Some(TakeProfit {
+                multiple: f64::from_str(multiple.trim()).ok()?,
+                sell_pct: u64::from_str(sell_pct.trim()).ok()?.min(100),
+            })
+        })
+        .collect();
+    rungs.sort_by(|a, b| a.multiple.total_cmp(&b.multiple));
+    rungs
+}
+
+/// Why a position is being sold and how much of it
+#[derive(Debug, Clone)]
+pub struct ExitSignal {
+    pub reason: String,
+    pub sell_pct: u64,
+    /// Sells the next take-profit rung
+    pub take_profit: bool,
+}
+
+/// Exit bookkeeping of a watched position
+#[derive(Debug, Clone, Default)]
+pub struct ExitState {
+    /// Highest value of a single token seen, in lamports, so partial sells don't move it
+    pub peak: f64,
+    /// Take-profit rungs already sold
+    pub rungs_done: usize,
+    /// Exit sells that failed in a row
+    pub failures: u32,
+    /// No sell is sent before this after a failure
+    pub retry_at: Option<Instant>,
+}
+
+impl ExitState {
+    /// Record a signal whose sell landed, a take-profit rung is only done once it is sold.
+    pub fn sold(&mut self, signal: &ExitSignal) {
+        if signal.take_profit {
+            self.rungs_done += 1;
+        }
+        self.failures = 0;
+        self.retry_at = None;
+    }
+
+    /// Record a sell that failed, holding off the next one for longer after each failure.
+    pub fn failed(&mut self, now: Instant) -> Duration {
+        let wait = RETRY_MIN
+            .saturating_mul(1 << self.failures.min(16))
+            .min(RETRY_MAX);
+        self.failures += 1;
+        self.retry_at = Some(now + wait);
+        wait
+    }
+
+    /// Whether a sell may be sent now.
+    pub fn may_sell(&self, now: Instant) -> bool {
+        self.retry_at.map_or(true, |at| now >= at)
+    }
+}
+
+/// Decide whether the position should be sold at `value` (lamports the whole holding would
+/// fetch right now).
+pub fn evaluate(
+    config: &ExitConfig,
+    position: &Position,
+    value: u64,
+    now: i64,
+    state: &mut ExitState,
+) -> Option<ExitSignal> {
+    let per_token = value as f64 / position.token_amount.max(1) as f64;
+    state.peak = state.peak.max(per_token);
+    let cost = position.cost_lamports.max(1) as f64;
+    let multiple = value as f64 / cost;
+
+    if let Some(max_hold) = config.max_hold {
+        if now - position.opened_at >= max_hold.as_secs() as i64 {
+            return Some(ExitSignal {
+                reason: format!("held longer than {}s", max_hold.as_secs()),
+                sell_pct: 100,
+                take_profit: false,
+            });
+        }
+    }
+    if let Some(stop_loss) = config.stop_loss_pct {
+        if multiple <= 1.0 - stop_loss / 100.0 {
+            return Some(ExitSignal {
+                reason: format!("stop loss at {:.2}x", multiple),
+                sell_pct: 100,
+                take_profit: false,
+            });
+        }
+    }
+    if let Some(trailing) = config.trailing_stop_pct {
+        if per_token <= state.peak * (1.0 - trailing / 100.0) {
+            return Some(ExitSignal {
+                reason: format!(
+                    "trailing stop, {:.1}% off the peak",
+                    100.0 - per_token * 100.0 / state.peak
+                ),
+                sell_pct: 100,
+                take_profit: false,
+            });
+        }
+    }
+    if let Some(rung) = config.take_profit.get(state.rungs_done) {
+        if multiple >= rung.multiple {
+            return Some(ExitSignal {
+                reason: format!("take profit at {:.2}x", multiple),
+                sell_pct: rung.sell_pct,
+                take_profit: true,
+            });
+        }
+    }
+    None
+}
+
+/// Local view of the pool the position trades on
+#[derive(Debug, Clone)]
+pub(crate) enum Quote {
+    Pump(PumpInfo),
+    Raydium {
+        token_vault: Pubkey,
+        sol_vault: Pubkey,
+        token_reserve: u64,
+        sol_reserve: u64,
+    },
+}
+
+impl Quote {
+    /// Lamports `tokens` would fetch.
+    pub(crate) fn value(&self, tokens: u64) -> u64 {
+        match self {
+            Quote::Pump(info) => info.sell_quote(tokens),
+            Quote::Raydium {
+                token_reserve,
+                sol_reserve,
+                ..
+            } => amm_quote(*token_reserve, *sol_reserve, tokens),
+        }
+    }
+
+    /// Tokens `lamports` would buy.
+    pub(crate) fn tokens_for(&self, lamports: u64) -> u64 {
+        match self {
+            Quote::Pump(info) => info.buy_quote(lamports),
+            Quote::Raydium {
+                token_reserve,
+                sol_reserve,
+                ..
+            } => amm_quote(*sol_reserve, *token_reserve, lamports),
+        }
+    }
+
+    /// Accounts whose updates move the price.
+    fn accounts(&self) -> Result<Vec<Pubkey>> {
+        match self {
+            Quote::Pump(info) => Ok(vec![Pubkey::from_str(&info.bonding_curve)?]),
+            Quote::Raydium {
+                token_vault,
+                sol_vault,
+                ..
+            } => Ok(vec![*token_vault, *sol_vault]),
+        }
+    }
+
+    fn update(&mut self, pubkey: &Pubkey, account: &Account) {
+        match self {
+            Quote::Pump(info) => {
+                if let Ok(curve) = BondingCurveAccount::deserialize(&mut account.data.as_slice()) {
+                    info.virtual_sol_reserves = curve.virtual_sol_reserves;
+                    info.virtual_token_reserves = curve.virtual_token_reserves;
+                    info.complete = curve.complete;
+                }
+            }
+            // vault balances, the pool's pending pnl is small enough to ignore here
+            Quote::Raydium {
+                token_vault,
+                sol_vault,
+                token_reserve,
+                sol_reserve,
+            } => {
+                let Ok(vault) = spl_token::state::Account::unpack(&account.data) else {
+                    return;
+                };
+                if pubkey == token_vault {
+                    *token_reserve = vault.amount;
+                } else if pubkey == sol_vault {
+                    *sol_reserve = vault.amount;
+                }
+            }
+        }
+    }
+}
+
+/// Lamports the position would fetch if sold now, from a fresh local quote.
+pub async fn mark(state: &AppState, position: &Position) -> Result<u64> {
+    Ok(load_quote(state, position)
+        .await?
+        .value(position.token_amount))
+}
+
+pub(crate) async fn load_quote(state: &AppState, position: &Position) -> Result<Quote> {
+    match position.venue {
+        Venue::Pump => Ok(Quote::Pump(
+            get_pump_info(state.rpc_client.clone(), &position.mint).await?,
+        )),
+        Venue::Raydium => {
+            let (_, pool) =
+                get_pool_state(state.rpc_client.clone(), position.pool_id.as_deref(), None).await?;
+            let mint = Pubkey::from_str(&position.mint)?;
+            let (token_vault, sol_vault) = if pool.coin_vault_mint == mint {
+                (pool.coin_vault, pool.pc_vault)
+            } else {
+                (pool.pc_vault, pool.coin_vault)
+            };
+            let client = &state.rpc_nonblocking_client;
+            let token_reserve = client.get_token_account_balance(&token_vault).await?;
+            let sol_reserve = client.get_token_account_balance(&sol_vault).await?;
+            Ok(Quote::Raydium {
+                token_vault,
+                sol_vault,
+                token_reserve: u64::from_str(&token_reserve.amount)?,
+                sol_reserve: u64::from_str(&sol_reserve.amount)?,
+            })
+        }
+    }
+}
+
+/// Start watching an open position, unless it is already watched or is an orphan the exit
+/// engine is not allowed to manage.
+pub async fn watch(state: AppState, jito_client: Arc<JitoRpcClient>, mint: String) {
+    // holdings the bot did not buy are only sold when asked for
+    let orphan = position::get(&mint)
+        .await
+        .is_some_and(|position| position.target == ORPHAN_TARGET);
+    if orphan && !*MANAGE_ORPHANS {
+        return;
+    }
+    if !WATCHING.lock().await.insert(mint.clone()) {
+        return;
+    }
+    tokio::spawn(async move {
+        if let Err(e) = run(&state, jito_client, &mint).await {
+            warn!(mint = %mint, error = %e, "exit watcher stopped");
+        }
+        WATCHING.lock().await.remove(&mint);
+    });
+}
+
+async fn run(state: &AppState, jito_client: Arc<JitoRpcClient>, mint: &str) -> Result<()> {
+    let position = position::get(mint)
+        .await
+        .ok_or(anyhow!("no open position"))?;
+    let mut quote = load_quote(state, &position).await?;
+
+    let pubsub = PubsubClient::new(&config::config().endpoints.websocket).await?;
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(CommitmentConfig::processed()),
+        ..Default::default()
+    };
+    let mut subscriptions = vec![];
+    for pubkey in quote.accounts()? {
+        let (updates, unsubscribe) = pubsub
+            .account_subscribe(&pubkey, Some(config.clone()))
+            .await?;
+        subscriptions.push(updates.map(move |update| (pubkey, update)));
+        // the subscription ends when the watcher returns and the client is dropped
+        drop(unsubscribe);
+    }
+    let mut updates = stream::select_all(subscriptions);
+    // re-evaluate without price updates too, for the holding time limit
+    let mut ticker = interval(Duration::from_secs(1));
+    let mut exit_state = ExitState::default();
+
+    loop {
+        tokio::select! {
+            update = updates.next() => {
+                let Some((pubkey, update)) = update else {
+                    return Err(anyhow!("account subscription closed"));
+                };
+                if let Some(account) = update.value.decode::<Account>() {
+                    quote.update(&pubkey, &account);
+                }
+            }
+            _ = ticker.tick() => {}
+        }
+
+        let Some(position) = position::get(mint).await else {
+            return Ok(());
+        };
+        let value = quote.value(position.token_amount);
+        let now = Utc::now().timestamp();
+        let exit_config = config::config().exit.clone();
+        if let Some(signal) = evaluate(&exit_config, &position, value, now, &mut exit_state) {
+            // a failed sell is retried on a later trigger, stops keep firing until it lands;
+            // during a panic the position is already being sold
+            if !exit_state.may_sell(Instant::now()) || control::selling_out() {
+                continue;
+            }
+            match sell(state, jito_client.clone(), &position, &signal).await {
+                Ok(()) => exit_state.sold(&signal),
+                Err(e) => {
+                    let wait = exit_state.failed(Instant::now());
+                    warn!(mint, error = %e, retry_in = ?wait, "exit sell failed");
+                }
+            }
+        }
+    }
+}
+
+/// Sell `sell_pct` percent of a position now, outside the exit rules.
+pub async fn force_sell(
+    state: &AppState,
+    jito_client: Arc<JitoRpcClient>,
+    mint: &str,
+    sell_pct: u64,
+    reason: &str,
+) -> Result<()> {
+    let position = position::get(mint)
+        .await
+        .ok_or(anyhow!("Exit: no open position in {}", mint))?;
+    let signal = ExitSignal {
+        reason: reason.to_string(),
+        sell_pct: sell_pct.clamp(1, 100),
+        take_profit: false,
+    };
+    sell(state, jito_client, &position, &signal).await
+}
+
+async fn sell(
+    state: &AppState,
+    jito_client: Arc<JitoRpcClient>,
+    position: &Position,
+    signal: &ExitSignal,
+) -> Result<()> {
+    let amount = if signal.sell_pct >= 100 {
+        position.token_amount
+    } else {
+        position.token_amount * signal.sell_pct / 100
+    };
+    log_message(&format!(
+        "Exit: {} selling {} of {} ({})",
+        position.mint, amount, position.token_amount, signal.reason
+    ));
+
+    // a pooled position is sold out of every wallet holding the mint, each selling the share
+    // of what it holds when the swap is built
+    let legs = wallets::allocate(
+        state,
+        &position.target,
+        &position.mint,
+        SwapDirection::Sell,
+        amount,
+    )
+    .await?;
+    let slippage = config::config().exit.slippage;
+    let mut failure = None;
+    for leg in legs {
+        let sol_before = sol_balance(&leg.state).await?;
+        let tokens_before = holding(&leg.state, &position.mint).await?;
+        let res = match position.venue {
+            Venue::Pump => {
+                pump_swap(
+                    leg.state.clone(),
+                    signal.sell_pct,
+                    SwapInType::Pct,
+                    SwapDirection::Sell,
+                    slippage,
+                    &position.mint,
+                    false,
+                    jito_client.clone(),
+                    Instant::now(),
+                )
+                .await
+            }
+            Venue::Raydium => {
+                raydium_swap(
+                    leg.state.clone(),
+                    signal.sell_pct,
+                    SwapInType::Pct,
+                    SwapDirection::Sell,
+                    position.pool_id.clone().unwrap_or_default(),
+                    slippage,
+                    &position.mint,
+                    false,
+                    jito_client.clone(),
+                    Instant::now(),
+                )
+                .await
+            }
+        };
+        trade_log::record(Event::Exit {
+            mint: position.mint.clone(),
+            venue: position.venue,
+            reason: signal.reason.clone(),
+            amount: leg.amount,
+            wallet: leg.state.wallet.pubkey().to_string(),
+            landed: res.is_ok(),
+            signatures: res.as_ref().cloned().unwrap_or_default(),
+            error: res.as_ref().err().map(|e| e.to_string()),
+        });
+        // exits are not copies, a failed one stays out of the copy failure streak
+        let signatures = match res {
+            Ok(signatures) => signatures,
+            Err(e) => {
+                failure = Some(e);
+                continue;
+            }
+        };
+
+        let fill = position::settle(
+            &leg.state,
+            &position.target,
+            &position.mint,
+            position.venue,
+            position.pool_id.clone(),
+            SwapDirection::Sell,
+            sol_before,
+            tokens_before,
+            &signatures,
+            None,
+        )
+        .await?;
+        if let Some(realized) = fill.realized {
+            risk::record_realized(realized, &config::config().risk).await;
+        }
+    }
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    if position::get(&position.mint).await.is_none() {
+        discard_exit(state, &position.mint).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(cost_lamports: u64, opened_at: i64) -> Position {
+        Position {
+            mint: "mint".to_string(),
+            target: "target".to_string(),
+            venue: Venue::Pump,
+            pool_id: None,
+            token_amount: 1_000,
+            cost_lamports,
+            opened_at,
+        }
+    }
+
+    #[test]
+    fn ladders_are_sorted_and_skip_bad_rungs() {
+        let rungs = parse_ladder("5:100, 2:50,x:10,3:150,4");
+        assert_eq!(
+            rungs,
+            vec![
+                TakeProfit {
+                    multiple: 2.0,
+                    sell_pct: 50
+                },
+                TakeProfit {
+                    multiple: 3.0,
+                    sell_pct: 100
+                },
+                TakeProfit {
+                    multiple: 5.0,
+                    sell_pct: 100
+                },
+            ]
+        );
+        assert!(parse_ladder("").is_empty());
+    }
+
+    #[test]
+    fn take_profit_rungs_fire_in_order() {
+        let config = ExitConfig {
+            take_profit: parse_ladder("2:50,3:100"),
+            ..ExitConfig::default()
+        };
+        let mut state = ExitState::default();
+        let position = position(1_000, 0);
+        assert!(evaluate(&config, &position, 1_500, 0, &mut state).is_none());
+        let signal = evaluate(&config, &position, 2_000, 0, &mut state).unwrap();
+        assert_eq!(signal.sell_pct, 50);
+        assert!(signal.take_profit);
+        // the rung is done only once its sell landed
+        assert!(evaluate(&config, &position, 2_000, 0, &mut state).is_some());
+        state.sold(&signal);
+        assert!(evaluate(&config, &position, 2_500, 0, &mut state).is_none());
+        let signal = evaluate(&config, &position, 3_000, 0, &mut state).unwrap();
+        assert_eq!(signal.sell_pct, 100);
+    }
+
+    #[test]
+    fn stops_and_the_holding_time_sell_everything() {
+        let position = position(1_000, 100);
+        let stop_loss = ExitConfig {
+            stop_loss_pct: Some(30.0),
+            ..ExitConfig::default()
+        };
+        let mut state = ExitState::default();
+        assert!(evaluate(&stop_loss, &position, 701, 100, &mut state).is_none());
+        let signal = evaluate(&stop_loss, &position, 650, 100, &mut state).unwrap();
+        assert_eq!(signal.sell_pct, 100);
+        assert!(!signal.take_profit);
+
+        let trailing = ExitConfig {
+            trailing_stop_pct: Some(20.0),
+            ..ExitConfig::default()
+        };
+        let mut state = ExitState::default();
+        assert!(evaluate(&trailing, &position, 3_000, 100, &mut state).is_none());
+        assert!(evaluate(&trailing, &position, 2_500, 100, &mut state).is_none());
+        assert!(evaluate(&trailing, &position, 2_300, 100, &mut state).is_some());
+
+        let max_hold = ExitConfig {
+            max_hold: Some(Duration::from_secs(60)),
+            ..ExitConfig::default()
+        };
+        let mut state = ExitState::default();
+        assert!(evaluate(&max_hold, &position, 1_000, 159, &mut state).is_none());
+        assert!(evaluate(&max_hold, &position, 1_000, 160, &mut state).is_some());
+    }
+
+    #[test]
+    fn failed_sells_back_off() {
+        let mut state = ExitState::default();
+        let now = Instant::now();
+        assert!(state.may_sell(now));
+        assert_eq!(state.failed(now), Duration::from_secs(1));
+        assert!(!state.may_sell(now));
+        assert!(state.may_sell(now + Duration::from_secs(1)));
+        assert_eq!(state.failed(now), Duration::from_secs(2));
+        for _ in 0..10 {
+            state.failed(now);
+        }
+        assert_eq!(state.failed(now), RETRY_MAX);
+        state.sold(&ExitSignal {
+            reason: String::new(),
+            sell_pct: 100,
+            take_profit: false,
+        });
+        assert!(state.may_sell(now));
+        assert_eq!(state.failed(now), Duration::from_secs(1));
+    }
+}
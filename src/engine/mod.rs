@@ -1 +1,12 @@
+pub mod analytics;
+pub mod backtest;
+pub mod exit;
+pub mod paper;
+pub mod pnl;
+pub mod position;
+pub mod presign;
+pub mod reconcile;
+pub mod risk;
+pub mod screener;
 pub mod swap;
+pub mod wallets;
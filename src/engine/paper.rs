@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use tokio::sync::Mutex;
+
+use crate::{
+    common::{
+        config,
+        utils::{env_or, log_message, AppState},
+    },
+    core::{
+        sim::SIGNATURE_FEE,
+        tx::{TradeCosts, TradeOutcome},
+    },
+    engine::{
+        exit::{load_quote, Quote},
+        position::{Fill, Position, Venue},
+        swap::SwapDirection,
+    },
+    services::store::store,
+};
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+// fake signatures of paper fills start with this, telling them apart from live fills and
+// reconcile imports in a journal shared with live runs
+const SIGNATURE_PREFIX: &str = "paper-";
+
+pub static PAPER_CONFIG: LazyLock<PaperConfig> = LazyLock::new(PaperConfig::from_env);
+/// Virtual wallet, picked up from the journal of earlier paper runs
+pub static PAPER_WALLET: LazyLock<Mutex<VirtualWallet>> =
+    LazyLock::new(|| Mutex::new(VirtualWallet::from_journal(&PAPER_CONFIG)));
+// what each paper fill paid and moved, by its fake signature
+static PAPER_OUTCOMES: LazyLock<Mutex<HashMap<String, TradeOutcome>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static PAPER_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone)]
+pub struct PaperConfig {
+    /// Fill trades locally instead of sending them
+    pub enabled: bool,
+    /// Lamports in the virtual wallet on the first run
+    pub starting_balance: u64,
+    /// Delay between the copy decision and the quote it fills at
+    pub latency: Duration,
+    /// Price impact beyond the local quote, in basis points
+    pub slippage_bps: u64,
+    /// Jito tip charged on every fill, in lamports
+    pub tip: u64,
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            starting_balance: 10_000_000_000,
+            latency: Duration::from_millis(400),
+            slippage_bps: 50,
+            tip: 100_000,
+        }
+    }
+}
+
+impl PaperConfig {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let sol = |key: &str, default: u64| {
+            (env_or(key, default as f64 / LAMPORTS_PER_SOL) * LAMPORTS_PER_SOL) as u64
+        };
+        Self {
+            enabled: env_or("PAPER", default.enabled),
+            starting_balance: sol("PAPER_STARTING_SOL", default.starting_balance),
+            latency: Duration::from_millis(env_or(
+                "PAPER_LATENCY_MS",
+                default.latency.as_millis() as u64,
+            )),
+            slippage_bps: env_or("PAPER_SLIPPAGE_BPS", default.slippage_bps),
+            tip: sol("PAPER_TIP_SOL", default.tip),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VirtualWallet {
+    pub lamports: u64,
+    /// Raw token balances, by mint
+    pub tokens: HashMap<String, u64>,
+}
+
+/// Whether the fill was made by the paper engine rather than sent or imported.
+pub fn is_paper_fill(fill: &Fill) -> bool {
+    !fill.signatures.is_empty()
+        && fill
+            .signatures
+            .iter()
+            .all(|signature| signature.starts_with(SIGNATURE_PREFIX))
+}
+
+impl VirtualWallet {
+    /// Starting balance moved by the paper fills among `fills`, fees included: a buy's lamports
+    /// carry its fees and a sell's are net of them. A sell whose fees ate the proceeds is
+    /// journaled at zero lamports, its fees are charged here instead.
+    pub fn replay(starting_balance: u64, fills: &[Fill]) -> Self {
+        let mut lamports = starting_balance as i64;
+        let mut tokens: HashMap<String, u64> = HashMap::new();
+        for fill in fills.iter().filter(|fill| is_paper_fill(fill)) {
+            let held = tokens.entry(fill.mint.clone()).or_insert(0);
+            match fill.direction {
+                SwapDirection::Buy => {
+                    lamports -= fill.lamports as i64;
+                    *held += fill.token_amount;
+                }
+                SwapDirection::Sell => {
+                    lamports += if fill.lamports == 0 {
+                        -((fill.network_fee + fill.priority_fee + fill.tip) as i64)
+                    } else {
+                        fill.lamports as i64
+                    };
+                    *held = held.saturating_sub(fill.token_amount);
+                }
+            }
+        }
+        tokens.retain(|_, amount| *amount > 0);
+        Self {
+            lamports: lamports.max(0) as u64,
+            tokens,
+        }
+    }
+
+    // picked up from the paper fills of earlier runs
+    fn from_journal(config: &PaperConfig) -> Self {
+        let fills = store()
+            .and_then(|store| store.fills(0, None, None))
+            .unwrap_or_default();
+        Self::replay(config.starting_balance, &fills)
+    }
+}
+
+pub async fn sol_balance() -> u64 {
+    PAPER_WALLET.lock().await.lamports
+}
+
+pub async fn token_balance(mint: &str) -> u64 {
+    PAPER_WALLET
+        .lock()
+        .await
+        .tokens
+        .get(mint)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// Costs and balance changes of the paper fills behind `signatures`. Settling a fill reads
+/// them once, so they are taken out of the book.
+pub async fn trade_outcome(signatures: &[String]) -> TradeOutcome {
+    let mut outcomes = PAPER_OUTCOMES.lock().await;
+    let mut total = TradeOutcome::default();
+    for outcome in signatures
+        .iter()
+        .filter_map(|signature| outcomes.remove(signature))
+    {
+        total.costs.network_fee += outcome.costs.network_fee;
+        total.costs.priority_fee += outcome.costs.priority_fee;
+        total.costs.tip += outcome.costs.tip;
+        total.sol_change += outcome.sol_change;
+        total.token_change += outcome.token_change;
+    }
+    total
+}
+
+/// Fill a swap against the local quote after the modelled latency, moving the virtual wallet.
+/// Fails like the real swap would when the price moved beyond `slippage` (basis points) or the
+/// wallet cannot cover it. Returns a fake signature.
+pub async fn execute(
+    state: &AppState,
+    venue: Venue,
+    mint: &str,
+    pool_id: Option<String>,
+    direction: SwapDirection,
+    amount_in: u64,
+    slippage: u64,
+) -> Result<Vec<String>> {
+    let config = &*PAPER_CONFIG;
+    let position = Position {
+        mint: mint.to_string(),
+        target: String::new(),
+        venue,
+        pool_id,
+        token_amount: 0,
+        cost_lamports: 0,
+        opened_at: 0,
+    };
+    // the quote the copy was sized against, then the one it lands at
+    let expected = load_quote(state, &position).await?;
+    tokio::time::sleep(config.latency).await;
+    let quote = load_quote(state, &position).await?;
+
+    let out_of = |quote: &Quote| match direction {
+        SwapDirection::Buy => quote.tokens_for(amount_in),
+        SwapDirection::Sell => quote.value(amount_in),
+    };
+    let min_out = out_of(&expected) as u128 * 10_000u128.saturating_sub(slippage as u128) / 10_000;
+    let out = (out_of(&quote) as u128 * (10_000 - config.slippage_bps.min(10_000)) as u128 / 10_000)
+        as u64;
+    if (out as u128) < min_out {
+        return Err(anyhow!(
+            "Slippage: paper fill {} below minimum {}",
+            out,
+            min_out
+        ));
+    }
+
+    let fee_config = config::config().fees.clone();
+    let costs = TradeCosts {
+        network_fee: SIGNATURE_FEE,
+        priority_fee: fee_config.min_unit_price * fee_config.unit_limit as u64 / 1_000_000,
+        tip: config.tip,
+    };
+    let fees = costs.network_fee + costs.priority_fee + costs.tip;
+    let (sol_change, token_change) = {
+        let mut wallet = PAPER_WALLET.lock().await;
+        let held = wallet.tokens.get(mint).copied().unwrap_or(0);
+        match direction {
+            SwapDirection::Buy => {
+                if wallet.lamports < amount_in + fees {
+                    return Err(anyhow!(
+                        "InsufficientFunds: paper wallet holds {} lamports",
+                        wallet.lamports
+                    ));
+                }
+                wallet.lamports -= amount_in + fees;
+                wallet.tokens.insert(mint.to_string(), held + out);
+                (-((amount_in + fees) as i64), out as i64)
+            }
+            SwapDirection::Sell => {
+                if held < amount_in || wallet.lamports + out < fees {
+                    return Err(anyhow!(
+                        "InsufficientFunds: paper wallet holds {} tokens",
+                        held
+                    ));
+                }
+                wallet.lamports = wallet.lamports + out - fees;
+                wallet.tokens.insert(mint.to_string(), held - amount_in);
+                (out as i64 - fees as i64, -(amount_in as i64))
+            }
+        }
+    };
+
+    let signature = format!(
+        "{}{}-{}",
+        SIGNATURE_PREFIX,
+        Utc::now().timestamp_millis(),
+        PAPER_SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    );
+    PAPER_OUTCOMES.lock().await.insert(
+        signature.clone(),
+        TradeOutcome {
+            costs,
+            sol_change,
+            token_change,
+        },
+    );
+    log_message(&format!(
+        "Paper: {} {:?} {} in {} out {} ({})",
+        mint, direction, venue, amount_in, out, signature
+    ));
+    Ok(vec![signature])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(mint: &str, direction: SwapDirection, tokens: u64, lamports: u64, sig: &str) -> Fill {
+        Fill {
+            mint: mint.to_string(),
+            target: "target".to_string(),
+            venue: Venue::Pump,
+            direction,
+            token_amount: tokens,
+            lamports,
+            realized: None,
+            timestamp: 0,
+            signatures: if sig.is_empty() {
+                vec![]
+            } else {
+                vec![sig.to_string()]
+            },
+            network_fee: 5_000,
+            priority_fee: 1_000,
+            tip: 100_000,
+            wallet: None,
+        }
+    }
+
+    #[test]
+    fn only_paper_signatures_make_a_paper_fill() {
+        assert!(is_paper_fill(&fill(
+            "a",
+            SwapDirection::Buy,
+            1,
+            1,
+            "paper-1-0"
+        )));
+        assert!(!is_paper_fill(&fill("a", SwapDirection::Buy, 1, 1, "5VfY")));
+        // reconcile imports and adjustments carry no signature
+        assert!(!is_paper_fill(&fill("a", SwapDirection::Buy, 1, 1, "")));
+    }
+
+    #[test]
+    fn the_wallet_is_rebuilt_from_paper_fills_only() {
+        let fills = [
+            // buy of 1 SOL plus 106_000 in fees
+            fill("a", SwapDirection::Buy, 1_000, 1_106_000, "paper-1-0"),
+            fill("b", SwapDirection::Buy, 500, 2_000_000, "paper-2-1"),
+            // live fill and reconcile import in a shared journal
+            fill("a", SwapDirection::Buy, 9_000, 50_000_000, "5VfY"),
+            fill("c", SwapDirection::Buy, 7_000, 3_000_000, ""),
+            // sold 400 for 600_000 net of fees
+            fill("a", SwapDirection::Sell, 400, 600_000, "paper-3-2"),
+            fill("b", SwapDirection::Sell, 500, 2_500_000, "paper-4-3"),
+        ];
+        let wallet = VirtualWallet::replay(10_000_000, &fills);
+        assert_eq!(
+            wallet.lamports,
+            10_000_000 - 1_106_000 - 2_000_000 + 600_000 + 2_500_000
+        );
+        assert_eq!(wallet.tokens.get("a"), Some(&600));
+        assert_eq!(wallet.tokens.get("b"), None);
+        assert_eq!(wallet.tokens.get("c"), None);
+    }
+
+    #[test]
+    fn a_sell_that_did_not_cover_its_fees_is_charged_them() {
+        let fills = [
+            fill("a", SwapDirection::Buy, 1_000, 1_106_000, "paper-1-0"),
+            fill("a", SwapDirection::Sell, 1_000, 0, "paper-2-1"),
+        ];
+        let wallet = VirtualWallet::replay(10_000_000, &fills);
+        assert_eq!(wallet.lamports, 10_000_000 - 1_106_000 - 106_000);
+        assert!(wallet.tokens.is_empty());
+    }
+
+    #[test]
+    fn the_balance_does_not_go_below_zero() {
+        let fills = [fill("a", SwapDirection::Buy, 1, 5_000_000, "paper-1-0")];
+        assert_eq!(VirtualWallet::replay(1_000_000, &fills).lamports, 0);
+    }
+}
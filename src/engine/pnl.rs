@@ -0,0 +1,405 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    common::utils::AppState,
+    engine::{
+        exit,
+        position::{self, Fill, Venue},
+        swap::SwapDirection,
+    },
+    services::store::store,
+};
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+
+/// How the cost of sold tokens is matched against earlier buys
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CostMethod {
+    /// Oldest buys are sold first
+    #[serde(rename = "fifo")]
+    Fifo,
+    /// Every token carries the average cost of the holding
+    #[serde(rename = "average")]
+    Average,
+}
+
+/// Tokens bought together, and what is left of their cost
+#[derive(Debug, Clone)]
+struct Lot {
+    target: String,
+    venue: Venue,
+    amount: u64,
+    cost: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Rollup {
+    pub buys: u32,
+    pub sells: u32,
+    /// Lamports spent on buys
+    pub spent: u64,
+    /// Lamports received from sells
+    pub received: u64,
+    pub realized: i64,
+    /// Marked value of the tokens still held minus their cost
+    pub unrealized: i64,
+    pub network_fee: u64,
+    pub priority_fee: u64,
+    pub tip: u64,
+}
+
+impl Rollup {
+    fn add_fill(&mut self, fill: &Fill, realized: Option<i64>) {
+        match fill.direction {
+            SwapDirection::Buy => {
+                self.buys += 1;
+                self.spent += fill.lamports;
+            }
+            SwapDirection::Sell => {
+                self.sells += 1;
+                self.received += fill.lamports;
+            }
+        }
+        self.realized += realized.unwrap_or(0);
+        self.network_fee += fill.network_fee;
+        self.priority_fee += fill.priority_fee;
+        self.tip += fill.tip;
+    }
+
+    pub fn fees(&self) -> u64 {
+        self.network_fee + self.priority_fee + self.tip
+    }
+
+    pub fn total(&self) -> i64 {
+        self.realized + self.unrealized
+    }
+}
+
+/// A fill with the profit it realized under the report's cost method
+#[derive(Debug, Clone, Serialize)]
+pub struct TradePnl {
+    pub fill: Fill,
+    pub realized: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PnlReport {
+    pub method: CostMethod,
+    pub trades: Vec<TradePnl>,
+    pub by_target: BTreeMap<String, Rollup>,
+    pub by_mint: BTreeMap<String, Rollup>,
+    pub by_venue: BTreeMap<String, Rollup>,
+    /// Realized profit and fees by local date; unrealized profit has no day
+    pub by_day: BTreeMap<String, Rollup>,
+    pub total: Rollup,
+}
+
+/// Match sold tokens against the lots of the mint. Returns the cost of what was sold; tokens
+/// sold beyond the lots (bought outside the bot) are taken at no cost.
+fn consume(lots: &mut VecDeque<Lot>, mut sold: u64) -> u64 {
+    let mut cost = 0;
+    while sold > 0 {
+        let Some(lot) = lots.front_mut() else {
+            break;
+        };
+        let taken = sold.min(lot.amount);
+        let lot_cost = if taken == lot.amount {
+            lot.cost
+        } else {
+            (lot.cost as u128 * taken as u128 / lot.amount as u128) as u64
+        };
+        cost += lot_cost;
+        lot.amount -= taken;
+        lot.cost -= lot_cost;
+        sold -= taken;
+        if lot.amount == 0 {
+            lots.pop_front();
+        }
+    }
+    cost
+}
+
+/// Replay fills (oldest first) into realized and unrealized profit. Fills before `since` (unix
+/// seconds) only build up the cost basis. `marks` holds the lamports one token of each open mint
+/// would fetch now.
+pub fn compute(
+    fills: &[Fill],
+    method: CostMethod,
+    since: i64,
+    marks: &HashMap<String, f64>,
+) -> PnlReport {
+    let mut lots: HashMap<String, VecDeque<Lot>> = HashMap::new();
+    let mut report = PnlReport {
+        method,
+        trades: vec![],
+        by_target: BTreeMap::new(),
+        by_mint: BTreeMap::new(),
+        by_venue: BTreeMap::new(),
+        by_day: BTreeMap::new(),
+        total: Rollup::default(),
+    };
+
+    for fill in fills {
+        let mint_lots = lots.entry(fill.mint.clone()).or_default();
+        let realized = match fill.direction {
+            SwapDirection::Buy => {
+                // the average method keeps a single lot per mint
+                if method == CostMethod::Average && !mint_lots.is_empty() {
+                    mint_lots[0].amount += fill.token_amount;
+                    mint_lots[0].cost += fill.lamports;
+                } else {
+                    mint_lots.push_back(Lot {
+                        target: fill.target.clone(),
+                        venue: fill.venue,
+                        amount: fill.token_amount,
+                        cost: fill.lamports,
+                    });
+                }
+                None
+            }
+            SwapDirection::Sell => {
+                Some(fill.lamports as i64 - consume(mint_lots, fill.token_amount) as i64)
+            }
+        };
+        if fill.timestamp < since {
+            continue;
+        }
+
+        let day = DateTime::<Utc>::from_timestamp(fill.timestamp, 0)
+            .map(|at| at.with_timezone(&Local).format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        for rollup in [
+            report.by_target.entry(fill.target.clone()).or_default(),
+            report.by_mint.entry(fill.mint.clone()).or_default(),
+            report.by_venue.entry(fill.venue.to_string()).or_default(),
+            report.by_day.entry(day).or_default(),
+        ] {
+            rollup.add_fill(fill, realized);
+        }
+        report.total.add_fill(fill, realized);
+        report.trades.push(TradePnl {
+            fill: fill.clone(),
+            realized,
+        });
+    }
+
+    for (mint, mint_lots) in lots.iter() {
+        let Some(price) = marks.get(mint) else {
+            continue;
+        };
+        for lot in mint_lots {
+            let unrealized = (lot.amount as f64 * price) as i64 - lot.cost as i64;
+            for rollup in [
+                report.by_target.entry(lot.target.clone()).or_default(),
+                report.by_mint.entry(mint.clone()).or_default(),
+                report.by_venue.entry(lot.venue.to_string()).or_default(),
+            ] {
+                rollup.unrealized += unrealized;
+            }
+            report.total.unrealized += unrealized;
+        }
+    }
+    report
+}
+
+/// Lamports per token of every open position, from fresh local quotes.
+pub async fn marks(state: &AppState) -> HashMap<String, f64> {
+    let mut marks = HashMap::new();
+    for position in position::all().await {
+        if position.token_amount == 0 {
+            continue;
+        }
+        match exit::mark(state, &position).await {
+            Ok(value) => {
+                marks.insert(
+                    position.mint.clone(),
+                    value as f64 / position.token_amount as f64,
+                );
+            }
+            Err(e) => warn!(mint = %position.mint, error = %e, "position not marked"),
+        }
+    }
+    marks
+}
+
+/// PnL of the fills journaled since `since` (unix seconds).
+pub async fn report(state: &AppState, method: CostMethod, since: i64) -> Result<PnlReport> {
+    let fills = store()?.fills(0, None, None)?;
+    Ok(compute(&fills, method, since, &marks(state).await))
+}
+
+fn sol(lamports: i64) -> String {
+    format!("{:.4}", lamports as f64 / LAMPORTS_PER_SOL)
+}
+
+fn print_rollups(title: &str, rollups: &BTreeMap<String, Rollup>) {
+    println!("\n{}", title);
+    println!(
+        "{:<46} {:>5} {:>5} {:>12} {:>12} {:>12} {:>10}",
+        "", "buys", "sells", "realized", "unrealized", "total", "fees"
+    );
+    for (key, rollup) in rollups {
+        println!(
+            "{:<46} {:>5} {:>5} {:>12} {:>12} {:>12} {:>10}",
+            key,
+            rollup.buys,
+            rollup.sells,
+            sol(rollup.realized),
+            sol(rollup.unrealized),
+            sol(rollup.total()),
+            sol(rollup.fees() as i64)
+        );
+    }
+}
+
+impl PnlReport {
+    /// Print the rollups as tables, amounts in SOL.
+    pub fn print(&self) {
+        println!("PnL ({:?} cost basis)", self.method);
+        print_rollups("By target", &self.by_target);
+        print_rollups("By mint", &self.by_mint);
+        print_rollups("By venue", &self.by_venue);
+        print_rollups("By day", &self.by_day);
+        println!(
+            "\nTotal: realized {} SOL, unrealized {} SOL, fees {} SOL (network {}, priority {}, tips {})",
+            sol(self.total.realized),
+            sol(self.total.unrealized),
+            sol(self.total.fees() as i64),
+            sol(self.total.network_fee as i64),
+            sol(self.total.priority_fee as i64),
+            sol(self.total.tip as i64)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINT: &str = "mint";
+
+    fn fill(direction: SwapDirection, token_amount: u64, lamports: u64, timestamp: i64) -> Fill {
+        Fill {
+            mint: MINT.to_string(),
+            target: "target".to_string(),
+            venue: Venue::Pump,
+            direction,
+            token_amount,
+            lamports,
+            realized: None,
+            timestamp,
+            signatures: vec![],
+            network_fee: 0,
+            priority_fee: 0,
+            tip: 0,
+            wallet: None,
+        }
+    }
+
+    fn buy(token_amount: u64, lamports: u64) -> Fill {
+        fill(SwapDirection::Buy, token_amount, lamports, 0)
+    }
+
+    fn sell(token_amount: u64, lamports: u64) -> Fill {
+        fill(SwapDirection::Sell, token_amount, lamports, 0)
+    }
+
+    fn marks(price: f64) -> HashMap<String, f64> {
+        HashMap::from([(MINT.to_string(), price)])
+    }
+
+    #[test]
+    fn fifo_sells_the_oldest_lot_first() {
+        let fills = [buy(100, 1_000), buy(100, 3_000), sell(100, 2_500)];
+        let report = compute(&fills, CostMethod::Fifo, 0, &marks(25.0));
+        assert_eq!(report.trades[2].realized, Some(1_500));
+        assert_eq!(report.total.realized, 1_500);
+        // the 3_000 lot is left, worth 2_500
+        assert_eq!(report.total.unrealized, -500);
+    }
+
+    #[test]
+    fn average_sells_at_the_average_cost() {
+        let fills = [buy(100, 1_000), buy(100, 3_000), sell(100, 2_500)];
+        let report = compute(&fills, CostMethod::Average, 0, &marks(25.0));
+        assert_eq!(report.trades[2].realized, Some(500));
+        assert_eq!(report.total.realized, 500);
+        assert_eq!(report.total.unrealized, 500);
+    }
+
+    #[test]
+    fn methods_agree_on_the_total() {
+        let fills = [buy(100, 1_000), buy(100, 3_000), sell(100, 2_500)];
+        let fifo = compute(&fills, CostMethod::Fifo, 0, &marks(25.0));
+        let average = compute(&fills, CostMethod::Average, 0, &marks(25.0));
+        assert_eq!(fifo.total.total(), 1_000);
+        assert_eq!(average.total.total(), 1_000);
+    }
+
+    #[test]
+    fn partial_sell_takes_part_of_a_lot() {
+        let fills = [buy(100, 1_000), sell(30, 600), sell(20, 100)];
+        let report = compute(&fills, CostMethod::Fifo, 0, &marks(20.0));
+        assert_eq!(report.trades[1].realized, Some(300));
+        assert_eq!(report.trades[2].realized, Some(-100));
+        // 50 tokens left at a cost of 500
+        assert_eq!(report.total.unrealized, 500);
+        assert_eq!(report.total.buys, 1);
+        assert_eq!(report.total.sells, 2);
+        assert_eq!(report.total.spent, 1_000);
+        assert_eq!(report.total.received, 700);
+    }
+
+    #[test]
+    fn tokens_sold_beyond_the_lots_cost_nothing() {
+        let fills = [buy(100, 1_000), sell(150, 3_000)];
+        let report = compute(&fills, CostMethod::Fifo, 0, &HashMap::new());
+        assert_eq!(report.total.realized, 2_000);
+        assert_eq!(report.total.unrealized, 0);
+    }
+
+    #[test]
+    fn fees_are_rolled_up_and_part_of_the_cost() {
+        let mut bought = buy(100, 1_050);
+        bought.network_fee = 5_000;
+        bought.priority_fee = 20;
+        bought.tip = 30;
+        let mut sold = sell(100, 1_950);
+        sold.network_fee = 5_000;
+        sold.tip = 50;
+        let report = compute(&[bought, sold], CostMethod::Fifo, 0, &HashMap::new());
+        // lamports already carry the fees, they are not taken off a second time
+        assert_eq!(report.total.realized, 900);
+        assert_eq!(report.total.network_fee, 10_000);
+        assert_eq!(report.total.priority_fee, 20);
+        assert_eq!(report.total.tip, 80);
+        assert_eq!(report.total.fees(), 10_100);
+        assert_eq!(report.by_target["target"].fees(), 10_100);
+    }
+
+    #[test]
+    fn fills_before_since_only_build_the_cost_basis() {
+        let fills = [
+            fill(SwapDirection::Buy, 100, 1_000, 10),
+            fill(SwapDirection::Sell, 50, 800, 20),
+        ];
+        let report = compute(&fills, CostMethod::Fifo, 15, &HashMap::new());
+        assert_eq!(report.trades.len(), 1);
+        assert_eq!(report.total.buys, 0);
+        assert_eq!(report.total.spent, 0);
+        assert_eq!(report.total.realized, 300);
+    }
+
+    #[test]
+    fn unmarked_mints_have_no_unrealized_profit() {
+        let report = compute(&[buy(100, 1_000)], CostMethod::Fifo, 0, &HashMap::new());
+        assert_eq!(report.total.unrealized, 0);
+        assert_eq!(report.by_mint[MINT].spent, 1_000);
+    }
+}
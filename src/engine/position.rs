@@ -0,0 +1,246 @@
+use std::{collections::HashMap, fmt, str::FromStr, sync::LazyLock};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use solana_sdk::signer::Signer;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::{
+    common::utils::AppState,
+    core::tx::trade_outcome,
+    engine::{
+        paper::{self, PAPER_CONFIG},
+        presign::{holding, sol_balance},
+        swap::SwapDirection,
+    },
+    services::{
+        metrics,
+        store::{persist_position, store},
+    },
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Venue {
+    #[serde(rename = "pump")]
+    Pump,
+    #[serde(rename = "raydium")]
+    Raydium,
+}
+
+impl fmt::Display for Venue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Venue::Pump => write!(f, "pump"),
+            Venue::Raydium => write!(f, "raydium"),
+        }
+    }
+}
+
+impl FromStr for Venue {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pump" => Ok(Venue::Pump),
+            "raydium" => Ok(Venue::Raydium),
+            _ => Err(anyhow!("unknown venue {}", s)),
+        }
+    }
+}
+
+/// Tokens we hold in a mint and what they cost us
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Position {
+    pub mint: String,
+    /// Target wallet whose trade opened the position
+    pub target: String,
+    pub venue: Venue,
+    pub pool_id: Option<String>,
+    pub token_amount: u64,
+    /// Lamports spent on the tokens still held, fees included
+    pub cost_lamports: u64,
+    /// Unix timestamp of the first buy
+    pub opened_at: i64,
+}
+
+/// Measured outcome of a landed copy trade
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fill {
+    pub mint: String,
+    pub target: String,
+    pub venue: Venue,
+    pub direction: SwapDirection,
+    pub token_amount: u64,
+    /// Lamports spent on a buy or received from a sell, fees and tips included
+    pub lamports: u64,
+    /// Realized profit of a sell against the average cost of the tokens sold
+    pub realized: Option<i64>,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub signatures: Vec<String>,
+    /// Part of `lamports` paid as signature fees, priority fees and Jito tips
+    #[serde(default)]
+    pub network_fee: u64,
+    #[serde(default)]
+    pub priority_fee: u64,
+    #[serde(default)]
+    pub tip: u64,
+    /// Execution wallet that made the trade
+    #[serde(default)]
+    pub wallet: Option<String>,
+}
+
+/// Open positions, by mint
+pub static POSITIONS: LazyLock<RwLock<HashMap<String, Position>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+pub async fn get(mint: &str) -> Option<Position> {
+    POSITIONS.read().await.get(mint).cloned()
+}
+
+pub async fn all() -> Vec<Position> {
+    POSITIONS.read().await.values().cloned().collect()
+}
+
+/// Apply a fill to the positions. A sell realizes profit against the average cost of the
+/// tokens sold and closes the position once nothing is left.
+pub async fn apply(fill: &mut Fill, pool_id: Option<String>) {
+    let mut positions = POSITIONS.write().await;
+    // written to the store once the lock is released, SQLite calls block
+    let changed = match fill.direction {
+        SwapDirection::Buy => {
+            let position = positions
+                .entry(fill.mint.clone())
+                .or_insert_with(|| Position {
+                    mint: fill.mint.clone(),
+                    target: fill.target.clone(),
+                    venue: fill.venue,
+                    pool_id: pool_id.clone(),
+                    token_amount: 0,
+                    cost_lamports: 0,
+                    opened_at: fill.timestamp,
+                });
+            position.token_amount += fill.token_amount;
+            position.cost_lamports += fill.lamports;
+            position.venue = fill.venue;
+            if pool_id.is_some() {
+                position.pool_id = pool_id;
+            }
+            Some(position.clone())
+        }
+        SwapDirection::Sell => {
+            let Some(position) = positions.get_mut(&fill.mint) else {
+                return;
+            };
+            let sold = fill.token_amount.min(position.token_amount);
+            let cost = if position.token_amount == 0 {
+                0
+            } else {
+                (position.cost_lamports as u128 * sold as u128 / position.token_amount as u128)
+                    as u64
+            };
+            fill.realized = Some(fill.lamports as i64 - cost as i64);
+            position.token_amount -= sold;
+            position.cost_lamports -= cost;
+            if position.token_amount == 0 {
+                positions.remove(&fill.mint);
+                None
+            } else {
+                Some(position.clone())
+            }
+        }
+    };
+    drop(positions);
+    persist_position(&fill.mint, changed.as_ref());
+}
+
+/// Load the positions saved by a previous run. Returns how many were restored.
+pub async fn restore() -> Result<usize> {
+    let saved = store()?.load_positions()?;
+    let mut positions = POSITIONS.write().await;
+    for position in saved.iter() {
+        positions.insert(position.mint.clone(), position.clone());
+    }
+    Ok(saved.len())
+}
+
+/// Measure a landed trade from the wallet's SOL and token balance changes recorded in its own
+/// transactions, break down what they paid in fees and tips, apply it to the positions and
+/// journal it under the copy attempt that sent it. Only when the transactions can't be read are
+/// the balances before and after compared instead, which other trades landing meanwhile skew.
+pub async fn settle(
+    state: &AppState,
+    target: &str,
+    mint: &str,
+    venue: Venue,
+    pool_id: Option<String>,
+    direction: SwapDirection,
+    sol_before: u64,
+    tokens_before: u64,
+    signatures: &[String],
+    attempt_id: Option<i64>,
+) -> Result<Fill> {
+    let outcome = if PAPER_CONFIG.enabled {
+        Ok(paper::trade_outcome(signatures).await)
+    } else {
+        trade_outcome(
+            &state.rpc_nonblocking_client,
+            signatures,
+            &state.wallet.pubkey(),
+            mint,
+        )
+        .await
+    };
+    let (sol_change, token_change, costs) = match outcome {
+        Ok(outcome) => (
+            outcome.sol_change,
+            outcome.token_change,
+            Some(outcome.costs),
+        ),
+        Err(e) => {
+            warn!(mint, error = %e, "trade outcome unreadable, measuring from balances");
+            let sol_after = sol_balance(state).await?;
+            let tokens_after = holding(state, mint).await.unwrap_or(0);
+            (
+                sol_after as i64 - sol_before as i64,
+                tokens_after as i64 - tokens_before as i64,
+                None,
+            )
+        }
+    };
+    let (token_amount, lamports) = match direction {
+        SwapDirection::Buy => (token_change.max(0) as u64, (-sol_change).max(0) as u64),
+        SwapDirection::Sell => ((-token_change).max(0) as u64, sol_change.max(0) as u64),
+    };
+    let mut fill = Fill {
+        mint: mint.to_string(),
+        target: target.to_string(),
+        venue,
+        direction,
+        token_amount,
+        lamports,
+        realized: None,
+        timestamp: Utc::now().timestamp(),
+        signatures: signatures.to_vec(),
+        network_fee: 0,
+        priority_fee: 0,
+        tip: 0,
+        wallet: Some(state.wallet.pubkey().to_string()),
+    };
+    if let Some(costs) = costs {
+        fill.network_fee = costs.network_fee;
+        fill.priority_fee = costs.priority_fee;
+        fill.tip = costs.tip;
+        metrics::NETWORK_FEE_LAMPORTS.add(costs.network_fee);
+        metrics::PRIORITY_FEE_LAMPORTS.add(costs.priority_fee);
+        metrics::TIP_LAMPORTS.add(costs.tip);
+    }
+    apply(&mut fill, pool_id).await;
+    if let Err(e) = store().and_then(|store| store.record_fill(&fill, attempt_id)) {
+        warn!(mint, error = %e, "fill not journaled");
+    }
+    Ok(fill)
+}
@@ -0,0 +1,216 @@
+use std::{collections::HashMap, str::FromStr, sync::LazyLock};
+
+use anyhow::{anyhow, Result};
+use solana_sdk::{
+    instruction::Instruction, pubkey::Pubkey, signer::Signer, transaction::Transaction,
+};
+use tokio::{sync::RwLock, time::Instant};
+
+use crate::{
+    common::utils::AppState,
+    core::{
+        nonce,
+        token::{self, get_account_info},
+        tx,
+    },
+    dex::{pump::Pump, raydium::Raydium},
+    engine::{
+        paper::{self, PAPER_CONFIG},
+        position::Venue,
+        swap::SwapDirection,
+    },
+    services::trade_log::{self, Event},
+};
+
+/// Sell transaction signed ahead of time against a durable nonce
+pub struct PresignedExit {
+    pub mint: String,
+    pub token_amount: u64,
+    pub nonce_account: Pubkey,
+    pub transaction: Transaction,
+    pub prepared_at: Instant,
+}
+
+/// Pre-signed exits of open positions, by mint
+pub static PRESIGNED_EXITS: LazyLock<RwLock<HashMap<String, PresignedExit>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Lamports in the wallet, or in the virtual wallet when paper trading.
+pub async fn sol_balance(state: &AppState) -> Result<u64> {
+    if PAPER_CONFIG.enabled {
+        return Ok(paper::sol_balance().await);
+    }
+    Ok(state
+        .rpc_nonblocking_client
+        .get_balance(&state.wallet.pubkey())
+        .await?)
+}
+
+/// Token balance of our ATA for the mint, which is what an exit sells.
+pub async fn holding(state: &AppState, mint: &str) -> Result<u64> {
+    if PAPER_CONFIG.enabled {
+        return Ok(paper::token_balance(mint).await);
+    }
+    let mint = Pubkey::from_str(mint)?;
+    let mint_info = token::get_token_mint_info(state.rpc_nonblocking_client.clone(), &mint).await?;
+    let ata = mint_info.associated_token_address(&state.wallet.pubkey());
+    let account = get_account_info(state.rpc_nonblocking_client.clone(), &mint, &ata).await?;
+    Ok(account.base.amount)
+}
+
+pub async fn prepare_pump_exit(state: &AppState, mint: &str, slippage: u64) -> Result<()> {
+    let token_amount = holding(state, mint).await?;
+    let swapx = Pump::new(
+        state.rpc_nonblocking_client.clone(),
+        state.rpc_client.clone(),
+        state.wallet.clone(),
+    );
+    let (instructions, _) = swapx
+        .swap_instructions(mint, token_amount, SwapDirection::Sell, slippage)
+        .await?;
+    store(state, mint, token_amount, instructions).await
+}
+
+pub async fn prepare_raydium_exit(
+    state: &AppState,
+    mint: &str,
+    pool_id: String,
+    slippage: u64,
+) -> Result<()> {
+    let token_amount = holding(state, mint).await?;
+    let swapx = Raydium::new(
+        state.rpc_nonblocking_client.clone(),
+        state.rpc_client.clone(),
+        state.wallet.clone(),
+    );
+    let (instructions, _) = swapx
+        .swap_instructions(mint, SwapDirection::Sell, token_amount, pool_id, slippage)
+        .await?;
+    store(state, mint, token_amount, instructions).await
+}
+
+async fn store(
+    state: &AppState,
+    mint: &str,
+    token_amount: u64,
+    instructions: Vec<Instruction>,
+) -> Result<()> {
+    // re-preparing a mint reuses its nonce account, the old transaction is simply replaced
+    let prepared = PRESIGNED_EXITS
+        .read()
+        .await
+        .get(mint)
+        .map(|exit| exit.nonce_account);
+    let nonce_account = match prepared {
+        Some(nonce_account) => nonce_account,
+        None => nonce::lease()
+            .await
+            .ok_or(anyhow!("NoNonceAccount: every nonce account is in use"))?,
+    };
+    let transaction = match tx::new_signed_with_nonce(
+        &state.rpc_client,
+        &state.wallet,
+        instructions,
+        &nonce_account,
+    ) {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            if prepared.is_none() {
+                nonce::release(&nonce_account).await;
+            }
+            return Err(e);
+        }
+    };
+    PRESIGNED_EXITS.write().await.insert(
+        mint.to_string(),
+        PresignedExit {
+            mint: mint.to_string(),
+            token_amount,
+            nonce_account,
+            transaction,
+            prepared_at: Instant::now(),
+        },
+    );
+    Ok(())
+}
+
+/// Whether a pre-signed exit is cached for the mint.
+pub async fn has_exit(mint: &str) -> bool {
+    PRESIGNED_EXITS.read().await.contains_key(mint)
+}
+
+/// How firing a pre-signed exit ended
+pub enum ExitOutcome {
+    /// The exit landed, with its signature
+    Landed(String),
+    /// The exit did not land and its nonce was advanced, so a fresh sell is safe
+    Invalidated(anyhow::Error),
+    /// The exit did not confirm and its nonce could not be advanced, so it may still land and
+    /// the nonce account stays leased
+    Pending(anyhow::Error),
+}
+
+/// Fire the pre-signed exit for the mint, if there is one, and wait for it to confirm. An exit
+/// that does not confirm is invalidated by advancing its nonce before anything else is sent, and
+/// its nonce account is released only once the nonce is known to have moved.
+pub async fn fire_exit(state: &AppState, mint: &str, venue: Venue) -> Option<ExitOutcome> {
+    let exit = PRESIGNED_EXITS.write().await.remove(mint)?;
+    let outcome = match tx::send_presigned(&state.rpc_nonblocking_client, &exit.transaction).await {
+        // landing consumed the nonce
+        Ok(signature) => ExitOutcome::Landed(signature.to_string()),
+        Err(e) => match nonce::advance_nonce(&state.rpc_client, &state.wallet, &exit.nonce_account)
+        {
+            Ok(_) => {
+                // the exit may have landed between the timeout and the advance
+                let signature = exit.transaction.signatures[0];
+                match state
+                    .rpc_nonblocking_client
+                    .get_signature_status(&signature)
+                    .await
+                {
+                    Ok(Some(Ok(()))) => ExitOutcome::Landed(signature.to_string()),
+                    _ => ExitOutcome::Invalidated(e),
+                }
+            }
+            Err(advance) => ExitOutcome::Pending(anyhow!(
+                "{}, and its nonce could not be advanced: {}",
+                e,
+                advance
+            )),
+        },
+    };
+    if !matches!(outcome, ExitOutcome::Pending(_)) {
+        nonce::release(&exit.nonce_account).await;
+    }
+    let (landed, signatures, error) = match &outcome {
+        ExitOutcome::Landed(signature) => (true, vec![signature.clone()], None),
+        ExitOutcome::Invalidated(e) | ExitOutcome::Pending(e) => {
+            (false, vec![], Some(e.to_string()))
+        }
+    };
+    trade_log::record(Event::Exit {
+        mint: exit.mint.clone(),
+        venue,
+        reason: format!(
+            "presigned exit, prepared {:?} ago",
+            exit.prepared_at.elapsed()
+        ),
+        amount: exit.token_amount,
+        wallet: state.wallet.pubkey().to_string(),
+        landed,
+        signatures,
+        error,
+    });
+    Some(outcome)
+}
+
+/// Drop the pre-signed exit of a position that was closed some other way. The stale
+/// transaction is invalidated by advancing its nonce before the account is reused.
+pub async fn discard_exit(state: &AppState, mint: &str) -> Result<()> {
+    let Some(exit) = PRESIGNED_EXITS.write().await.remove(mint) else {
+        return Ok(());
+    };
+    nonce::advance_nonce(&state.rpc_client, &state.wallet, &exit.nonce_account)?;
+    nonce::release(&exit.nonce_account).await;
+    Ok(())
+}
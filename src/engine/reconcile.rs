@@ -0,0 +1,311 @@
+use std::{collections::HashMap, str::FromStr, sync::LazyLock};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use solana_account_decoder::UiAccountData;
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_sdk::signer::Signer;
+use tracing::warn;
+
+use crate::{
+    common::utils::{env_or, log_message, AppState},
+    dex::{pump::get_pump_info, raydium::get_pool_state_by_mint},
+    engine::{
+        exit,
+        position::{self, Fill, Position, Venue},
+        swap::SwapDirection,
+        wallets,
+    },
+    services::store::store,
+};
+
+/// Reconcile the wallet against the positions on startup (`RECONCILE`, default true)
+pub static RECONCILE: LazyLock<bool> = LazyLock::new(|| env_or("RECONCILE", true));
+
+/// Let the exit engine sell imported orphans (`RECONCILE_MANAGE_ORPHANS`, default false)
+pub static MANAGE_ORPHANS: LazyLock<bool> =
+    LazyLock::new(|| env_or("RECONCILE_MANAGE_ORPHANS", false));
+
+/// Target recorded for holdings the bot did not buy itself
+pub const ORPHAN_TARGET: &str = "orphan";
+
+// a holding that could not be priced is looked up again after this long, or once its amount
+// changes; dust and spam tokens would otherwise cost a pool scan on every startup
+const UNPRICED_RETRY_SECS: i64 = 24 * 60 * 60;
+
+/// Position whose ledger balance no longer matches the wallet
+#[derive(Debug, Clone)]
+pub struct Drift {
+    pub mint: String,
+    pub ledger: u64,
+    pub wallet: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileReport {
+    /// Holdings with no position, imported at their marked value
+    pub imported: Vec<Position>,
+    /// Holdings that could not be priced on pump or Raydium, left alone
+    pub unpriced: Vec<String>,
+    pub drifted: Vec<Drift>,
+    /// Holdings found unpriced on a recent startup, not looked up again yet
+    pub skipped: usize,
+}
+
+impl ReconcileReport {
+    pub fn summary(&self) -> String {
+        let drifted: Vec<String> = self
+            .drifted
+            .iter()
+            .map(|d| format!("{} {} -> {}", d.mint, d.ledger, d.wallet))
+            .collect();
+        format!(
+            "Reconcile: {} imported, {} unpriced [{}] ({} skipped), {} drifted [{}]",
+            self.imported.len(),
+            self.unpriced.len(),
+            self.unpriced.join(", "),
+            self.skipped,
+            self.drifted.len(),
+            drifted.join(", ")
+        )
+    }
+}
+
+/// Raw balances of every token account of the wallet, summed by mint.
+pub async fn wallet_holdings(state: &AppState) -> Result<HashMap<String, u64>> {
+    let owner = state.wallet.pubkey();
+    let mut holdings = HashMap::new();
+    for program_id in [spl_token::ID, spl_token_2022::ID] {
+        let accounts = state
+            .rpc_nonblocking_client
+            .get_token_accounts_by_owner(&owner, TokenAccountsFilter::ProgramId(program_id))
+            .await?;
+        for keyed in accounts {
+            let UiAccountData::Json(parsed) = keyed.account.data else {
+                return Err(anyhow!("unparsed token account {}", keyed.pubkey));
+            };
+            let info = &parsed.parsed["info"];
+            let mint = info["mint"].as_str().unwrap_or_default().to_string();
+            let amount = info["tokenAmount"]["amount"]
+                .as_str()
+                .and_then(|amount| u64::from_str(amount).ok())
+                .unwrap_or(0);
+            if mint == spl_token::native_mint::ID.to_string() {
+                continue;
+            }
+            *holdings.entry(mint).or_insert(0) += amount;
+        }
+    }
+    Ok(holdings)
+}
+
+/// Find where an orphan holding trades and what it would fetch there.
+async fn price_orphan(state: &AppState, mint: &str, amount: u64) -> Result<Position> {
+    let mut position = Position {
+        mint: mint.to_string(),
+        target: ORPHAN_TARGET.to_string(),
+        venue: Venue::Pump,
+        pool_id: None,
+        token_amount: amount,
+        cost_lamports: 0,
+        opened_at: Utc::now().timestamp(),
+    };
+    let on_curve = get_pump_info(state.rpc_client.clone(), mint)
+        .await
+        .map(|info| !info.complete)
+        .unwrap_or(false);
+    if !on_curve {
+        let (pool_id, _) = get_pool_state_by_mint(state.rpc_client.clone(), mint).await?;
+        position.venue = Venue::Raydium;
+        position.pool_id = Some(pool_id.to_string());
+    }
+    position.cost_lamports = exit::mark(state, &position).await?;
+    Ok(position)
+}
+
+/// Fill that resyncs a position to the wallet balance, `None` when they agree. It moves no
+/// lamports, so a drop in balance takes its share of the cost with it when applied and a rise
+/// comes at no cost.
+pub fn adjustment(position: &Position, wallet: u64, timestamp: i64) -> Option<Fill> {
+    let (direction, token_amount) = if wallet > position.token_amount {
+        (SwapDirection::Buy, wallet - position.token_amount)
+    } else if wallet < position.token_amount {
+        (SwapDirection::Sell, position.token_amount - wallet)
+    } else {
+        return None;
+    };
+    Some(Fill {
+        mint: position.mint.clone(),
+        target: position.target.clone(),
+        venue: position.venue,
+        direction,
+        token_amount,
+        lamports: 0,
+        realized: None,
+        timestamp,
+        signatures: vec![],
+        network_fee: 0,
+        priority_fee: 0,
+        tip: 0,
+        wallet: None,
+    })
+}
+
+// whether the same holding was found unpriced recently enough not to look it up again
+fn recently_unpriced(mint: &str, amount: u64, now: i64) -> bool {
+    store()
+        .and_then(|store| store.unpriced_at(mint, amount))
+        .ok()
+        .flatten()
+        .is_some_and(|checked_at| now - checked_at < UNPRICED_RETRY_SECS)
+}
+
+/// Compare the wallet's token accounts with the positions. Holdings without a position are
+/// imported as orphans with their marked value as cost basis, so their PnL starts at zero.
+/// Positions whose balance moved outside the bot are flagged and resynced to the wallet with an
+/// `adjustment` fill. Holdings that could not be priced are remembered and skipped until their
+/// amount changes or a day has passed.
+pub async fn reconcile(state: &AppState) -> Result<ReconcileReport> {
+    // positions are held across the execution wallets
+    let mut holdings: HashMap<String, u64> = HashMap::new();
+    for executor in wallets::executors(state).await {
+        for (mint, amount) in wallet_holdings(&executor).await? {
+            *holdings.entry(mint).or_insert(0) += amount;
+        }
+    }
+    let mut report = ReconcileReport::default();
+
+    for position in position::all().await {
+        let wallet = holdings.get(&position.mint).copied().unwrap_or(0);
+        let Some(mut fill) = adjustment(&position, wallet, Utc::now().timestamp()) else {
+            continue;
+        };
+        report.drifted.push(Drift {
+            mint: position.mint.clone(),
+            ledger: position.token_amount,
+            wallet,
+        });
+        // journaled too, so the PnL replay ends up with the same holding
+        position::apply(&mut fill, None).await;
+        if let Err(e) = store().and_then(|store| store.record_fill(&fill, None)) {
+            warn!(mint = %position.mint, error = %e, "could not journal the adjustment fill");
+        }
+    }
+
+    for (mint, amount) in holdings.iter() {
+        if *amount == 0 || position::get(mint).await.is_some() {
+            continue;
+        }
+        if recently_unpriced(mint, *amount, Utc::now().timestamp()) {
+            report.skipped += 1;
+            continue;
+        }
+        let orphan = match price_orphan(state, mint, *amount).await {
+            Ok(orphan) => orphan,
+            Err(e) => {
+                warn!(mint = %mint, error = %e, "could not price orphan holding");
+                if let Err(e) = store().and_then(|store| store.mark_unpriced(mint, *amount)) {
+                    warn!(mint = %mint, error = %e, "could not journal the unpriced holding");
+                }
+                report.unpriced.push(mint.clone());
+                continue;
+            }
+        };
+        // journal the import as a buy so the PnL replay has a cost basis for it
+        let mut fill = Fill {
+            mint: mint.clone(),
+            target: ORPHAN_TARGET.to_string(),
+            venue: orphan.venue,
+            direction: SwapDirection::Buy,
+            token_amount: *amount,
+            lamports: orphan.cost_lamports,
+            realized: None,
+            timestamp: orphan.opened_at,
+            signatures: vec![],
+            network_fee: 0,
+            priority_fee: 0,
+            tip: 0,
+            wallet: None,
+        };
+        position::apply(&mut fill, orphan.pool_id.clone()).await;
+        if let Err(e) = store().and_then(|store| store.record_fill(&fill, None)) {
+            warn!(mint = %mint, error = %e, "could not journal the orphan import");
+        }
+        report.imported.push(orphan);
+    }
+
+    log_message(&report.summary());
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(mint: &str, token_amount: u64, cost_lamports: u64) -> Position {
+        Position {
+            mint: mint.to_string(),
+            target: "target".to_string(),
+            venue: Venue::Pump,
+            pool_id: None,
+            token_amount,
+            cost_lamports,
+            opened_at: 100,
+        }
+    }
+
+    #[test]
+    fn a_matching_balance_needs_no_adjustment() {
+        assert!(adjustment(&position("mint", 1_000, 1_000_000), 1_000, 200).is_none());
+    }
+
+    #[test]
+    fn drift_is_adjusted_by_the_difference_at_no_cost() {
+        let held = position("mint", 1_000, 1_000_000);
+        let fill = adjustment(&held, 400, 200).unwrap();
+        assert_eq!(fill.direction, SwapDirection::Sell);
+        assert_eq!(
+            (fill.token_amount, fill.lamports, fill.timestamp),
+            (600, 0, 200)
+        );
+        assert_eq!((fill.target.as_str(), fill.venue), ("target", Venue::Pump));
+        assert!(fill.signatures.is_empty());
+
+        let fill = adjustment(&held, 1_500, 200).unwrap();
+        assert_eq!(fill.direction, SwapDirection::Buy);
+        assert_eq!((fill.token_amount, fill.lamports), (500, 0));
+
+        let fill = adjustment(&held, 0, 200).unwrap();
+        assert_eq!(
+            (fill.direction, fill.token_amount),
+            (SwapDirection::Sell, 1_000)
+        );
+    }
+
+    #[tokio::test]
+    async fn applying_an_adjustment_resyncs_the_position() {
+        let mint = solana_sdk::pubkey::Pubkey::new_unique().to_string();
+        let held = position(&mint, 1_000, 1_000_000);
+        position::POSITIONS
+            .write()
+            .await
+            .insert(mint.clone(), held.clone());
+
+        // tokens gone: their share of the cost is written off as realized
+        let mut fill = adjustment(&held, 400, 200).unwrap();
+        position::apply(&mut fill, None).await;
+        assert_eq!(fill.realized, Some(-600_000));
+        let synced = position::get(&mint).await.unwrap();
+        assert_eq!((synced.token_amount, synced.cost_lamports), (400, 400_000));
+
+        // tokens appeared: the holding grows, the cost does not
+        let mut fill = adjustment(&synced, 900, 300).unwrap();
+        position::apply(&mut fill, None).await;
+        let synced = position::get(&mint).await.unwrap();
+        assert_eq!((synced.token_amount, synced.cost_lamports), (900, 400_000));
+
+        let mut fill = adjustment(&synced, 0, 400).unwrap();
+        position::apply(&mut fill, None).await;
+        assert!(position::get(&mint).await.is_none());
+    }
+}
@@ -0,0 +1,486 @@
+use std::{collections::VecDeque, sync::LazyLock, time::Duration};
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::{sync::Mutex, time::Instant};
+
+use anyhow::Result;
+
+use crate::{
+    common::utils::{env_checked, log_message},
+    engine::position::{self, Position},
+    services::store::store,
+};
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+// decisions kept in memory for inspection
+const DECISION_HISTORY: usize = 200;
+
+pub static RISK: LazyLock<Mutex<RiskState>> = LazyLock::new(|| Mutex::new(RiskState::new()));
+
+// The config file gives amounts in SOL and the cool-down in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskConfig {
+    /// Largest buy in lamports, bigger copies are scaled down
+    #[serde(with = "sol")]
+    pub max_sol_per_trade: u64,
+    pub max_open_positions: usize,
+    /// Largest cost basis held in a single mint, in lamports
+    #[serde(rename = "max_sol_per_mint", with = "sol")]
+    pub max_exposure_per_mint: u64,
+    /// Largest cost basis held in positions copied from a single target, in lamports
+    #[serde(rename = "max_sol_per_target", with = "sol")]
+    pub max_exposure_per_target: u64,
+    /// Lamports always left in the wallet for fees and rent
+    #[serde(with = "sol")]
+    pub min_sol_reserve: u64,
+    /// Realized loss in lamports after which buying halts until the next day, 0 for no limit
+    #[serde(rename = "max_daily_loss_sol", with = "sol")]
+    pub max_daily_loss: u64,
+    /// Failed copies in a row that trigger the cool-down
+    pub max_consecutive_failures: u32,
+    #[serde(rename = "cooldown_secs", with = "secs")]
+    pub cooldown: Duration,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        Self {
+            max_sol_per_trade: 500_000_000,
+            max_open_positions: 10,
+            max_exposure_per_mint: 1_000_000_000,
+            max_exposure_per_target: 3_000_000_000,
+            min_sol_reserve: 50_000_000,
+            max_daily_loss: 2_000_000_000,
+            max_consecutive_failures: 3,
+            cooldown: Duration::from_secs(300),
+        }
+    }
+}
+
+fn sol_to_lamports(sol: f64) -> u64 {
+    (sol * LAMPORTS_PER_SOL) as u64
+}
+
+mod sol {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(lamports: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(*lamports as f64 / LAMPORTS_PER_SOL)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let sol = f64::deserialize(deserializer)?;
+        if sol < 0.0 {
+            return Err(serde::de::Error::custom("SOL amount can't be negative"));
+        }
+        Ok(sol_to_lamports(sol))
+    }
+}
+
+mod secs {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_secs(u64::deserialize(deserializer)?))
+    }
+}
+
+impl RiskConfig {
+    pub fn from_env() -> Self {
+        Self::default().env_overrides(&mut vec![])
+    }
+
+    /// Replace the settings given in the environment.
+    pub fn env_overrides(self, errors: &mut Vec<String>) -> Self {
+        let mut sol = |key: &str, value: u64| {
+            sol_to_lamports(env_checked(key, value as f64 / LAMPORTS_PER_SOL, errors))
+        };
+        let max_sol_per_trade = sol("RISK_MAX_SOL_PER_TRADE", self.max_sol_per_trade);
+        let max_exposure_per_mint = sol("RISK_MAX_SOL_PER_MINT", self.max_exposure_per_mint);
+        let max_exposure_per_target = sol("RISK_MAX_SOL_PER_TARGET", self.max_exposure_per_target);
+        let min_sol_reserve = sol("RISK_MIN_SOL_RESERVE", self.min_sol_reserve);
+        let max_daily_loss = sol("RISK_MAX_DAILY_LOSS_SOL", self.max_daily_loss);
+        Self {
+            max_sol_per_trade,
+            max_open_positions: env_checked(
+                "RISK_MAX_OPEN_POSITIONS",
+                self.max_open_positions,
+                errors,
+            ),
+            max_exposure_per_mint,
+            max_exposure_per_target,
+            min_sol_reserve,
+            max_daily_loss,
+            max_consecutive_failures: env_checked(
+                "RISK_MAX_CONSECUTIVE_FAILURES",
+                self.max_consecutive_failures,
+                errors,
+            ),
+            cooldown: Duration::from_secs(env_checked(
+                "RISK_COOLDOWN_SECS",
+                self.cooldown.as_secs(),
+                errors,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskDecision {
+    pub at: DateTime<Local>,
+    pub target: String,
+    pub mint: String,
+    /// Lamports the copy asked for
+    pub requested: u64,
+    /// Lamports the copy may spend, zero when rejected
+    pub approved: u64,
+    pub reasons: Vec<String>,
+}
+
+impl RiskDecision {
+    pub fn allowed(&self) -> bool {
+        self.approved > 0
+    }
+}
+
+#[derive(Debug)]
+pub struct RiskState {
+    day: NaiveDate,
+    /// Realized profit of the day in lamports, negative for a loss
+    pub realized_today: i64,
+    pub consecutive_failures: u32,
+    pub cooldown_until: Option<Instant>,
+    pub decisions: VecDeque<RiskDecision>,
+}
+
+impl RiskState {
+    fn new() -> Self {
+        Self {
+            day: Local::now().date_naive(),
+            realized_today: 0,
+            consecutive_failures: 0,
+            cooldown_until: None,
+            decisions: VecDeque::new(),
+        }
+    }
+
+    fn roll_day(&mut self, today: NaiveDate) {
+        if today != self.day {
+            self.day = today;
+            self.realized_today = 0;
+        }
+    }
+
+    pub fn halted(&self, config: &RiskConfig) -> bool {
+        config.max_daily_loss > 0 && self.realized_today <= -(config.max_daily_loss as i64)
+    }
+
+    /// Cap a buy against the limits, given the positions open now.
+    fn decide(
+        &self,
+        target: &str,
+        mint: &str,
+        requested: u64,
+        spendable: u64,
+        open: &[Position],
+        config: &RiskConfig,
+    ) -> RiskDecision {
+        let mut reasons = vec![];
+        let mut approved = requested;
+        let mut cap = |limit: u64, reason: String, approved: &mut u64| {
+            if *approved > limit {
+                *approved = limit;
+                reasons.push(reason);
+            }
+        };
+
+        if self.halted(config) {
+            cap(
+                0,
+                format!(
+                    "daily loss limit hit: {:.3} SOL realized today",
+                    self.realized_today as f64 / LAMPORTS_PER_SOL
+                ),
+                &mut approved,
+            );
+        }
+        if let Some(until) = self.cooldown_until {
+            if until > Instant::now() {
+                cap(
+                    0,
+                    format!(
+                        "cooling down for {}s after {} failed copies",
+                        (until - Instant::now()).as_secs(),
+                        self.consecutive_failures
+                    ),
+                    &mut approved,
+                );
+            }
+        }
+
+        if !open.iter().any(|p| p.mint == mint) && open.len() >= config.max_open_positions {
+            cap(
+                0,
+                format!("{} positions already open", open.len()),
+                &mut approved,
+            );
+        }
+        cap(
+            config.max_sol_per_trade,
+            "max SOL per trade".to_string(),
+            &mut approved,
+        );
+        let mint_exposure: u64 = open
+            .iter()
+            .filter(|p| p.mint == mint)
+            .map(|p| p.cost_lamports)
+            .sum();
+        cap(
+            config.max_exposure_per_mint.saturating_sub(mint_exposure),
+            format!(
+                "mint exposure {:.3} SOL",
+                mint_exposure as f64 / LAMPORTS_PER_SOL
+            ),
+            &mut approved,
+        );
+        let target_exposure: u64 = open
+            .iter()
+            .filter(|p| p.target == target)
+            .map(|p| p.cost_lamports)
+            .sum();
+        cap(
+            config
+                .max_exposure_per_target
+                .saturating_sub(target_exposure),
+            format!(
+                "target exposure {:.3} SOL",
+                target_exposure as f64 / LAMPORTS_PER_SOL
+            ),
+            &mut approved,
+        );
+        cap(
+            spendable,
+            format!(
+                "spendable balance {:.3} SOL above the reserve",
+                spendable as f64 / LAMPORTS_PER_SOL
+            ),
+            &mut approved,
+        );
+
+        RiskDecision {
+            at: Local::now(),
+            target: target.to_string(),
+            mint: mint.to_string(),
+            requested,
+            approved,
+            reasons,
+        }
+    }
+
+    /// Count a copy's outcome. Returns whether it started the cool-down.
+    fn record_copy(&mut self, success: bool, config: &RiskConfig) -> bool {
+        if success {
+            self.consecutive_failures = 0;
+            return false;
+        }
+        self.consecutive_failures += 1;
+        if self.consecutive_failures < config.max_consecutive_failures {
+            return false;
+        }
+        self.cooldown_until = Some(Instant::now() + config.cooldown);
+        true
+    }
+}
+
+/// Pick up the profit realized today by an earlier run, so a restart does not lift the daily
+/// loss limit. Returns it in lamports.
+pub async fn restore() -> Result<i64> {
+    let midnight = Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|midnight| midnight.timestamp())
+        .unwrap_or_default();
+    let realized = store()?
+        .fills(midnight, None, None)?
+        .iter()
+        .filter_map(|fill| fill.realized)
+        .sum();
+    let mut state = RISK.lock().await;
+    state.roll_day(Local::now().date_naive());
+    state.realized_today = realized;
+    Ok(realized)
+}
+
+/// Decide how much of a copy buy may go through. Limits scale the buy down where they can
+/// and reject it when nothing is left. `spendable` is what the executing wallets hold above
+/// their reserve, see `wallets::spendable`.
+pub async fn check_buy(
+    target: &str,
+    mint: &str,
+    requested: u64,
+    spendable: u64,
+    config: &RiskConfig,
+) -> RiskDecision {
+    let open = position::all().await;
+    let mut state = RISK.lock().await;
+    state.roll_day(Local::now().date_naive());
+    let decision = state.decide(target, mint, requested, spendable, &open, config);
+    log_message(&format!(
+        "Risk: {} {} requested {} approved {} [{}]",
+        decision.target,
+        decision.mint,
+        decision.requested,
+        decision.approved,
+        decision.reasons.join(", ")
+    ));
+    state.decisions.push_back(decision.clone());
+    if state.decisions.len() > DECISION_HISTORY {
+        state.decisions.pop_front();
+    }
+    decision
+}
+
+/// Track the outcome of a copy; enough failures in a row start the cool-down.
+pub async fn record_copy(success: bool, config: &RiskConfig) {
+    let mut state = RISK.lock().await;
+    if state.record_copy(success, config) {
+        log_message(&format!(
+            "Risk: {} failed copies in a row, buying paused for {}s",
+            state.consecutive_failures,
+            config.cooldown.as_secs()
+        ));
+    }
+}
+
+pub async fn record_realized(realized: i64, config: &RiskConfig) {
+    let mut state = RISK.lock().await;
+    state.roll_day(Local::now().date_naive());
+    state.realized_today += realized;
+    if state.halted(config) {
+        log_message(&format!(
+            "Risk: daily loss limit hit ({:.3} SOL), buying halted",
+            state.realized_today as f64 / LAMPORTS_PER_SOL
+        ));
+    }
+}
+
+/// Most recent decisions, oldest first.
+pub async fn decisions() -> Vec<RiskDecision> {
+    RISK.lock().await.decisions.iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(mint: &str, target: &str, cost_lamports: u64) -> Position {
+        Position {
+            mint: mint.to_string(),
+            target: target.to_string(),
+            venue: position::Venue::Pump,
+            pool_id: None,
+            token_amount: 1_000,
+            cost_lamports,
+            opened_at: 0,
+        }
+    }
+
+    #[test]
+    fn buys_are_capped_by_the_tightest_limit() {
+        let state = RiskState::new();
+        let config = RiskConfig::default();
+        let decision = state.decide("t", "m", 2_000_000_000, u64::MAX, &[], &config);
+        assert_eq!(decision.approved, config.max_sol_per_trade);
+        assert_eq!(decision.reasons, vec!["max SOL per trade"]);
+
+        let open = [position("m", "t", 800_000_000)];
+        let decision = state.decide("t", "m", 400_000_000, u64::MAX, &open, &config);
+        assert_eq!(decision.approved, 200_000_000);
+        assert!(decision.reasons[0].starts_with("mint exposure"));
+
+        let open = [
+            position("a", "t", 1_000_000_000),
+            position("b", "t", 1_900_000_000),
+        ];
+        let decision = state.decide("t", "m", 400_000_000, u64::MAX, &open, &config);
+        assert_eq!(decision.approved, 100_000_000);
+        assert!(decision.reasons[0].starts_with("target exposure"));
+
+        let decision = state.decide("t", "m", 400_000_000, 50_000_000, &[], &config);
+        assert_eq!(decision.approved, 50_000_000);
+        assert!(decision.reasons[0].starts_with("spendable balance"));
+    }
+
+    #[test]
+    fn buys_into_a_new_mint_stop_at_the_open_position_limit() {
+        let state = RiskState::new();
+        let config = RiskConfig {
+            max_open_positions: 1,
+            ..RiskConfig::default()
+        };
+        let open = [position("m", "t", 100)];
+        assert!(!state
+            .decide("t", "other", 100, u64::MAX, &open, &config)
+            .allowed());
+        assert!(state
+            .decide("t", "m", 100, u64::MAX, &open, &config)
+            .allowed());
+    }
+
+    #[test]
+    fn the_daily_loss_limit_halts_buying_and_zero_disables_it() {
+        let mut state = RiskState::new();
+        let config = RiskConfig::default();
+        assert!(!state.halted(&config));
+        state.realized_today = -(config.max_daily_loss as i64) + 1;
+        assert!(!state.halted(&config));
+        state.realized_today = -(config.max_daily_loss as i64);
+        assert!(state.halted(&config));
+        assert!(!state
+            .decide("t", "m", 100, u64::MAX, &[], &config)
+            .allowed());
+
+        let unlimited = RiskConfig {
+            max_daily_loss: 0,
+            ..RiskConfig::default()
+        };
+        assert!(!RiskState::new().halted(&unlimited));
+        assert!(!state.halted(&unlimited));
+    }
+
+    #[test]
+    fn a_new_day_resets_the_realized_profit() {
+        let mut state = RiskState::new();
+        let today = state.day;
+        state.realized_today = -5;
+        state.roll_day(today);
+        assert_eq!(state.realized_today, -5);
+        state.roll_day(today.succ_opt().unwrap());
+        assert_eq!(state.realized_today, 0);
+    }
+
+    #[test]
+    fn failures_in_a_row_start_the_cooldown() {
+        let mut state = RiskState::new();
+        let config = RiskConfig::default();
+        assert!(!state.record_copy(false, &config));
+        assert!(!state.record_copy(false, &config));
+        assert!(!state.record_copy(true, &config));
+        assert_eq!(state.consecutive_failures, 0);
+        for _ in 1..config.max_consecutive_failures {
+            assert!(!state.record_copy(false, &config));
+        }
+        assert!(state.record_copy(false, &config));
+        assert!(state.cooldown_until.is_some());
+        assert!(!state
+            .decide("t", "m", 100, u64::MAX, &[], &config)
+            .allowed());
+    }
+}
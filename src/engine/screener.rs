@@ -0,0 +1,365 @@
+use std::{collections::HashSet, str::FromStr, sync::Arc, sync::LazyLock};
+
+use anyhow::Result;
+use solana_sdk::{program_option::COption, pubkey::Pubkey, signature::Keypair};
+use spl_token_2022::extension::{
+    non_transferable::NonTransferable, permanent_delegate::PermanentDelegate,
+    transfer_fee::TransferFeeConfig, transfer_hook, BaseStateWithExtensions,
+};
+
+use crate::{
+    common::utils::{env_or, log_message},
+    core::token::get_mint_info,
+    dex::{
+        pump::{get_pump_info, PumpInfo, PUMP_PROGRAM},
+        raydium::{AMM_PROGRAM, RAYDIUM_AUTHORITY_V4},
+    },
+};
+
+pub static SCREENER_CONFIG: LazyLock<ScreenerConfig> = LazyLock::new(ScreenerConfig::from_env);
+
+#[derive(Debug, Clone)]
+pub struct ScreenerConfig {
+    /// Screen every mint before copying a buy
+    pub enabled: bool,
+    /// Minimum score (0-100) a mint needs to be bought
+    pub min_score: u32,
+    pub reject_mint_authority: bool,
+    pub reject_freeze_authority: bool,
+    pub reject_permanent_delegate: bool,
+    /// Highest Token-2022 transfer fee accepted, in basis points
+    pub max_transfer_fee_bps: u16,
+    /// Largest share of supply a single holder may own, in percent (curve and pools excluded)
+    pub max_top_holder_pct: f64,
+    /// Largest share of supply the ten biggest holders may own, in percent (curve and pools
+    /// excluded)
+    pub max_top10_pct: f64,
+    /// Accepted range of pump bonding curve progress, in percent
+    pub min_curve_progress: f64,
+    pub max_curve_progress: f64,
+}
+
+impl Default for ScreenerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_score: 60,
+            reject_mint_authority: true,
+            reject_freeze_authority: true,
+            reject_permanent_delegate: true,
+            max_transfer_fee_bps: 100,
+            max_top_holder_pct: 20.0,
+            max_top10_pct: 50.0,
+            min_curve_progress: 0.0,
+            max_curve_progress: 100.0,
+        }
+    }
+}
+
+impl ScreenerConfig {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            enabled: env_or("SCREEN", default.enabled),
+            min_score: env_or("SCREEN_MIN_SCORE", default.min_score),
+            reject_mint_authority: env_or(
+                "SCREEN_REJECT_MINT_AUTHORITY",
+                default.reject_mint_authority,
+            ),
+            reject_freeze_authority: env_or(
+                "SCREEN_REJECT_FREEZE_AUTHORITY",
+                default.reject_freeze_authority,
+            ),
+            reject_permanent_delegate: env_or(
+                "SCREEN_REJECT_PERMANENT_DELEGATE",
+                default.reject_permanent_delegate,
+            ),
+            max_transfer_fee_bps: env_or(
+                "SCREEN_MAX_TRANSFER_FEE_BPS",
+                default.max_transfer_fee_bps,
+            ),
+            max_top_holder_pct: env_or("SCREEN_MAX_TOP_HOLDER_PCT", default.max_top_holder_pct),
+            max_top10_pct: env_or("SCREEN_MAX_TOP10_PCT", default.max_top10_pct),
+            min_curve_progress: env_or("SCREEN_MIN_CURVE_PROGRESS", default.min_curve_progress),
+            max_curve_progress: env_or("SCREEN_MAX_CURVE_PROGRESS", default.max_curve_progress),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub check: &'static str,
+    pub penalty: u32,
+    /// Rejects the mint whatever its score
+    pub fatal: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScreenReport {
+    pub mint: String,
+    pub score: u32,
+    pub findings: Vec<Finding>,
+    pub accepted: bool,
+}
+
+impl ScreenReport {
+    pub fn summary(&self) -> String {
+        let findings: Vec<String> = self
+            .findings
+            .iter()
+            .map(|f| format!("{}: {}", f.check, f.detail))
+            .collect();
+        format!(
+            "Screen {} score {} => {} [{}]",
+            self.mint,
+            self.score,
+            if self.accepted { "accept" } else { "reject" },
+            findings.join(", ")
+        )
+    }
+}
+
+struct Screen<'a> {
+    config: &'a ScreenerConfig,
+    findings: Vec<Finding>,
+}
+
+impl Screen<'_> {
+    fn flag(&mut self, check: &'static str, penalty: u32, fatal: bool, detail: String) {
+        self.findings.push(Finding {
+            check,
+            penalty,
+            fatal,
+            detail,
+        });
+    }
+
+    fn report(self, mint: &str) -> ScreenReport {
+        let penalty: u32 = self.findings.iter().map(|f| f.penalty).sum();
+        let score = 100u32.saturating_sub(penalty);
+        let accepted = score >= self.config.min_score && !self.findings.iter().any(|f| f.fatal);
+        ScreenReport {
+            mint: mint.to_string(),
+            score,
+            findings: self.findings,
+            accepted,
+        }
+    }
+}
+
+/// Screen a mint before copying a buy into it. A failed holder lookup counts against the mint
+/// rather than aborting the screen.
+pub async fn screen(
+    rpc_nonblocking_client: Arc<solana_client::nonblocking::rpc_client::RpcClient>,
+    rpc_client: Arc<solana_client::rpc_client::RpcClient>,
+    keypair: Arc<Keypair>,
+    mint: &str,
+    config: &ScreenerConfig,
+) -> Result<ScreenReport> {
+    let mint_pubkey = Pubkey::from_str(mint)?;
+    let mut screen = Screen {
+        config,
+        findings: vec![],
+    };
+
+    let mint_info = get_mint_info(rpc_nonblocking_client.clone(), keypair, &mint_pubkey).await?;
+    if let COption::Some(authority) = mint_info.base.mint_authority {
+        screen.flag(
+            "mint_authority",
+            40,
+            config.reject_mint_authority,
+            format!("supply can still be minted by {}", authority),
+        );
+    }
+    if let COption::Some(authority) = mint_info.base.freeze_authority {
+        screen.flag(
+            "freeze_authority",
+            40,
+            config.reject_freeze_authority,
+            format!("accounts can be frozen by {}", authority),
+        );
+    }
+    if let Ok(delegate) = mint_info.get_extension::<PermanentDelegate>() {
+        if let Some(delegate) = Option::<Pubkey>::from(delegate.delegate) {
+            screen.flag(
+                "permanent_delegate",
+                50,
+                config.reject_permanent_delegate,
+                format!("balances can be moved by {}", delegate),
+            );
+        }
+    }
+    if mint_info.get_extension::<NonTransferable>().is_ok() {
+        screen.flag(
+            "non_transferable",
+            100,
+            true,
+            "tokens cannot be sold".to_string(),
+        );
+    }
+    if let Some(hook_program) = transfer_hook::get_program_id(&mint_info) {
+        screen.flag(
+            "transfer_hook",
+            100,
+            true,
+            format!(
+                "transfers run program {}, which swaps cannot invoke",
+                hook_program
+            ),
+        );
+    }
+    if let Ok(transfer_fee) = mint_info.get_extension::<TransferFeeConfig>() {
+        let epoch = rpc_nonblocking_client.get_epoch_info().await?.epoch;
+        let fee_bps = u16::from(transfer_fee.get_epoch_fee(epoch).transfer_fee_basis_points);
+        if fee_bps > 0 {
+            screen.flag(
+                "transfer_fee",
+                (fee_bps as u32 / 10).min(50),
+                fee_bps > config.max_transfer_fee_bps,
+                format!("{} bps withheld on every transfer", fee_bps),
+            );
+        }
+    }
+
+    let pump_info = get_pump_info(rpc_client, mint).await.ok();
+    match largest_holders(&rpc_nonblocking_client, &mint_pubkey).await {
+        Ok(holders) => {
+            let supply = mint_info.base.supply.max(1) as f64;
+            let shares: Vec<f64> = holders
+                .iter()
+                .map(|holder| {
+                    u64::from_str(&holder.amount.amount).unwrap_or_default() as f64 * 100.0 / supply
+                })
+                .collect();
+            let top = shares.first().copied().unwrap_or_default();
+            let top10: f64 = shares.iter().take(10).sum();
+            if top > config.max_top_holder_pct {
+                screen.flag(
+                    "top_holder",
+                    25,
+                    false,
+                    format!("largest holder owns {:.1}%", top),
+                );
+            }
+            if top10 > config.max_top10_pct {
+                screen.flag(
+                    "top10_holders",
+                    25,
+                    false,
+                    format!("ten largest holders own {:.1}%", top10),
+                );
+            }
+        }
+        Err(e) => screen.flag(
+            "holders",
+            20,
+            false,
+            format!("largest accounts unavailable: {}", e),
+        ),
+    }
+
+    if let Some(pump_info) = pump_info.as_ref() {
+        check_curve(&mut screen, pump_info);
+    }
+
+    let report = screen.report(mint);
+    log_message(&report.summary());
+    Ok(report)
+}
+
+/// Largest token accounts of the mint, without the bonding curve and the pool vaults: they
+/// hold the liquidity, not a holder's stake.
+async fn largest_holders(
+    client: &solana_client::nonblocking::rpc_client::RpcClient,
+    mint: &Pubkey,
+) -> Result<Vec<solana_client::rpc_response::RpcTokenAccountBalance>> {
+    let holders = client.get_token_largest_accounts(mint).await?;
+    let addresses = holders
+        .iter()
+        .map(|holder| Pubkey::from_str(&holder.address))
+        .collect::<Result<Vec<_>, _>>()?;
+    // the token account owner sits right after the mint, in both token programs
+    let authorities: Vec<Option<Pubkey>> = client
+        .get_multiple_accounts(&addresses)
+        .await?
+        .into_iter()
+        .map(|account| {
+            account
+                .and_then(|account| account.data.get(32..64).map(|owner| owner.to_vec()))
+                .and_then(|owner| Pubkey::try_from(owner).ok())
+        })
+        .collect();
+    let known: Vec<Pubkey> = authorities.iter().flatten().copied().collect();
+    let programs: Vec<Option<Pubkey>> = client
+        .get_multiple_accounts(&known)
+        .await?
+        .into_iter()
+        .map(|account| account.map(|account| account.owner))
+        .collect();
+    let pools: HashSet<Pubkey> = known
+        .iter()
+        .zip(programs)
+        .filter(|(authority, program)| pool_authority(authority, program.as_ref()))
+        .map(|(authority, _)| *authority)
+        .collect();
+    Ok(holders
+        .into_iter()
+        .zip(authorities)
+        .filter(|(_, authority)| !authority.is_some_and(|authority| pools.contains(&authority)))
+        .map(|(holder, _)| holder)
+        .collect())
+}
+
+// the Raydium v4 vault authority, or an account of the pump or Raydium programs (the bonding
+// curve, an AMM pool)
+fn pool_authority(authority: &Pubkey, program: Option<&Pubkey>) -> bool {
+    let owned_by = |id: &str| Pubkey::from_str(id).is_ok_and(|id| program == Some(&id));
+    Pubkey::from_str(RAYDIUM_AUTHORITY_V4).is_ok_and(|id| *authority == id)
+        || owned_by(PUMP_PROGRAM)
+        || owned_by(AMM_PROGRAM)
+}
+
+fn check_curve(screen: &mut Screen, pump_info: &PumpInfo) {
+    if pump_info.complete {
+        return;
+    }
+    let progress = pump_info.curve_progress();
+    if progress < screen.config.min_curve_progress || progress > screen.config.max_curve_progress {
+        screen.flag(
+            "curve_progress",
+            100,
+            true,
+            format!(
+                "bonding curve {:.1}% outside {:.1}%..{:.1}%",
+                progress, screen.config.min_curve_progress, screen.config.max_curve_progress
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_authorities_are_excluded() {
+        let raydium = Pubkey::from_str(RAYDIUM_AUTHORITY_V4).unwrap();
+        let pump = Pubkey::from_str(PUMP_PROGRAM).unwrap();
+        let amm = Pubkey::from_str(AMM_PROGRAM).unwrap();
+        let curve = Pubkey::new_unique();
+        assert!(pool_authority(&raydium, None));
+        assert!(pool_authority(&curve, Some(&pump)));
+        assert!(pool_authority(&curve, Some(&amm)));
+    }
+
+    #[test]
+    fn wallets_are_holders() {
+        let wallet = Pubkey::new_unique();
+        assert!(!pool_authority(
+            &wallet,
+            Some(&solana_sdk::system_program::id())
+        ));
+        assert!(!pool_authority(&wallet, None));
+    }
+}
@@ -1,17 +1,22 @@
 use std::sync::Arc;
 
-use crate::common::utils::AppState;
+use crate::common::{config::config, utils::AppState};
 use crate::dex::pump::Pump;
 use crate::dex::raydium::Raydium;
-use anyhow::Result;
+use crate::engine::{
+    paper::{self, PAPER_CONFIG},
+    position::Venue,
+    presign::{holding, sol_balance},
+};
+use anyhow::{anyhow, Result};
 use clap::ValueEnum;
 use jito_json_rpc_client::jsonrpc_client::rpc_client::RpcClient as JitoRpcClient;
 use raydium_amm::state::AmmInfo;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
 use tokio::time::Instant;
 
-#[derive(ValueEnum, Debug, Clone, Deserialize)]
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SwapDirection {
     #[serde(rename = "buy")]
     Buy,
@@ -27,7 +32,7 @@ impl From<SwapDirection> for u8 {
     }
 }
 
-#[derive(ValueEnum, Debug, Clone, Deserialize)]
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 pub enum SwapInType {
     /// Quantity
     #[serde(rename = "qty")]
@@ -37,35 +42,68 @@ pub enum SwapInType {
     Pct,
 }
 
+/// Amount a swap trades: `amount_in` itself for `Qty`, or for `Pct` that percentage of our
+/// token balance on a sell and of the SOL above the risk reserve on a buy.
+pub async fn resolve_amount(
+    state: &AppState,
+    mint: &str,
+    swap_direction: SwapDirection,
+    in_type: SwapInType,
+    amount_in: u64,
+) -> Result<u64> {
+    match in_type {
+        SwapInType::Qty => Ok(amount_in),
+        SwapInType::Pct => {
+            if amount_in == 0 || amount_in > 100 {
+                return Err(anyhow!("Pct: {} is not within 1..=100", amount_in));
+            }
+            let available = match swap_direction {
+                SwapDirection::Sell => holding(state, mint).await?,
+                SwapDirection::Buy => sol_balance(state)
+                    .await?
+                    .saturating_sub(config().risk.min_sol_reserve),
+            };
+            let amount = (available as u128 * amount_in as u128 / 100) as u64;
+            if amount == 0 {
+                return Err(anyhow!("Pct: nothing to {:?} for {}", swap_direction, mint));
+            }
+            Ok(amount)
+        }
+    }
+}
+
 pub async fn pump_swap(
     state: AppState,
     amount_in: u64,
-    swap_direction: &str,
+    in_type: SwapInType,
+    swap_direction: SwapDirection,
     slippage: u64,
     mint: &str,
+    simulate: bool,
     jito_client: Arc<JitoRpcClient>,
     timestamp: Instant,
 ) -> Result<Vec<String>> {
-    let swap_direction = match swap_direction {
-        "buy" => SwapDirection::Buy,
-        "sell" => SwapDirection::Sell,
-        _ => todo!(),
-    };
-    let in_type = "qty";
-    let use_jito = true;
-    let in_type = match in_type {
-        "qty" => SwapInType::Qty,
-        "pct" => SwapInType::Pct,
-        _ => todo!(),
-    };
+    let amount_in = resolve_amount(&state, mint, swap_direction, in_type, amount_in).await?;
+    if PAPER_CONFIG.enabled {
+        return paper::execute(
+            &state,
+            Venue::Pump,
+            mint,
+            None,
+            swap_direction,
+            amount_in,
+            slippage,
+        )
+        .await;
+    }
     let swapx = Pump::new(state.rpc_nonblocking_client, state.rpc_client, state.wallet);
-    println!("2.2: {:#?}", timestamp.elapsed());
     let res = match swapx
         .swap(
             mint,
             amount_in,
             swap_direction,
             slippage,
+            simulate,
             jito_client.clone(),
             timestamp.clone(),
         )
@@ -82,21 +120,30 @@ pub async fn pump_swap(
 pub async fn raydium_swap(
     state: AppState,
     amount_in: u64,
-    swap_direction: &str,
+    in_type: SwapInType,
+    swap_direction: SwapDirection,
     pool_id: String,
     slippage: u64,
     mint: &str,
+    simulate: bool,
     jito_client: Arc<JitoRpcClient>,
     timestamp: Instant,
 ) -> Result<Vec<String>> {
-    let swap_direction = match swap_direction {
-        "buy" => SwapDirection::Buy,
-        "sell" => SwapDirection::Sell,
-        _ => todo!(),
-    };
+    let amount_in = resolve_amount(&state, mint, swap_direction, in_type, amount_in).await?;
 
+    if PAPER_CONFIG.enabled {
+        return paper::execute(
+            &state,
+            Venue::Raydium,
+            mint,
+            Some(pool_id),
+            swap_direction,
+            amount_in,
+            slippage,
+        )
+        .await;
+    }
     let swapx = Raydium::new(state.rpc_nonblocking_client, state.rpc_client, state.wallet);
-    println!("2.2: {:#?}", timestamp.elapsed());
     let res = match swapx
         .swap_by_mint(
             mint,
@@ -104,6 +151,7 @@ pub async fn raydium_swap(
             amount_in,
             pool_id,
             slippage,
+            simulate,
             timestamp.clone(),
             jito_client.clone(),
         )
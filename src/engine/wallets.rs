@@ -0,0 +1,366 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::{
+    common::{
+        config::{config, Allocation},
+        keystore::load_keypair,
+        utils::{log_message, AppState},
+    },
+    core::sim::SIGNATURE_FEE,
+    engine::{
+        paper::PAPER_CONFIG,
+        presign::{holding, sol_balance},
+        swap::SwapDirection,
+    },
+};
+
+/// Execution wallet of the pool
+#[derive(Clone)]
+pub struct PoolWallet {
+    pub label: String,
+    pub keypair: Arc<Keypair>,
+    /// Targets this wallet copies under `per-target`
+    pub targets: Vec<String>,
+}
+
+/// Part of a copy and the wallet that executes it
+pub struct Leg {
+    pub state: AppState,
+    pub amount: u64,
+}
+
+static POOL: LazyLock<RwLock<Vec<PoolWallet>>> = LazyLock::new(|| RwLock::new(vec![]));
+// next wallet under `round-robin`
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+/// Last known lamports of each execution wallet
+pub static BALANCES: LazyLock<RwLock<HashMap<Pubkey, u64>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Whether copies run on the pool rather than the main wallet. Paper trading has a single
+/// virtual wallet, so it always uses the main one.
+pub fn pooled() -> bool {
+    config().wallet.allocation != Allocation::Single && !PAPER_CONFIG.enabled
+}
+
+/// Load the pool wallets of the config. Returns how many were loaded.
+pub async fn init() -> Result<usize> {
+    let config = config();
+    let mut pool = vec![];
+    for (i, wallet) in config.wallet.pool.iter().enumerate() {
+        pool.push(PoolWallet {
+            label: wallet
+                .label
+                .clone()
+                .unwrap_or_else(|| format!("wallet-{}", i)),
+            keypair: Arc::new(load_keypair(&wallet.keypair_path)?),
+            targets: wallet.targets.clone(),
+        });
+    }
+    let loaded = pool.len();
+    *POOL.write().await = pool;
+    Ok(loaded)
+}
+
+fn with_wallet(state: &AppState, keypair: Arc<Keypair>) -> AppState {
+    AppState {
+        wallet: keypair,
+        ..state.clone()
+    }
+}
+
+/// Wallets the copies trade from: the pool, or the main wallet alone.
+pub async fn executors(state: &AppState) -> Vec<AppState> {
+    if !pooled() {
+        return vec![state.clone()];
+    }
+    POOL.read()
+        .await
+        .iter()
+        .map(|wallet| with_wallet(state, wallet.keypair.clone()))
+        .collect()
+}
+
+/// Read and remember the SOL balance of a wallet.
+pub async fn refresh_balance(state: &AppState) -> Result<u64> {
+    let lamports = sol_balance(state).await?;
+    BALANCES
+        .write()
+        .await
+        .insert(state.wallet.pubkey(), lamports);
+    Ok(lamports)
+}
+
+async fn cached_balance(state: &AppState) -> u64 {
+    let cached = BALANCES.read().await.get(&state.wallet.pubkey()).copied();
+    match cached {
+        Some(lamports) => lamports,
+        None => refresh_balance(state).await.unwrap_or(0),
+    }
+}
+
+/// SOL the copies can spend from: the main wallet's balance, or the pool's combined.
+pub async fn available_sol(state: &AppState) -> Result<u64> {
+    if !pooled() {
+        return sol_balance(state).await;
+    }
+    let mut total = 0;
+    for wallet in POOL.read().await.iter() {
+        total += cached_balance(&with_wallet(state, wallet.keypair.clone())).await;
+    }
+    Ok(total)
+}
+
+// lamports each pool wallet can spend above the risk reserve, in pool order
+async fn pool_spendable(state: &AppState, pool: &[PoolWallet]) -> Vec<u64> {
+    let reserve = config().risk.min_sol_reserve;
+    let mut spendable = vec![];
+    for wallet in pool.iter() {
+        let leg = with_wallet(state, wallet.keypair.clone());
+        spendable.push(cached_balance(&leg).await.saturating_sub(reserve));
+    }
+    spendable
+}
+
+/// Lamports a single copy buy can spend above the risk reserve every wallet keeps: the main
+/// wallet's `balance`, or what the pool can put into one copy under `wallet.allocation`, i.e.
+/// the richest wallet, or every wallet together under `split`.
+pub async fn spendable(state: &AppState, balance: u64) -> u64 {
+    if !pooled() {
+        return balance.saturating_sub(config().risk.min_sol_reserve);
+    }
+    let pool = POOL.read().await.clone();
+    let spendable = pool_spendable(state, &pool).await;
+    match config().wallet.allocation {
+        Allocation::Split => spendable.iter().sum(),
+        _ => spendable.into_iter().max().unwrap_or(0),
+    }
+}
+
+/// Split `amount` as evenly as the wallets' `spendable` lamports allow: a wallet that can't
+/// pay its share pays what it can and the rest moves to the others. `None` when the wallets
+/// can't pay the whole amount together.
+fn split(amount: u64, spendable: &[u64]) -> Option<Vec<u64>> {
+    let mut order: Vec<usize> = (0..spendable.len()).collect();
+    order.sort_by_key(|i| spendable[*i]);
+    let mut shares = vec![0; spendable.len()];
+    let mut left = amount;
+    for (paid, i) in order.into_iter().enumerate() {
+        let share = left.div_ceil((spendable.len() - paid) as u64);
+        shares[i] = share.min(spendable[i]);
+        left -= shares[i];
+    }
+    (left == 0).then_some(shares)
+}
+
+/// Spread a copy over the execution wallets. Buys follow `wallet.allocation` and skip the
+/// wallets that can't pay for their part above the risk reserve, `split` moving the part of a
+/// short wallet to the others; sells come out of every wallet holding the mint, in proportion
+/// to what each holds.
+pub async fn allocate(
+    state: &AppState,
+    target: &str,
+    mint: &str,
+    direction: SwapDirection,
+    amount: u64,
+) -> Result<Vec<Leg>> {
+    if !pooled() {
+        return Ok(vec![Leg {
+            state: state.clone(),
+            amount,
+        }]);
+    }
+    let pool = POOL.read().await.clone();
+    if pool.is_empty() {
+        return Err(anyhow!("Wallets: the pool is empty"));
+    }
+
+    if direction == SwapDirection::Sell {
+        let mut held = vec![];
+        for wallet in pool {
+            let leg = with_wallet(state, wallet.keypair);
+            let tokens = holding(&leg, mint).await.unwrap_or(0);
+            if tokens > 0 {
+                held.push((leg, tokens));
+            }
+        }
+        let total: u64 = held.iter().map(|(_, tokens)| tokens).sum();
+        if total == 0 {
+            return Err(anyhow!("Wallets: no pool wallet holds {}", mint));
+        }
+        let amount = amount.min(total);
+        return Ok(held
+            .into_iter()
+            .map(|(state, tokens)| Leg {
+                state,
+                amount: (amount as u128 * tokens as u128 / total as u128) as u64,
+            })
+            .filter(|leg| leg.amount > 0)
+            .collect());
+    }
+
+    let spendable = pool_spendable(state, &pool).await;
+    let round_robin = || {
+        let start = NEXT.fetch_add(1, Ordering::Relaxed);
+        (0..pool.len())
+            .map(|i| (start + i) % pool.len())
+            .find(|i| spendable[*i] >= amount)
+    };
+    let picked: Vec<(usize, u64)> = match config().wallet.allocation {
+        Allocation::Split => split(amount, &spendable)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, share)| *share > 0)
+            .collect(),
+        Allocation::PerTarget => pool
+            .iter()
+            .enumerate()
+            .position(|(i, wallet)| {
+                wallet.targets.iter().any(|t| t == target) && spendable[i] >= amount
+            })
+            .or_else(round_robin)
+            .map(|i| (i, amount))
+            .into_iter()
+            .collect(),
+        Allocation::RoundRobin | Allocation::Single => {
+            round_robin().map(|i| (i, amount)).into_iter().collect()
+        }
+    };
+    if picked.is_empty() {
+        return Err(anyhow!(
+            "Wallets: no pool wallet can pay {} lamports above the reserve",
+            amount
+        ));
+    }
+    Ok(picked
+        .into_iter()
+        .map(|(i, amount)| Leg {
+            state: with_wallet(state, pool[i].keypair.clone()),
+            amount,
+        })
+        .collect())
+}
+
+/// Label, address and lamports of the main wallet and every pool wallet.
+pub async fn balances(state: &AppState) -> Result<Vec<(String, Pubkey, u64)>> {
+    let mut balances = vec![(
+        "main".to_string(),
+        state.wallet.pubkey(),
+        sol_balance(state).await?,
+    )];
+    for wallet in POOL.read().await.iter() {
+        let leg = with_wallet(state, wallet.keypair.clone());
+        balances.push((
+            wallet.label.clone(),
+            wallet.keypair.pubkey(),
+            refresh_balance(&leg).await?,
+        ));
+    }
+    Ok(balances)
+}
+
+async fn transfer(state: &AppState, from: &Keypair, to: &Pubkey, lamports: u64) -> Result<String> {
+    let client = &state.rpc_nonblocking_client;
+    let blockhash = client.get_latest_blockhash().await?;
+    let tx = Transaction::new_signed_with_payer(
+        &[system_instruction::transfer(&from.pubkey(), to, lamports)],
+        Some(&from.pubkey()),
+        &[from],
+        blockhash,
+    );
+    let signature = client.send_and_confirm_transaction(&tx).await?;
+    Ok(signature.to_string())
+}
+
+/// Pool wallet by label or address.
+pub async fn find(wallet: &str) -> Option<PoolWallet> {
+    POOL.read()
+        .await
+        .iter()
+        .find(|w| w.label == wallet || w.keypair.pubkey().to_string() == wallet)
+        .cloned()
+}
+
+/// Send `lamports` from the main wallet to a pool wallet.
+pub async fn fund(state: &AppState, wallet: &str, lamports: u64) -> Result<String> {
+    let wallet = find(wallet)
+        .await
+        .ok_or(anyhow!("Wallets: no pool wallet {}", wallet))?;
+    let signature = transfer(state, &state.wallet, &wallet.keypair.pubkey(), lamports).await?;
+    log_message(&format!(
+        "Wallets: funded {} with {} lamports, {}",
+        wallet.label, lamports, signature
+    ));
+    Ok(signature)
+}
+
+/// Move the SOL of every pool wallet back to the main wallet, leaving `keep` lamports in each
+/// (0, or at least the rent-exempt minimum).
+pub async fn sweep(state: &AppState, keep: u64) -> Result<Vec<String>> {
+    let mut signatures = vec![];
+    for wallet in POOL.read().await.clone() {
+        let leg = with_wallet(state, wallet.keypair.clone());
+        let lamports = sol_balance(&leg)
+            .await?
+            .saturating_sub(keep)
+            .saturating_sub(SIGNATURE_FEE);
+        if lamports == 0 {
+            continue;
+        }
+        match transfer(state, &wallet.keypair, &state.wallet.pubkey(), lamports).await {
+            Ok(signature) => {
+                log_message(&format!(
+                    "Wallets: swept {} lamports from {}, {}",
+                    lamports, wallet.label, signature
+                ));
+                signatures.push(signature);
+            }
+            Err(e) => warn!(wallet = %wallet.label, error = %e, "sweep failed"),
+        }
+        let _ = refresh_balance(&leg).await;
+    }
+    Ok(signatures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_is_even_when_every_wallet_can_pay() {
+        assert_eq!(
+            split(900, &[1_000, 1_000, 1_000]),
+            Some(vec![300, 300, 300])
+        );
+        assert_eq!(split(10, &[100, 100, 100]), Some(vec![4, 3, 3]));
+    }
+
+    #[test]
+    fn short_wallets_pass_their_share_to_the_others() {
+        assert_eq!(split(900, &[1_000, 100, 1_000]), Some(vec![400, 100, 400]));
+        assert_eq!(split(900, &[0, 1_000, 1_000]), Some(vec![0, 450, 450]));
+        assert_eq!(split(900, &[700, 100, 150]), Some(vec![650, 100, 150]));
+    }
+
+    #[test]
+    fn split_fails_when_the_pool_cannot_pay() {
+        assert_eq!(split(900, &[300, 300, 299]), None);
+        assert_eq!(split(1, &[]), None);
+    }
+}
@@ -1,12 +1,41 @@
+use anyhow::anyhow;
 use bincode::Options;
+use clap::{Args, Parser, Subcommand};
 use jito_json_rpc_client::jsonrpc_client::rpc_client::RpcClient as JitoRpcClient;
+use temp::common::config::{self, config, Config};
+use temp::common::keystore::{load_keypair, save_keypair, KeyFormat};
+use temp::common::telemetry;
 use temp::common::utils::{
-    create_arc_rpc_client, create_nonblocking_rpc_client, import_arc_wallet, import_env_var,
-    import_wallet, log_message, AppState,
+    create_arc_rpc_client, create_nonblocking_rpc_client, env_or, import_arc_wallet, import_wallet,
+    log_message, AppState,
 };
-use temp::core::token::get_account_info;
+use temp::core::lut::{self, LOOKUP_TABLE};
+use temp::core::nonce::{self, NONCE_ACCOUNTS};
+use temp::core::sim::SIMULATION_CONFIG;
+use temp::core::token::{close_empty_accounts, get_account_info, get_token_mint_info};
 use temp::core::tx::jito_confirm;
-use temp::engine::swap::{pump_swap, raydium_swap};
+use temp::dex::raydium::get_pool_state_by_mint;
+use temp::engine::analytics::{analyze, print_ranking};
+use temp::engine::backtest::{backtest, events_from_transaction, export_events, token_balances};
+use temp::engine::exit;
+use temp::engine::paper::PAPER_CONFIG;
+use temp::engine::pnl::{self, CostMethod};
+use temp::engine::position::{self, Venue};
+use temp::engine::presign::{
+    discard_exit, fire_exit, has_exit, holding, prepare_pump_exit, prepare_raydium_exit,
+    sol_balance, ExitOutcome,
+};
+use temp::engine::reconcile::{reconcile, wallet_holdings, RECONCILE};
+use temp::engine::risk;
+use temp::engine::screener::{screen, SCREENER_CONFIG};
+use temp::engine::swap::{pump_swap, raydium_swap, SwapDirection, SwapInType};
+use temp::engine::wallets;
+use temp::services::control;
+use temp::services::jito;
+use temp::services::metrics;
+use temp::services::recorder::{replay, Recorder, RECORD_DIR, REPLAY_FILES, REPLAY_PACE};
+use temp::services::store::{self, store, AttemptStatus, TargetTrade};
+use temp::services::trade_log::{self, Event};
 // use copy_trading_bot::dex::pump::pump_sdk_swap;
 use dotenv::dotenv;
 use futures_util::{SinkExt, StreamExt};
@@ -14,14 +43,20 @@ use serde::Serialize;
 use serde_json::Value;
 use solana_sdk::message::VersionedMessage;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
 use solana_sdk::signer::Signer;
 use solana_sdk::transaction::VersionedTransaction;
 use spl_associated_token_account::get_associated_token_address;
-use std::env;
+use spl_token::ui_amount_to_amount;
+use std::collections::HashSet;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, LazyLock};
-use tokio::time::Instant;
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
 use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+use tracing::{error, info, info_span, instrument, warn, Instrument};
 
 #[derive(Serialize)]
 struct SwapRequest {
@@ -31,47 +66,582 @@ struct SwapRequest {
     dynamicComputeUnitLimit: bool,
     prioritizationFeeLamports: u64,
 }
-#[tokio::main]
+/// Solana copy trading bot
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Copy the configured targets (the default)
+    Run,
+    /// Buy a token on pump or Raydium
+    Buy(TradeArgs),
+    /// Sell a token on pump or Raydium
+    Sell(TradeArgs),
+    /// SOL and token holdings of the wallet
+    Balance,
+    /// Open positions, marked at the current quote
+    Positions,
+    /// Realized and unrealized PnL by target, mint, venue and day
+    Pnl {
+        #[arg(value_enum, default_value = "fifo")]
+        method: CostMethod,
+        /// Only fills of the last days
+        days: Option<i64>,
+    },
+    /// Close the token accounts holding nothing and reclaim their rent
+    CloseEmptyAccounts,
+    /// Run the trade parsers on one transaction and print what they decode
+    InspectTx { signature: String },
+    /// Copy a target over recordings or exports of market events, offline
+    Backtest { target: String, files: Vec<String> },
+    /// Export every swap on the pump curves and Raydium pools of mints as market events for
+    /// `backtest` and `analyze`
+    BacktestExport {
+        /// JSON lines file to write
+        out: String,
+        mints: Vec<String>,
+        /// Latest transactions fetched per curve or pool
+        #[arg(long, default_value_t = 1000)]
+        limit: usize,
+    },
+    /// Rank candidate targets over recordings or exports of market events, offline
+    Analyze {
+        /// Comma separated wallets, `all` for every trader
+        wallets: String,
+        files: Vec<String>,
+    },
+    /// Create or convert key files
+    #[command(subcommand)]
+    Keystore(KeystoreCommand),
+    /// Balances of the execution wallets, and SOL moves between them and the main wallet
+    #[command(subcommand)]
+    Wallets(WalletsCommand),
+    /// Durable nonce accounts backing the pre-signed exits
+    #[command(subcommand)]
+    Nonce(NonceCommand),
+    /// Address lookup table our transactions are compiled against
+    #[command(subcommand)]
+    Lut(LutCommand),
+}
+
+#[derive(Subcommand)]
+enum KeystoreCommand {
+    /// Generate a new keypair
+    New {
+        out: String,
+        #[arg(long, value_enum, default_value = "keystore")]
+        format: KeyFormat,
+        /// Replace `out` if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Rewrite a JSON keypair, base58 key or keystore in another format
+    Convert {
+        input: String,
+        out: String,
+        #[arg(long, value_enum, default_value = "keystore")]
+        format: KeyFormat,
+        /// Replace `out` if it exists
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum WalletsCommand {
+    /// SOL of the main wallet and of every pool wallet
+    List,
+    /// Send SOL from the main wallet to a pool wallet
+    Fund {
+        /// Label or address of the pool wallet
+        wallet: String,
+        sol: f64,
+    },
+    /// Send the SOL of every pool wallet back to the main wallet
+    Sweep {
+        /// SOL left in each pool wallet
+        #[arg(long, default_value_t = 0.0)]
+        keep: f64,
+    },
+}
+
+#[derive(Subcommand)]
+enum NonceCommand {
+    /// Create nonce accounts owned by the wallet, to list in `NONCE_ACCOUNTS`
+    Create {
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum LutCommand {
+    /// Create a table holding the static accounts and those of the open positions, to set as
+    /// `LOOKUP_TABLE`
+    Create,
+    /// Replace `LOOKUP_TABLE` with a new table leaving out closed positions, and deactivate it
+    Rebuild,
+    /// Close a deactivated table and reclaim its rent
+    Close { table: String },
+}
+
+#[derive(Args)]
+struct TradeArgs {
+    mint: String,
+    /// SOL to spend on a buy or tokens to sell, a percentage with `--in-type pct`
+    amount: f64,
+    #[arg(long, value_enum, default_value = "pump")]
+    venue: Venue,
+    #[arg(long, value_enum, default_value = "qty")]
+    in_type: SwapInType,
+    /// Slippage in basis points, `trading.slippage_bps` when unset
+    #[arg(long)]
+    slippage: Option<u64>,
+}
+
+/// Target recorded for trades made from the command line
+const MANUAL_TARGET: &str = "manual";
+/// Wait before reconnecting the stream, doubled on every failed attempt up to `RECONNECT_MAX`
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+/// Silence after which the stream is considered dead and reconnected (`STREAM_IDLE_SECS`); it
+/// is pinged at half that, so a quiet but live connection keeps answering
+static STREAM_IDLE: LazyLock<Duration> =
+    LazyLock::new(|| Duration::from_secs(env_or("STREAM_IDLE_SECS", 60).max(2)));
 
+#[tokio::main]
 async fn main() {
     dotenv().ok();
-    let target = env::var("TARGET_PUBKEY").expect("TARGET not set");
+    if let Err(e) = telemetry::init() {
+        // no subscriber to log through
+        eprintln!("{}", e);
+    }
+    let command = Cli::parse().command.unwrap_or(Command::Run);
+    // offline commands need no endpoints or wallet
+    match &command {
+        Command::Backtest { target, files } => {
+            match backtest(target, files) {
+                Ok(result) => result.print(),
+                Err(e) => error!(error = %e, "backtest failed"),
+            }
+            return;
+        }
+        Command::Analyze { wallets, files } => {
+            let wallets: Vec<String> = if wallets == "all" {
+                vec![]
+            } else {
+                wallets.split(',').map(|w| w.trim().to_string()).collect()
+            };
+            match analyze(&wallets, files) {
+                Ok(ranked) => print_ranking(&ranked),
+                Err(e) => error!(error = %e, "analyze failed"),
+            }
+            return;
+        }
+        Command::Keystore(command) => {
+            let (keypair, out, format, force) = match command {
+                KeystoreCommand::New { out, format, force } => {
+                    (Ok(Keypair::new()), out, format, force)
+                }
+                KeystoreCommand::Convert {
+                    input,
+                    out,
+                    format,
+                    force,
+                } => (load_keypair(input), out, format, force),
+            };
+            match keypair.and_then(|keypair| {
+                save_keypair(out, &keypair, *format, *force)?;
+                Ok(keypair)
+            }) {
+                Ok(keypair) => println!("{} written for {}", out, keypair.pubkey()),
+                Err(e) => error!(error = %e, "keystore failed"),
+            }
+            return;
+        }
+        _ => {}
+    }
+    let settings = match config::init() {
+        Ok(settings) => settings,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    if let Err(e) = store::open() {
+        error!(error = %e, "journal not opened");
+        return;
+    }
 
     let rpc_client = create_arc_rpc_client().unwrap();
     let rpc_nonblocking_client = create_nonblocking_rpc_client().await.unwrap();
-    let wallet = import_arc_wallet().unwrap();
+    let wallet = match import_arc_wallet() {
+        Ok(wallet) => wallet,
+        Err(e) => {
+            error!(error = %e, "wallet not loaded");
+            return;
+        }
+    };
 
     let state = AppState {
         rpc_client,
         rpc_nonblocking_client,
         wallet,
     };
-    pub static BLOCK_ENGINE_URL: LazyLock<String> =
-        LazyLock::new(|| import_env_var("JITO_BLOCK_ENGINE_URL"));
+    match wallets::init().await {
+        Ok(0) => {}
+        Ok(loaded) => info!(loaded, "pool wallets loaded"),
+        Err(e) => {
+            error!(error = %e, "pool wallets not loaded");
+            return;
+        }
+    }
+    if !matches!(command, Command::Run) {
+        if let Err(e) = position::restore().await {
+            warn!(error = %e, "positions not restored");
+        }
+    }
+    match command {
+        Command::Run => run(state, settings).await,
+        Command::Buy(args) => {
+            if let Err(e) = trade(&state, SwapDirection::Buy, args).await {
+                error!(error = %e, "buy failed");
+            }
+        }
+        Command::Sell(args) => {
+            if let Err(e) = trade(&state, SwapDirection::Sell, args).await {
+                error!(error = %e, "sell failed");
+            }
+        }
+        Command::Balance => {
+            if let Err(e) = print_balance(&state).await {
+                error!(error = %e, "balance failed");
+            }
+        }
+        Command::Positions => print_positions(&state).await,
+        Command::Pnl { method, days } => {
+            let since = days
+                .map(|days| chrono::Utc::now().timestamp() - days * 86_400)
+                .unwrap_or(0);
+            match pnl::report(&state, method, since).await {
+                Ok(report) => report.print(),
+                Err(e) => error!(error = %e, "pnl failed"),
+            }
+        }
+        Command::CloseEmptyAccounts => {
+            match close_empty_accounts(&state.rpc_nonblocking_client, &state.wallet).await {
+                Ok(signatures) if signatures.is_empty() => println!("no empty token account"),
+                Ok(signatures) => println!("closed in {}", signatures.join(", ")),
+                Err(e) => error!(error = %e, "close-empty-accounts failed"),
+            }
+        }
+        Command::BacktestExport { out, mints, limit } => {
+            match export_events(&state, &mints, Path::new(&out), limit).await {
+                Ok(exported) => println!("{} market events written to {}", exported, out),
+                Err(e) => error!(error = %e, "backtest export failed"),
+            }
+        }
+        Command::InspectTx { signature } => {
+            match events_from_transaction(&state.rpc_nonblocking_client, &signature).await {
+                Ok(events) if events.is_empty() => println!("no pump or Raydium trade decoded"),
+                Ok(events) => {
+                    for event in events {
+                        println!("{:#?}", event);
+                    }
+                }
+                Err(e) => error!(error = %e, "inspect-tx failed"),
+            }
+        }
+        Command::Wallets(command) => {
+            if let Err(e) = manage_wallets(&state, command).await {
+                error!(error = %e, "wallets failed");
+            }
+        }
+        Command::Lut(command) => {
+            if let Err(e) = manage_lookup_table(&state, command).await {
+                error!(error = %e, "lut failed");
+            }
+        }
+        Command::Nonce(NonceCommand::Create { count }) => {
+            for _ in 0..count {
+                match nonce::create_nonce_account(&state.rpc_client, &state.wallet) {
+                    Ok(nonce_account) => println!("nonce account {} created", nonce_account),
+                    Err(e) => {
+                        error!(error = %e, "nonce create failed");
+                        break;
+                    }
+                }
+            }
+        }
+        Command::Backtest { .. } | Command::Analyze { .. } | Command::Keystore(_) => {}
+    }
+    trade_log::flush(Duration::from_secs(2));
+}
+
+/// The copy bot: follow the configured targets on the stream, or on recordings with `REPLAY`.
+async fn run(state: AppState, settings: Arc<Config>) {
+    // replayed trades go through the live swap path, they must never be sent for real
+    if !REPLAY_FILES.is_empty() && !PAPER_CONFIG.enabled {
+        error!("REPLAY is only allowed with PAPER=true, recorded trades are not sent for real");
+        return;
+    }
+    for target in settings.targets.iter() {
+        let label = target.label.as_deref();
+        let stored = store().and_then(|store| {
+            store.upsert_target(&target.address, label, target.enabled)
+        });
+        if let Err(e) = stored {
+            warn!(target = %target.address, error = %e, "target not journaled");
+        }
+    }
+    match position::restore().await {
+        Ok(restored) => info!(restored, "positions restored"),
+        Err(e) => warn!(error = %e, "positions not restored"),
+    }
+    if let Err(e) = risk::restore().await {
+        warn!(error = %e, "daily realized profit not restored");
+    }
+    if *RECONCILE && !PAPER_CONFIG.enabled {
+        match reconcile(&state).await {
+            Ok(report) => info!("{}", report.summary()),
+            Err(e) => warn!(error = %e, "reconcile failed"),
+        }
+    }
+    if let Some(table) = *LOOKUP_TABLE {
+        let accounts = table_accounts(&state).await;
+        match lut::extend(&state.rpc_client, &state.wallet, &table, &accounts).await {
+            Ok(added) => info!(%table, added, "lookup table extended"),
+            Err(e) => warn!(%table, error = %e, "lookup table not extended"),
+        }
+    }
+    if wallets::pooled() {
+        match wallets::balances(&state).await {
+            Ok(balances) => {
+                for (label, pubkey, lamports) in balances {
+                    info!(label = %label, %pubkey, lamports, "pool wallet balance");
+                }
+            }
+            Err(e) => warn!(error = %e, "pool wallet balances unreadable"),
+        }
+    }
     let jito_client = Arc::new(JitoRpcClient::new(format!(
         "{}/api/v1/bundles",
-        *BLOCK_ENGINE_URL
+        settings.endpoints.jito_block_engine
     )));
-    let unwanted_key = env::var("JUP_PUBKEY").expect("JUP_PUBKEY not set");
-    let ws_url = env::var("RPC_WEBSOCKET_ENDPOINT").expect("RPC_WEBSOCKET_ENDPOINT not set");
-
-    let (ws_stream, _) = connect_async(ws_url)
+    if settings.exit.enabled {
+        for position in position::all().await {
+            exit::watch(state.clone(), jito_client.clone(), position.mint).await;
+        }
+    }
+    if !REPLAY_FILES.is_empty() {
+        match replay(&REPLAY_FILES, *REPLAY_PACE, |text| {
+            handle_message(text, state.clone(), jito_client.clone())
+        })
         .await
-        .expect("Failed to connect to WebSocket server");
-    let (mut write, mut read) = ws_stream.split();
-    // Subscribe to logs
-    let subscription_message = serde_json::json!({
+        {
+            Ok(replayed) => info!(replayed, "messages replayed"),
+            Err(e) => error!(error = %e, "replay failed"),
+        }
+        return;
+    }
+    let recorder = RECORD_DIR.as_deref().map(|dir| {
+        let recorder = Recorder::start(dir).expect("Failed to start recorder");
+        info!(path = %recorder.path.display(), "recording stream");
+        recorder
+    });
+    let mut request_id = 0;
+    let mut filter = subscription_filter(&settings);
+    config::spawn_watcher();
+    telemetry::spawn_latency_log();
+    if let Err(e) = metrics::spawn_server().await {
+        error!(error = %e, "metrics server not started");
+    }
+    if let Err(e) = control::spawn_server(state.clone(), jito_client.clone()).await {
+        error!(error = %e, "control API not started");
+    }
+    let mut updates = config::subscribe();
+
+    log_message("copy bot started");
+
+    let mut backoff = RECONNECT_MIN;
+    // runs until ctrl-c, the recording is flushed before exiting
+    let stream = async {
+        loop {
+            let mut ws_stream = match connect_async(settings.endpoints.websocket.as_str()).await {
+                Ok((ws_stream, _)) => ws_stream,
+                Err(e) => {
+                    warn!(error = %e, retry_in = ?backoff, "stream connect failed");
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(RECONNECT_MAX);
+                    continue;
+                }
+            };
+            // Subscribe to the transactions of the targets. `subscribe_id` is the request of the
+            // current subscription, `subscription` its id once the reply arrived; requests
+            // replaced before their reply are unsubscribed when it comes in.
+            let mut subscribe_id: Option<u64> = None;
+            let mut subscription: Option<u64> = None;
+            let mut replaced: HashSet<u64> = HashSet::new();
+            if subscribes_nothing(&filter) {
+                info!("every target is paused, waiting for the config to enable one");
+            } else {
+                request_id += 1;
+                subscribe_id = Some(request_id);
+                let request = subscription_request(request_id, &filter).to_string();
+                if let Err(e) = ws_stream.send(request.into()).await {
+                    warn!(error = %e, retry_in = ?backoff, "subscribe failed");
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(RECONNECT_MAX);
+                    continue;
+                }
+            }
+            backoff = RECONNECT_MIN;
+            metrics::STREAM_CONNECTED.store(true, Ordering::Relaxed);
+            let (mut write, mut read) = ws_stream.split();
+            let mut ping = tokio::time::interval(*STREAM_IDLE / 2);
+
+            // Listen for messages
+            loop {
+                tokio::select! {
+                    msg = tokio::time::timeout(*STREAM_IDLE, read.next()) => {
+                        let Ok(msg) = msg else {
+                            warn!(idle = ?*STREAM_IDLE, "stream silent, reconnecting");
+                            break;
+                        };
+                        let Some(Ok(msg)) = msg else {
+                            break;
+                        };
+                        let now = chrono::Utc::now().timestamp();
+                        metrics::LAST_MESSAGE_AT.store(now, Ordering::Relaxed);
+                        if let WsMessage::Text(text) = msg {
+                            // replies to our requests, the subscribe reply carries the
+                            // subscription id
+                            if !text.contains("\"method\"") {
+                                let reply = serde_json::from_str::<Value>(&text).ok();
+                                let subscribed = reply.as_ref().and_then(|reply| {
+                                    Some((reply["id"].as_u64()?, reply["result"].as_u64()?))
+                                });
+                                let Some((id, subscribed)) = subscribed else {
+                                    continue;
+                                };
+                                if replaced.remove(&id) {
+                                    request_id += 1;
+                                    let request = unsubscribe_request(request_id, subscribed);
+                                    if let Err(e) = write.send(request.to_string().into()).await {
+                                        warn!(
+                                            subscription = subscribed,
+                                            error = %e,
+                                            "unsubscribe failed"
+                                        );
+                                    }
+                                } else if subscribe_id == Some(id) {
+                                    subscription = Some(subscribed);
+                                }
+                                continue;
+                            }
+                            if let Some(recorder) = recorder.as_ref() {
+                                recorder.record(&text);
+                            }
+                            handle_message(text, state.clone(), jito_client.clone()).await;
+                        }
+                    }
+                    _ = ping.tick() => {
+                        if let Err(e) = write.send(WsMessage::Ping(Default::default())).await {
+                            warn!(error = %e, "stream ping failed");
+                            break;
+                        }
+                    }
+                    Ok(()) = updates.changed() => {
+                        let next = subscription_filter(&updates.borrow_and_update());
+                        if next == filter {
+                            continue;
+                        }
+                        filter = next;
+                        if let Some(id) = subscription.take() {
+                            request_id += 1;
+                            let request = unsubscribe_request(request_id, id);
+                            if let Err(e) = write.send(request.to_string().into()).await {
+                                warn!(subscription = id, error = %e, "unsubscribe failed");
+                            }
+                        } else if let Some(pending) = subscribe_id {
+                            // still waiting for the reply, unsubscribed when it arrives
+                            replaced.insert(pending);
+                        }
+                        subscribe_id = None;
+                        if subscribes_nothing(&filter) {
+                            info!("every target is paused, stream unsubscribed");
+                            continue;
+                        }
+                        request_id += 1;
+                        subscribe_id = Some(request_id);
+                        match write
+                            .send(subscription_request(request_id, &filter).to_string().into())
+                            .await
+                        {
+                            Ok(()) => {
+                                info!(accounts = %filter["accountInclude"], "stream resubscribed")
+                            }
+                            Err(e) => warn!(error = %e, "resubscribe failed"),
+                        }
+                    }
+                }
+            }
+            metrics::STREAM_CONNECTED.store(false, Ordering::Relaxed);
+            metrics::WS_RECONNECTS.inc();
+            warn!("stream closed, reconnecting");
+        }
+    };
+    tokio::select! {
+        _ = stream => {}
+        _ = tokio::signal::ctrl_c() => info!("shutting down"),
+    }
+    if let Some(recorder) = recorder.as_ref() {
+        recorder.flush().await;
+    }
+}
+
+// an empty accountInclude would subscribe to every transaction
+fn subscribes_nothing(filter: &Value) -> bool {
+    filter["accountInclude"]
+        .as_array()
+        .map_or(true, Vec::is_empty)
+}
+
+/// Transactions the stream delivers: those touching an enabled target, minus the excluded
+/// accounts.
+fn subscription_filter(config: &Config) -> Value {
+    let targets: Vec<&str> = config
+        .enabled_targets()
+        .map(|target| target.address.as_str())
+        .collect();
+    serde_json::json!({
+        "failed": false,
+        "accountInclude": targets,
+        "accountExclude": config.trading.exclude_accounts,
+    })
+}
+
+fn unsubscribe_request(id: u64, subscription: u64) -> Value {
+    serde_json::json!({
         "jsonrpc": "2.0",
-        "id": 1,
+        "id": id,
+        "method": "transactionUnsubscribe",
+        "params": [subscription]
+    })
+}
+
+fn subscription_request(id: u64, filter: &Value) -> Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
         "method": "transactionSubscribe",
         "params": [
-
-            {
-                "failed": false,
-                "accountInclude": ["675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8", "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P"],
-                "accountExclude": [unwanted_key],
-                // Optionally specify accounts of interest
-            },
+            filter,
             {
                 "commitment": "processed",
                 "encoding": "jsonParsed",
@@ -79,32 +649,254 @@ async fn main() {
                 "maxSupportedTransactionVersion": 0
             }
         ]
-    });
+    })
+}
 
-    write
-        .send(subscription_message.to_string().into())
-        .await
-        .expect("Failed to send subscription message");
+/// Manual swap from the command line, settled into the positions and the journal like a copy.
+async fn trade(state: &AppState, direction: SwapDirection, args: TradeArgs) -> anyhow::Result<()> {
+    let config = config();
+    let slippage = args.slippage.unwrap_or(config.trading.slippage_bps);
+    let jito_client = Arc::new(JitoRpcClient::new(format!(
+        "{}/api/v1/bundles",
+        config.endpoints.jito_block_engine
+    )));
+    let mint = Pubkey::from_str(&args.mint)?;
+    let position = position::get(&args.mint).await;
+    let sol_before = sol_balance(state).await?;
+    let tokens_before = holding(state, &args.mint).await.unwrap_or(0);
 
-    let _ = log_message("---------------------   Copy-trading-bot start!!!  ------------------\n")
-        .await;
+    // percentages are resolved against the balances by the swap itself
+    let amount_in = match (args.in_type, direction) {
+        (SwapInType::Qty, SwapDirection::Buy) => ui_amount_to_amount(args.amount, 9),
+        (SwapInType::Qty, SwapDirection::Sell) => {
+            let info = get_token_mint_info(state.rpc_nonblocking_client.clone(), &mint).await?;
+            ui_amount_to_amount(args.amount, info.decimals)
+        }
+        (SwapInType::Pct, _) if args.amount.fract() != 0.0 => {
+            return Err(anyhow!("percentage must be a whole number: {}", args.amount));
+        }
+        (SwapInType::Pct, _) => args.amount as u64,
+    };
+    if amount_in == 0 {
+        return Err(anyhow!("nothing to {:?}", direction));
+    }
 
-    // Listen for messages
-    while let Some(Ok(msg)) = read.next().await {
-        if let WsMessage::Text(text) = msg {
-            let json: Value = serde_json::from_str(&text).unwrap();
+    let simulate = SIMULATION_CONFIG.enabled_for(MANUAL_TARGET);
+    let pool_id = match args.venue {
+        Venue::Pump => None,
+        Venue::Raydium => match position.as_ref().and_then(|p| p.pool_id.clone()) {
+            Some(pool_id) => Some(pool_id),
+            None => {
+                let (pool_id, _) = get_pool_state_by_mint(state.rpc_client.clone(), &args.mint)
+                    .await?;
+                Some(pool_id.to_string())
+            }
+        },
+    };
+    let signatures = match &pool_id {
+        None => {
+            pump_swap(
+                state.clone(),
+                amount_in,
+                args.in_type,
+                direction,
+                slippage,
+                &args.mint,
+                simulate,
+                jito_client,
+                Instant::now(),
+            )
+            .await?
+        }
+        Some(pool_id) => {
+            raydium_swap(
+                state.clone(),
+                amount_in,
+                args.in_type,
+                direction,
+                pool_id.clone(),
+                slippage,
+                &args.mint,
+                simulate,
+                jito_client,
+                Instant::now(),
+            )
+            .await?
+        }
+    };
 
-            let sig = json["params"]["result"]["signature"]
-                .as_str()
-                .unwrap_or_default();
-            let timestamp = Instant::now();
+    // a position keeps the target it was copied from
+    let target = position
+        .map(|p| p.target)
+        .unwrap_or(MANUAL_TARGET.to_string());
+    let fill = position::settle(
+        state,
+        &target,
+        &args.mint,
+        args.venue,
+        pool_id,
+        direction,
+        sol_before,
+        tokens_before,
+        &signatures,
+        None,
+    )
+    .await?;
+    println!(
+        "{:?} {} {}: {} tokens for {} lamports, {}",
+        direction,
+        fill.venue,
+        fill.mint,
+        fill.token_amount,
+        fill.lamports,
+        signatures.join(", ")
+    );
+    Ok(())
+}
+
+async fn print_balance(state: &AppState) -> anyhow::Result<()> {
+    let lamports = sol_balance(state).await?;
+    println!("{}: {:.9} SOL", state.wallet.pubkey(), lamports as f64 / 1_000_000_000.0);
+    let mut holdings: Vec<(String, u64)> = wallet_holdings(state).await?.into_iter().collect();
+    holdings.sort();
+    for (mint, amount) in holdings {
+        println!("{:<44} {}", mint, amount);
+    }
+    Ok(())
+}
+
+async fn manage_wallets(state: &AppState, command: WalletsCommand) -> anyhow::Result<()> {
+    match command {
+        WalletsCommand::List => {
+            for (label, pubkey, lamports) in wallets::balances(state).await? {
+                let sol = lamports as f64 / 1_000_000_000.0;
+                println!("{:<16} {:<44} {:>16.9} SOL", label, pubkey, sol);
+            }
+        }
+        WalletsCommand::Fund { wallet, sol } => {
+            let signature = wallets::fund(state, &wallet, ui_amount_to_amount(sol, 9)).await?;
+            println!("funded {} in {}", wallet, signature);
+        }
+        WalletsCommand::Sweep { keep } => {
+            let signatures = wallets::sweep(state, ui_amount_to_amount(keep, 9)).await?;
+            if signatures.is_empty() {
+                println!("nothing to sweep");
+            } else {
+                println!("swept in {}", signatures.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Accounts our transactions look up: the static ones and the token accounts the execution
+/// wallets hold the open positions in.
+async fn table_accounts(state: &AppState) -> Vec<Pubkey> {
+    // the tip accounts are part of the static accounts
+    if let Err(e) = jito::init_tip_accounts().await {
+        warn!(error = %e, "jito tip accounts not loaded");
+    }
+    let executors: Vec<Pubkey> = wallets::executors(state)
+        .await
+        .iter()
+        .map(|executor| executor.wallet.pubkey())
+        .collect();
+    let mints: Vec<String> = position::all()
+        .await
+        .into_iter()
+        .map(|position| position.mint)
+        .collect();
+    let positions = lut::position_accounts(&state.rpc_nonblocking_client, &executors, &mints).await;
+    lut::static_accounts(&state.wallet.pubkey(), &positions).await
+}
+
+async fn manage_lookup_table(state: &AppState, command: LutCommand) -> anyhow::Result<()> {
+    match command {
+        LutCommand::Create => {
+            let accounts = table_accounts(state).await;
+            let table = lut::rebuild(&state.rpc_client, &state.wallet, None, &accounts).await?;
+            println!("lookup table {} created, set LOOKUP_TABLE={}", table, table);
+        }
+        LutCommand::Rebuild => {
+            let old = LOOKUP_TABLE.ok_or(anyhow!("LOOKUP_TABLE is not set"))?;
+            let accounts = table_accounts(state).await;
+            let table =
+                lut::rebuild(&state.rpc_client, &state.wallet, Some(&old), &accounts).await?;
+            println!(
+                "lookup table {} created, set LOOKUP_TABLE={}; {} is deactivated, close it \
+                 with `lut close {}` once the cool-down is over",
+                table, table, old, old
+            );
+        }
+        LutCommand::Close { table } => {
+            lut::close(&state.rpc_client, &state.wallet, &Pubkey::from_str(&table)?)?;
+            println!("lookup table {} closed", table);
+        }
+    }
+    Ok(())
+}
 
-            // filter tx raydium part
-            tx_ray();
+async fn print_positions(state: &AppState) {
+    println!(
+        "{:<44} {:<44} {:<8} {:>16} {:>12} {:>12}",
+        "mint", "target", "venue", "tokens", "cost", "value"
+    );
+    for position in position::all().await {
+        let value = match exit::mark(state, &position).await {
+            Ok(value) => format!("{:.4}", value as f64 / 1_000_000_000.0),
+            Err(_) => "-".to_string(),
+        };
+        println!(
+            "{:<44} {:<44} {:<8} {:>16} {:>12.4} {:>12}",
+            position.mint,
+            position.target,
+            position.venue,
+            position.token_amount,
+            position.cost_lamports as f64 / 1_000_000_000.0,
+            value
+        );
+    }
+}
 
-            // filter tx pumpfun part
-            tx_pump();
+/// Run one raw stream message, live or replayed, through the pipeline.
+pub async fn handle_message(text: String, state: AppState, jito_client: Arc<JitoRpcClient>) {
+    let json: Value = match serde_json::from_str(&text) {
+        Ok(json) => json,
+        Err(e) => {
+            warn!(error = %e, "unparsable stream message");
+            metrics::TRADES_SKIPPED.inc("unparsable");
+            return;
         }
+    };
+
+    let sig = json["params"]["result"]["signature"]
+        .as_str()
+        .unwrap_or_default();
+    let timestamp = Instant::now();
+    // entered for the synchronous filtering below, dropped before anything is awaited
+    let receive = info_span!("receive", signature = sig).entered();
+    metrics::TRADES_SEEN.inc();
+
+    // only transactions signed by an enabled target are copied
+    let config = config();
+    let signer = json["params"]["result"]["transaction"]["transaction"]["message"]["accountKeys"]
+        [0]["pubkey"]
+        .as_str()
+        .unwrap_or_default();
+    let Some(target) = config.target(signer).map(|target| target.address.clone()) else {
+        metrics::TRADES_SKIPPED.inc("not_target");
+        return;
+    };
+    drop(receive);
+
+    // filter tx raydium part
+    if config.venues.raydium {
+        tx_ray();
+    }
+
+    // filter tx pumpfun part
+    if config.venues.pump {
+        tx_pump();
     }
 }
 
@@ -115,7 +907,17 @@ pub async fn tx_ray(
     state: AppState,
     jito_client: Arc<JitoRpcClient>,
 ) {
+    let signature = json["params"]["result"]["signature"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    // covers decoding the swap and sizing the copy, closed before the copy is sent
+    let parse = info_span!("parse", signature = %signature, venue = "raydium").entered();
     // parsing tx part
+    let slot = json["params"]["result"]["slot"].as_u64().unwrap_or_default();
+    let percent = config().size_pct(&target);
+    metrics::TRADES_PARSED.inc("raydium");
+    drop(parse);
 
     if  {
         dirs = "buy".to_string();
@@ -123,7 +925,12 @@ pub async fn tx_ray(
             mint,
             amount_in * percent / 100,
             dirs,
+            0,
+            false,
             pool_id,
+            target.clone(),
+            signature.clone(),
+            slot,
             timestamp.clone(),
             jito_client.clone(),
             state.clone(),
@@ -131,11 +938,17 @@ pub async fn tx_ray(
         .await;
     } else {
         dirs = "sell".to_string();
+        let (held, left) = target_holding(&json, &target, &mint);
         swap_to_events_on_raydium(
             mint,
             amount_in * percent / 100,
             dirs,
+            share_sold(held, left),
+            left == 0,
             pool_id,
+            target.clone(),
+            signature.clone(),
+            slot,
             timestamp.clone(),
             jito_client.clone(),
             state.clone(),
@@ -151,7 +964,17 @@ pub async fn tx_pump(
     state: AppState,
     jito_client: Arc<JitoRpcClient>,
 ) {
+    let signature = json["params"]["result"]["signature"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    // covers decoding the swap and sizing the copy, closed before the copy is sent
+    let parse = info_span!("parse", signature = %signature, venue = "pump").entered();
     // Iterate over logs and check for unwanted_key
+    let slot = json["params"]["result"]["slot"].as_u64().unwrap_or_default();
+    let percent = config().size_pct(&target);
+    metrics::TRADES_PARSED.inc("pump");
+    drop(parse);
 
     if  {
         dirs = "buy".to_string();
@@ -159,6 +982,11 @@ pub async fn tx_pump(
             mint,
             amount_in * percent / 100,
             dirs,
+            0,
+            false,
+            target.clone(),
+            signature.clone(),
+            slot,
             timestamp.clone(),
             jito_client.clone(),
             state.clone(),
@@ -166,11 +994,16 @@ pub async fn tx_pump(
         .await;
     } else {
         dirs = "sell".to_string();
-
+        let (held, left) = target_holding(&json, &target, &mint);
         swap_to_events_on_pump(
             mint,
             amount_in * percent / 100,
             dirs,
+            share_sold(held, left),
+            left == 0,
+            target.clone(),
+            signature.clone(),
+            slot,
             timestamp.clone(),
             jito_client.clone(),
             state.clone(),
@@ -179,56 +1012,753 @@ pub async fn tx_pump(
     }
 }
 
+/// Tokens of `mint` the target held before and after its transaction.
+fn target_holding(json: &Value, target: &str, mint: &str) -> (u64, u64) {
+    let meta = &json["params"]["result"]["transaction"]["meta"];
+    let held = |key| {
+        token_balances(meta, key, target)
+            .get(mint)
+            .copied()
+            .unwrap_or(0)
+    };
+    (held("preTokenBalances"), held("postTokenBalances"))
+}
+
+/// Share of its holding the target sold, in percent: 100 only once it closed the position, a
+/// partial sell rounded down to between 1 and 99, 0 when its balance before the trade is unknown.
+fn share_sold(held: u64, left: u64) -> u64 {
+    if held == 0 {
+        return 0;
+    }
+    if left == 0 {
+        return 100;
+    }
+    let sold = held.saturating_sub(left) as u128;
+    (sold * 100 / held as u128).clamp(1, 99) as u64
+}
+
 pub async fn swap_on_jup(mint: String, dir: String, amount: u64) {
     // get tx
     jito_confirm()
 }
+/// Screen and size a copy before it is sent. Returns the amount to trade, `None` to skip it.
+async fn approve_copy(
+    state: &AppState,
+    target: &str,
+    mint: &str,
+    dirs: &str,
+    amount_in: u64,
+    sol_before: u64,
+) -> Option<u64> {
+    if dirs != "buy" {
+        // a panic sells the position itself, a copied sell would settle the same tokens twice
+        if control::selling_out() {
+            metrics::TRADES_SKIPPED.inc("panic");
+            return None;
+        }
+        return Some(amount_in);
+    }
+    // pausing stops new positions only, sells of the open ones are still copied
+    if control::paused(target) {
+        metrics::TRADES_SKIPPED.inc("paused");
+        return None;
+    }
+    if SCREENER_CONFIG.enabled {
+        match screen(
+            state.rpc_nonblocking_client.clone(),
+            state.rpc_client.clone(),
+            state.wallet.clone(),
+            mint,
+            &SCREENER_CONFIG,
+        )
+        .await
+        {
+            Ok(report) if report.accepted => {}
+            Ok(report) => {
+                info!("{}", report.summary());
+                metrics::TRADES_SKIPPED.inc("screener");
+                return None;
+            }
+            Err(e) => {
+                warn!(mint, error = %e, "screen failed, buy skipped");
+                metrics::TRADES_SKIPPED.inc("screener_error");
+                return None;
+            }
+        }
+    }
+    let spendable = wallets::spendable(state, sol_before).await;
+    let decision = risk::check_buy(target, mint, amount_in, spendable, &config().risk).await;
+    if !decision.allowed() {
+        info!(mint, reasons = %decision.reasons.join(", "), "risk rejected buy");
+        metrics::TRADES_SKIPPED.inc("risk");
+        return None;
+    }
+    Some(decision.approved)
+}
+
+fn direction_of(dirs: &str) -> SwapDirection {
+    if dirs == "buy" {
+        SwapDirection::Buy
+    } else {
+        SwapDirection::Sell
+    }
+}
+
+/// Journal the target's trade and the copy about to be made of it. Returns the attempt id, or
+/// `None` when the copy was rejected or the journal is unavailable.
+fn journal_copy(
+    target: &str,
+    signature: &str,
+    mint: &str,
+    venue: Venue,
+    dirs: &str,
+    requested: u64,
+    approved: Option<u64>,
+) -> Option<i64> {
+    let direction = direction_of(dirs);
+    let trade = TargetTrade {
+        signature: signature.to_string(),
+        target: target.to_string(),
+        mint: mint.to_string(),
+        venue,
+        direction,
+        amount: requested,
+        seen_at: chrono::Utc::now().timestamp(),
+    };
+    if let Err(e) = store().and_then(|store| store.record_target_trade(&trade)) {
+        warn!(signature, error = %e, "target trade not journaled");
+    }
+    trade_log::record(Event::TargetTrade {
+        target: trade.target,
+        signature: trade.signature,
+        mint: trade.mint,
+        venue,
+        direction,
+        amount: requested,
+    });
+    let status = match approved {
+        Some(_) => AttemptStatus::Sent,
+        None => AttemptStatus::Rejected,
+    };
+    let id = store()
+        .and_then(|store| {
+            store.record_attempt(
+                Some(signature),
+                target,
+                mint,
+                venue,
+                direction,
+                requested,
+                approved.unwrap_or(0),
+                status,
+            )
+        })
+        .map_err(|e| warn!(signature, error = %e, "copy attempt not journaled"))
+        .ok();
+    approved.and(id)
+}
+
+/// Mark the journaled attempt landed or failed.
+fn journal_outcome(attempt_id: Option<i64>, res: &anyhow::Result<Vec<String>>) {
+    let Some(id) = attempt_id else {
+        return;
+    };
+    let journaled = store().and_then(|store| match res {
+        Ok(signatures) => store.update_attempt(id, AttemptStatus::Landed, None, signatures),
+        Err(e) => store.update_attempt(id, AttemptStatus::Failed, Some(&e.to_string()), &[]),
+    });
+    if let Err(e) = journaled {
+        warn!(attempt = id, error = %e, "copy attempt outcome not journaled");
+    }
+}
+
+/// Record the outcome of one swap of a copy against the positions and the daily loss.
+async fn settle_copy(
+    state: &AppState,
+    target: &str,
+    mint: &str,
+    venue: Venue,
+    pool_id: Option<String>,
+    dirs: &str,
+    sol_before: u64,
+    tokens_before: u64,
+    attempt_id: Option<i64>,
+    res: &anyhow::Result<Vec<String>>,
+) {
+    let Ok(signatures) = res else {
+        return;
+    };
+    match position::settle(
+        state,
+        target,
+        mint,
+        venue,
+        pool_id,
+        direction_of(dirs),
+        sol_before,
+        tokens_before,
+        signatures,
+        attempt_id,
+    )
+    .await
+    {
+        Ok(fill) => {
+            if let Some(realized) = fill.realized {
+                risk::record_realized(realized, &config().risk).await;
+            }
+        }
+        Err(e) => warn!(mint, error = %e, "copy not settled"),
+    }
+}
+
+fn venue_of(pool_id: &Option<String>) -> Venue {
+    match pool_id {
+        Some(_) => Venue::Raydium,
+        None => Venue::Pump,
+    }
+}
+
+/// Log, measure and settle one swap of a copy made from the `leg` wallet.
+async fn finish_leg(
+    leg: &AppState,
+    target: &str,
+    signature: &str,
+    mint: &str,
+    pool_id: Option<String>,
+    dirs: &str,
+    amount: u64,
+    sol_before: u64,
+    tokens_before: u64,
+    attempt_id: Option<i64>,
+    target_slot: u64,
+    timestamp: Instant,
+    res: &anyhow::Result<Vec<String>>,
+) {
+    let venue = venue_of(&pool_id);
+    trade_log::record(Event::Copy {
+        target: target.to_string(),
+        target_signature: signature.to_string(),
+        mint: mint.to_string(),
+        venue,
+        direction: direction_of(dirs),
+        amount,
+        wallet: leg.wallet.pubkey().to_string(),
+        landed: res.is_ok(),
+        signatures: res.as_ref().cloned().unwrap_or_default(),
+        error: res.as_ref().err().map(|e| e.to_string()),
+        latency_ms: timestamp.elapsed().as_millis() as u64,
+    });
+    match res {
+        Ok(signatures) => {
+            let elapsed = timestamp.elapsed();
+            telemetry::observe("land", elapsed);
+            info!(wallet = %leg.wallet.pubkey(), ?elapsed, "copy landed");
+            metrics::COPIES.inc("landed");
+            if !PAPER_CONFIG.enabled {
+                let client = leg.rpc_nonblocking_client.clone();
+                metrics::track_slot_delay(client, target_slot, signatures.clone());
+            }
+        }
+        Err(_) => metrics::COPIES.inc("failed"),
+    }
+    settle_copy(
+        leg,
+        target,
+        mint,
+        venue,
+        pool_id,
+        dirs,
+        sol_before,
+        tokens_before,
+        attempt_id,
+        res,
+    )
+    .await;
+}
+
+/// Copy a target closing its position with the pre-signed exit, journaled and settled like
+/// any other copy. Returns false when there is no pre-signed exit or it did not land, for a
+/// fresh sell to be sent instead.
+async fn copy_with_presigned_exit(
+    state: &AppState,
+    target: &str,
+    signature: &str,
+    mint: &str,
+    pool_id: Option<String>,
+    amount_in: u64,
+    target_slot: u64,
+    timestamp: Instant,
+) -> bool {
+    if !has_exit(mint).await {
+        return false;
+    }
+    let sol_before = sol_balance(state).await.unwrap_or_default();
+    let tokens_before = holding(state, mint).await.unwrap_or(0);
+    let res = match fire_exit(state, mint, venue_of(&pool_id)).await {
+        Some(ExitOutcome::Landed(exit_signature)) => Ok(vec![exit_signature]),
+        Some(ExitOutcome::Invalidated(e)) => {
+            warn!(mint, error = %e, "presigned exit invalidated, sending a fresh sell");
+            return false;
+        }
+        // a fresh sell could sell the position twice if the exit still lands
+        Some(ExitOutcome::Pending(e)) => {
+            error!(mint, error = %e, "presigned exit may still land, not sending a fresh sell");
+            Err(e)
+        }
+        None => return false,
+    };
+    let attempt_id = journal_copy(
+        target,
+        signature,
+        mint,
+        venue_of(&pool_id),
+        "sell",
+        amount_in,
+        Some(tokens_before),
+    );
+    metrics::COPIES.inc("sent");
+    finish_leg(
+        state,
+        target,
+        signature,
+        mint,
+        pool_id,
+        "sell",
+        tokens_before,
+        sol_before,
+        tokens_before,
+        attempt_id,
+        target_slot,
+        timestamp,
+        &res,
+    )
+    .await;
+    journal_outcome(attempt_id, &res);
+    risk::record_copy(res.is_ok(), &config().risk).await;
+    true
+}
+
+/// Keep the pre-signed exit in step with the holding after a copy: signed again for what is
+/// held now, or dropped once the position is closed. Exits are presigned for the main wallet
+/// only.
+async fn refresh_presigned_exit(state: &AppState, mint: &str, pool_id: Option<String>) {
+    if NONCE_ACCOUNTS.is_empty() || PAPER_CONFIG.enabled || wallets::pooled() {
+        return;
+    }
+    let slippage = config().trading.slippage_bps;
+    let res = match pool_id {
+        _ if position::get(mint).await.is_none() => discard_exit(state, mint).await,
+        None => prepare_pump_exit(state, mint, slippage).await,
+        Some(pool_id) => prepare_raydium_exit(state, mint, pool_id, slippage).await,
+    };
+    if let Err(e) = res {
+        warn!(mint, error = %e, "presigned exit not prepared");
+    }
+}
+
+/// Make an approved copy from the execution wallets, one swap per wallet `wallets::allocate`
+/// picks, and settle each. A sell with `sold_pct` sells that share of each wallet's holding.
+/// Returns the signatures of every swap that landed, or the errors when none did.
+async fn execute_copy(
+    state: &AppState,
+    target: &str,
+    signature: &str,
+    mint: &str,
+    pool_id: Option<String>,
+    dirs: &str,
+    amount_in: u64,
+    sold_pct: u64,
+    sol_before: u64,
+    tokens_before: u64,
+    attempt_id: Option<i64>,
+    target_slot: u64,
+    timestamp: Instant,
+    jito_client: Arc<JitoRpcClient>,
+) -> anyhow::Result<Vec<String>> {
+    let slippage = config().trading.slippage_bps;
+    let simulate = SIMULATION_CONFIG.enabled_for(target);
+    let legs = match wallets::allocate(state, target, mint, direction_of(dirs), amount_in).await {
+        Ok(legs) => legs,
+        Err(e) => {
+            metrics::TRADES_SKIPPED.inc("no_wallet");
+            let res = Err(e);
+            journal_outcome(attempt_id, &res);
+            risk::record_copy(false, &config().risk).await;
+            return res;
+        }
+    };
+    let mut landed = vec![];
+    let mut errors = vec![];
+    for leg in legs {
+        // the balances of the main wallet were read before the copy was approved
+        let (sol_before, tokens_before) = if wallets::pooled() {
+            (
+                sol_balance(&leg.state).await.unwrap_or_default(),
+                holding(&leg.state, mint).await.unwrap_or(0),
+            )
+        } else {
+            (sol_before, tokens_before)
+        };
+        metrics::COPIES.inc("sent");
+        // resolved against the holding when the swap is built, the ledger can be behind it
+        let (amount, in_type) = match direction_of(dirs) {
+            SwapDirection::Sell if sold_pct > 0 => (sold_pct, SwapInType::Pct),
+            _ => (leg.amount, SwapInType::Qty),
+        };
+        let res = match &pool_id {
+            None => {
+                pump_swap(
+                    leg.state.clone(),
+                    amount,
+                    in_type,
+                    direction_of(dirs),
+                    slippage,
+                    mint,
+                    simulate,
+                    jito_client.clone(),
+                    timestamp,
+                )
+                .await
+            }
+            Some(pool_id) => {
+                raydium_swap(
+                    leg.state.clone(),
+                    amount,
+                    in_type,
+                    direction_of(dirs),
+                    pool_id.clone(),
+                    slippage,
+                    mint,
+                    simulate,
+                    jito_client.clone(),
+                    timestamp,
+                )
+                .await
+            }
+        };
+        finish_leg(
+            &leg.state,
+            target,
+            signature,
+            mint,
+            pool_id.clone(),
+            dirs,
+            leg.amount,
+            sol_before,
+            tokens_before,
+            attempt_id,
+            target_slot,
+            timestamp,
+            &res,
+        )
+        .await;
+        if wallets::pooled() {
+            let _ = wallets::refresh_balance(&leg.state).await;
+        }
+        match res {
+            Ok(signatures) => landed.extend(signatures),
+            Err(e) if wallets::pooled() => {
+                errors.push(format!("{}: {}", leg.state.wallet.pubkey(), e))
+            }
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    let res = if landed.is_empty() {
+        Err(anyhow!("{}", errors.join("; ")))
+    } else {
+        Ok(landed)
+    };
+    journal_outcome(attempt_id, &res);
+    // once per copy, however many wallets it was split across
+    risk::record_copy(res.is_ok(), &config().risk).await;
+    res
+}
+
+#[instrument(
+    name = "copy",
+    skip_all,
+    fields(signature = %signature, target = %target, venue = "pump", direction = %dirs)
+)]
 pub async fn swap_to_events_on_pump(
     mint: String,
     amount_in: u64,
     dirs: String,
+    sold_pct: u64,
+    full_exit: bool,
+    target: String,
+    signature: String,
+    slot: u64,
     timestamp: Instant,
     jito_client: Arc<JitoRpcClient>,
     state: AppState,
 ) {
-    println!("2: {:#?}", timestamp.elapsed().clone());
+    // the pre-signed exit sells the whole holding, so it only copies a full exit
+    if dirs == "sell" && full_exit {
+        let presigned = copy_with_presigned_exit(
+            &state, &target, &signature, &mint, None, amount_in, slot, timestamp,
+        )
+        .await;
+        if presigned {
+            return;
+        }
+    }
 
-    let slippage = 10000;
-    println!("2.1: {:#?}", timestamp.elapsed());
-    let res = pump_swap(
-        state,
-        amount_in,
+    let size = info_span!("size");
+    let sol_before = wallets::available_sol(&state)
+        .instrument(size.clone())
+        .await
+        .unwrap_or_default();
+    // what the wallet holds, the ledger misses tokens moved outside the bot
+    let tokens_before = holding(&state, &mint)
+        .instrument(size.clone())
+        .await
+        .unwrap_or(0);
+    drop(size);
+    let approved = approve_copy(&state, &target, &mint, &dirs, amount_in, sol_before)
+        .instrument(info_span!("risk"))
+        .await;
+    let attempt_id = journal_copy(
+        &target,
+        &signature,
+        &mint,
+        Venue::Pump,
         &dirs,
-        slippage,
+        amount_in,
+        approved,
+    );
+    let Some(amount_in) = approved else {
+        return;
+    };
+
+    let res = execute_copy(
+        &state,
+        &target,
+        &signature,
         &mint,
-        jito_client,
-        timestamp.clone(),
+        None,
+        &dirs,
+        amount_in,
+        sold_pct,
+        sol_before,
+        tokens_before,
+        attempt_id,
+        slot,
+        timestamp,
+        jito_client.clone(),
     )
     .await;
+
+    if res.is_ok() {
+        refresh_presigned_exit(&state, &mint, None).await;
+    }
+    if res.is_ok() && dirs == "buy" {
+        let executors = wallets::executors(&state).await;
+        let owners = executors.iter().map(|executor| executor.wallet.pubkey());
+        lut::extend_with_position(&state, owners.collect(), mint.clone());
+    }
+    if res.is_ok() && dirs == "buy" && config().exit.enabled {
+        exit::watch(state, jito_client, mint).await;
+    }
 }
 
+#[instrument(
+    name = "copy",
+    skip_all,
+    fields(
+        signature = %signature,
+        target = %target,
+        venue = "raydium",
+        pool = %pool_id,
+        direction = %dirs
+    )
+)]
 pub async fn swap_to_events_on_raydium(
     mint: String,
     amount_in: u64,
     dirs: String,
+    sold_pct: u64,
+    full_exit: bool,
     pool_id: String,
+    target: String,
+    signature: String,
+    slot: u64,
     timestamp: Instant,
     jito_client: Arc<JitoRpcClient>,
     state: AppState,
 ) {
-    println!("2: {:#?}", timestamp.elapsed().clone());
+    // the pre-signed exit sells the whole holding, so it only copies a full exit
+    if dirs == "sell" && full_exit {
+        let presigned = copy_with_presigned_exit(
+            &state, &target, &signature, &mint, Some(pool_id.clone()), amount_in, slot, timestamp,
+        )
+        .await;
+        if presigned {
+            return;
+        }
+    }
 
-    let slippage = 10000;
-    println!("2.1: {:#?}", timestamp.elapsed());
-    let res = raydium_swap(
-        state,
-        amount_in,
+    let size = info_span!("size");
+    let sol_before = wallets::available_sol(&state)
+        .instrument(size.clone())
+        .await
+        .unwrap_or_default();
+    // what the wallet holds, the ledger misses tokens moved outside the bot
+    let tokens_before = holding(&state, &mint)
+        .instrument(size.clone())
+        .await
+        .unwrap_or(0);
+    drop(size);
+    let approved = approve_copy(&state, &target, &mint, &dirs, amount_in, sol_before)
+        .instrument(info_span!("risk"))
+        .await;
+    let attempt_id = journal_copy(
+        &target,
+        &signature,
+        &mint,
+        Venue::Raydium,
         &dirs,
-        pool_id,
-        slippage,
+        amount_in,
+        approved,
+    );
+    let Some(amount_in) = approved else {
+        return;
+    };
+
+    let res = execute_copy(
+        &state,
+        &target,
+        &signature,
         &mint,
-        jito_client,
-        timestamp.clone(),
+        Some(pool_id.clone()),
+        &dirs,
+        amount_in,
+        sold_pct,
+        sol_before,
+        tokens_before,
+        attempt_id,
+        slot,
+        timestamp,
+        jito_client.clone(),
     )
     .await;
+
+    if res.is_ok() {
+        refresh_presigned_exit(&state, &mint, Some(pool_id)).await;
+    }
+    if res.is_ok() && dirs == "buy" {
+        let executors = wallets::executors(&state).await;
+        let owners = executors.iter().map(|executor| executor.wallet.pubkey());
+        lut::extend_with_position(&state, owners.collect(), mint.clone());
+    }
+    if res.is_ok() && dirs == "buy" && config().exit.enabled {
+        exit::watch(state, jito_client, mint).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temp::services::recorder::read_recording;
+
+    // a transactionNotification as the stream delivers it and the recorder keeps it, with the
+    // token balances the target held in `mint` before and after
+    fn notification(target: &str, mint: &str, pre: Option<u64>, post: Option<u64>) -> String {
+        let balance = |amount: Option<u64>| match amount {
+            Some(amount) => serde_json::json!([{
+                "accountIndex": 1,
+                "mint": mint,
+                "owner": target,
+                "uiTokenAmount": { "amount": amount.to_string(), "decimals": 6 },
+            }]),
+            None => serde_json::json!([]),
+        };
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "transactionNotification",
+            "params": {
+                "result": {
+                    "signature": "sig",
+                    "slot": 300_000_000u64,
+                    "transaction": {
+                        "transaction": {
+                            "message": { "accountKeys": [{ "pubkey": target }] }
+                        },
+                        "meta": {
+                            "preTokenBalances": balance(pre),
+                            "postTokenBalances": balance(post),
+                        },
+                    },
+                },
+            },
+        })
+        .to_string()
+    }
+
+    // written with the recorder and read back, as a replay would see them
+    async fn recorded(messages: &[String]) -> Vec<Value> {
+        let dir = std::env::temp_dir().join(format!("main-tests-{}", Pubkey::new_unique()));
+        let recorder = Recorder::start(&dir).unwrap();
+        for message in messages {
+            recorder.record(message);
+        }
+        recorder.flush().await;
+        let recorded = read_recording(&recorder.path)
+            .unwrap()
+            .into_iter()
+            .map(|recorded| serde_json::from_str(&recorded.message).unwrap())
+            .collect();
+        std::fs::remove_dir_all(dir).unwrap();
+        recorded
+    }
+
+    #[tokio::test]
+    async fn recorded_sells_tell_full_and_partial_exits_apart() {
+        let (target, mint) = (Pubkey::new_unique().to_string(), Pubkey::new_unique().to_string());
+        let messages = recorded(&[
+            notification(&target, &mint, Some(1_000), Some(400)),
+            notification(&target, &mint, Some(400), Some(0)),
+            notification(&target, &mint, Some(400), None),
+        ])
+        .await;
+        assert_eq!(target_holding(&messages[0], &target, &mint), (1_000, 400));
+        assert_eq!(target_holding(&messages[1], &target, &mint), (400, 0));
+        // a closed token account drops out of the post balances
+        assert_eq!(target_holding(&messages[2], &target, &mint), (400, 0));
+    }
+
+    #[tokio::test]
+    async fn recorded_first_buy_starts_from_nothing() {
+        let (target, mint) = (Pubkey::new_unique().to_string(), Pubkey::new_unique().to_string());
+        let messages = recorded(&[notification(&target, &mint, None, Some(5_000))]).await;
+        assert_eq!(target_holding(&messages[0], &target, &mint), (0, 5_000));
+    }
+
+    #[test]
+    fn balances_of_other_owners_and_mints_are_ignored() {
+        let (target, mint) = (Pubkey::new_unique().to_string(), Pubkey::new_unique().to_string());
+        let other = Pubkey::new_unique().to_string();
+        let json: Value =
+            serde_json::from_str(&notification(&other, &mint, Some(10), Some(0))).unwrap();
+        assert_eq!(target_holding(&json, &target, &mint), (0, 0));
+        let json: Value =
+            serde_json::from_str(&notification(&target, &other, Some(10), Some(0))).unwrap();
+        assert_eq!(target_holding(&json, &target, &mint), (0, 0));
+    }
+
+    #[test]
+    fn venue_follows_the_pool() {
+        assert_eq!(venue_of(&None), Venue::Pump);
+        assert_eq!(venue_of(&Some("pool".to_string())), Venue::Raydium);
+    }
+
+    #[test]
+    fn sells_copy_the_share_of_the_holding_the_target_sold() {
+        assert_eq!(share_sold(1_000, 0), 100);
+        assert_eq!(share_sold(1_000, 400), 60);
+        assert_eq!(share_sold(1_000, 999), 1);
+        assert_eq!(share_sold(1_000, 996), 1);
+        assert_eq!(share_sold(1_000, 4), 99);
+        assert_eq!(share_sold(3, 1), 66);
+        assert_eq!(share_sold(0, 0), 0);
+    }
 }
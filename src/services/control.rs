@@ -0,0 +1,503 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock, RwLock,
+    },
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use jito_json_rpc_client::jsonrpc_client::rpc_client::RpcClient as JitoRpcClient;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use solana_sdk::signature::Signer;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::{
+    common::{
+        config::{self, config, TargetConfig},
+        utils::{log_message, AppState},
+    },
+    engine::{exit, paper::PAPER_CONFIG, position, wallets},
+    services::{
+        http::{self, Request, Response},
+        metrics,
+        store::store,
+    },
+};
+
+/// Address the control API listens on (`CONTROL_ADDR`, e.g. `127.0.0.1:9185`), off when unset.
+/// Only loopback addresses are accepted.
+pub static CONTROL_ADDR: LazyLock<Option<String>> =
+    LazyLock::new(|| std::env::var("CONTROL_ADDR").ok());
+
+/// Bearer token every request must carry (`CONTROL_TOKEN`)
+pub static CONTROL_TOKEN: LazyLock<Option<String>> = LazyLock::new(|| {
+    std::env::var("CONTROL_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty())
+});
+
+// buys of every target paused at once, without touching the config
+static PAUSED: AtomicBool = AtomicBool::new(false);
+// targets whose buys are paused; they stay subscribed so their sells are still copied
+static PAUSED_TARGETS: LazyLock<RwLock<HashSet<String>>> =
+    LazyLock::new(|| RwLock::new(HashSet::new()));
+// set while a panic sells every position, so no other sell races it for the same tokens
+static SELLING_OUT: AtomicBool = AtomicBool::new(false);
+static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+const DEFAULT_TRADES: usize = 50;
+
+/// Whether copying the target's buys is paused from the control API, for every target or for
+/// this one. Sells are copied either way, so positions are not left behind.
+pub fn paused(target: &str) -> bool {
+    PAUSED.load(Ordering::Relaxed) || target_paused(target)
+}
+
+fn target_paused(target: &str) -> bool {
+    PAUSED_TARGETS
+        .read()
+        .is_ok_and(|targets| targets.contains(target))
+}
+
+/// Whether a panic is selling every position. Copied sells and exit rules stand aside until it
+/// is done, otherwise two sells of the same tokens settle against each other.
+pub fn selling_out() -> bool {
+    SELLING_OUT.load(Ordering::Relaxed)
+}
+
+// pause or resume the buys of one target, or of every target when `target` is `None`
+fn set_paused(target: Option<&str>, pause: bool) {
+    let Some(address) = target else {
+        PAUSED.store(pause, Ordering::Relaxed);
+        return;
+    };
+    if let Ok(mut targets) = PAUSED_TARGETS.write() {
+        if pause {
+            targets.insert(address.to_string());
+        } else {
+            targets.remove(address);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PauseRequest {
+    /// One target instead of all
+    target: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SellRequest {
+    mint: String,
+    /// Share of the position to sell, in percent
+    #[serde(default = "all")]
+    pct: u64,
+}
+
+fn all() -> u64 {
+    100
+}
+
+fn sell_request(request: &Request) -> Result<SellRequest> {
+    let request: SellRequest = body(request)?;
+    if !(1..=100).contains(&request.pct) {
+        return Err(anyhow!(
+            "Control: pct must be within 1..=100: {}",
+            request.pct
+        ));
+    }
+    Ok(request)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SizingRequest {
+    size_pct: Option<u64>,
+    slippage_bps: Option<u64>,
+    /// Set `size_pct` of this target rather than the default
+    target: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetRequest {
+    address: String,
+    label: Option<String>,
+    size_pct: Option<u64>,
+    #[serde(default = "enabled")]
+    enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+// compares every byte whatever the first difference, so the time taken says nothing of the token
+fn same_token(given: &str, token: &str) -> bool {
+    let (given, token) = (given.as_bytes(), token.as_bytes());
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token.iter())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| same_token(given.trim(), token))
+}
+
+fn body<T: DeserializeOwned>(request: &Request) -> Result<T> {
+    let body: &[u8] = if request.body.is_empty() {
+        b"{}"
+    } else {
+        &request.body
+    };
+    serde_json::from_slice(body).map_err(|e| anyhow!("Control: bad request body: {}", e))
+}
+
+// apply a change to the running config and log what it changed
+async fn update(change: impl Fn(&mut config::Config) + Send + Sync + 'static) -> Response {
+    match config::update(change) {
+        Ok(changes) => {
+            if !changes.is_empty() {
+                let message = format!("Control: config updated:\n  {}", changes.join("\n  "));
+                info!("{}", message);
+                log_message(&message);
+            }
+            Response::json(200, &json!({ "changes": changes }))
+        }
+        Err(e) => Response::error(400, e),
+    }
+}
+
+async fn status(state: &AppState) -> Response {
+    let config = config();
+    let positions = position::all().await;
+    let exposure: u64 = positions.iter().map(|p| p.cost_lamports).sum();
+    let targets: Vec<_> = config
+        .targets
+        .iter()
+        .map(|target| {
+            json!({
+                "address": target.address,
+                "label": target.label,
+                "enabled": target.enabled,
+                "paused": target_paused(&target.address),
+                "size_pct": config.size_pct(&target.address),
+            })
+        })
+        .collect();
+    Response::json(
+        200,
+        &json!({
+            "paused": PAUSED.load(Ordering::Relaxed),
+            "uptime_secs": STARTED.elapsed().as_secs(),
+            "wallet": state.wallet.pubkey().to_string(),
+            "pooled": wallets::pooled(),
+            "paper": PAPER_CONFIG.enabled,
+            "targets": targets,
+            "trading": config.trading,
+            "stream": {
+                "connected": metrics::STREAM_CONNECTED.load(Ordering::Relaxed),
+                "last_message_at": metrics::LAST_MESSAGE_AT.load(Ordering::Relaxed),
+                "reconnects": metrics::WS_RECONNECTS.get(),
+            },
+            "positions": positions.len(),
+            "exposure_lamports": exposure,
+        }),
+    )
+}
+
+async fn pause(request: &Request, pause: bool) -> Response {
+    let request: PauseRequest = match body(request) {
+        Ok(request) => request,
+        Err(e) => return Response::error(400, e),
+    };
+    let Some(address) = request.target else {
+        set_paused(None, pause);
+        let message = if pause {
+            "Control: buys paused, sells are still copied"
+        } else {
+            "Control: buys resumed"
+        };
+        info!("{}", message);
+        log_message(message);
+        return Response::json(200, &json!({ "paused": pause }));
+    };
+    if !config().targets.iter().any(|t| t.address == address) {
+        return Response::error(404, format!("no target {}", address));
+    }
+    set_paused(Some(&address), pause);
+    let message = format!(
+        "Control: buys of {} {}",
+        address,
+        if pause { "paused" } else { "resumed" }
+    );
+    info!("{}", message);
+    log_message(&message);
+    Response::json(200, &json!({ "paused": pause, "target": address }))
+}
+
+async fn sell(request: &Request, state: &AppState, jito_client: Arc<JitoRpcClient>) -> Response {
+    let request = match sell_request(request) {
+        Ok(request) => request,
+        Err(e) => return Response::error(400, e),
+    };
+    if position::get(&request.mint).await.is_none() {
+        return Response::error(404, format!("no open position in {}", request.mint));
+    }
+    let reason = format!("control sell {}%", request.pct);
+    match exit::force_sell(state, jito_client, &request.mint, request.pct, &reason).await {
+        Ok(()) => Response::json(200, &json!({ "sold": request.mint })),
+        Err(e) => Response::error(500, e),
+    }
+}
+
+// pause, then sell every position in full while copied and exit sells wait
+async fn panic(state: &AppState, jito_client: Arc<JitoRpcClient>) -> Response {
+    if SELLING_OUT.swap(true, Ordering::Relaxed) {
+        return Response::error(409, "a panic is already selling");
+    }
+    set_paused(None, true);
+    log_message("Control: panic, copying paused and selling every position");
+    let mut sold = vec![];
+    let mut failed = vec![];
+    for position in position::all().await {
+        let res = exit::force_sell(state, jito_client.clone(), &position.mint, 100, "panic").await;
+        match res {
+            Ok(()) => sold.push(position.mint),
+            Err(e) => failed.push(json!({ "mint": position.mint, "error": e.to_string() })),
+        }
+    }
+    SELLING_OUT.store(false, Ordering::Relaxed);
+    let status = if failed.is_empty() { 200 } else { 500 };
+    Response::json(
+        status,
+        &json!({ "paused": true, "sold": sold, "failed": failed }),
+    )
+}
+
+async fn sizing(request: &Request) -> Response {
+    let request: SizingRequest = match body(request) {
+        Ok(request) => request,
+        Err(e) => return Response::error(400, e),
+    };
+    if request.size_pct.is_none() && request.slippage_bps.is_none() {
+        return Response::error(400, "nothing to change, give size_pct or slippage_bps");
+    }
+    if let Some(address) = request.target.as_deref() {
+        if !config().targets.iter().any(|t| t.address == address) {
+            return Response::error(404, format!("no target {}", address));
+        }
+    }
+    update(move |config| {
+        if let Some(size_pct) = request.size_pct {
+            match request.target.as_deref() {
+                Some(address) => {
+                    for target in config.targets.iter_mut().filter(|t| t.address == address) {
+                        target.size_pct = Some(size_pct);
+                    }
+                }
+                None => config.trading.size_pct = size_pct,
+            }
+        }
+        if let Some(slippage_bps) = request.slippage_bps {
+            config.trading.slippage_bps = slippage_bps;
+        }
+    })
+    .await
+}
+
+async fn add_target(request: &Request) -> Response {
+    let request: TargetRequest = match body(request) {
+        Ok(request) => request,
+        Err(e) => return Response::error(400, e),
+    };
+    if config()
+        .targets
+        .iter()
+        .any(|t| t.address == request.address)
+    {
+        return Response::error(400, format!("{} is already a target", request.address));
+    }
+    let target = TargetConfig {
+        address: request.address,
+        label: request.label,
+        enabled: request.enabled,
+        size_pct: request.size_pct,
+    };
+    let (address, label, active) = (target.address.clone(), target.label.clone(), target.enabled);
+    let response = update(move |config| {
+        if !config.targets.iter().any(|t| t.address == target.address) {
+            config.targets.push(target.clone());
+        }
+    })
+    .await;
+    if response.status == 200 {
+        if let Err(e) =
+            store().and_then(|store| store.upsert_target(&address, label.as_deref(), active))
+        {
+            warn!(target = %address, error = %e, "target not journaled");
+        }
+    }
+    response
+}
+
+async fn remove_target(address: &str) -> Response {
+    if !config().targets.iter().any(|t| t.address == address) {
+        return Response::error(404, format!("no target {}", address));
+    }
+    let removed = address.to_string();
+    let response = update(move |config| config.targets.retain(|t| t.address != removed)).await;
+    if response.status == 200 {
+        // the journal keeps its trades, inactive
+        if let Err(e) = store().and_then(|store| store.upsert_target(address, None, false)) {
+            warn!(target = address, error = %e, "target not journaled");
+        }
+    }
+    response
+}
+
+async fn respond(request: Request, state: AppState, jito_client: Arc<JitoRpcClient>) -> Response {
+    let Some(token) = CONTROL_TOKEN.as_deref() else {
+        return Response::error(401, "no CONTROL_TOKEN");
+    };
+    if !authorized(&request, token) {
+        return Response::error(401, "missing or wrong bearer token");
+    }
+    let method = request.method.as_str();
+    let path = request.path.trim_end_matches('/');
+    match (method, path) {
+        ("GET", "/status") => status(&state).await,
+        ("GET", "/positions") => Response::json(200, &position::all().await),
+        ("GET", "/trades") => {
+            let limit = request
+                .query("limit")
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(DEFAULT_TRADES);
+            match store().and_then(|store| store.recent_attempts(limit)) {
+                Ok(attempts) => Response::json(200, &attempts),
+                Err(e) => Response::error(500, e),
+            }
+        }
+        ("POST", "/pause") => pause(&request, true).await,
+        ("POST", "/resume") => pause(&request, false).await,
+        ("POST", "/sell") => sell(&request, &state, jito_client).await,
+        ("POST", "/panic") => panic(&state, jito_client).await,
+        ("POST", "/sizing") => sizing(&request).await,
+        ("POST", "/targets") => add_target(&request).await,
+        ("DELETE", _) if path.starts_with("/targets/") => {
+            remove_target(&path["/targets/".len()..]).await
+        }
+        (
+            _,
+            "/status" | "/positions" | "/trades" | "/pause" | "/resume" | "/sell" | "/panic"
+            | "/sizing" | "/targets",
+        ) => Response::error(405, "method not allowed"),
+        _ => Response::error(404, "not found"),
+    }
+}
+
+/// Serve the control API on `CONTROL_ADDR`. Refuses to start on a non-loopback address or
+/// without `CONTROL_TOKEN`, since the API can sell every position.
+pub async fn spawn_server(state: AppState, jito_client: Arc<JitoRpcClient>) -> Result<()> {
+    let Some(addr) = CONTROL_ADDR.as_deref() else {
+        return Ok(());
+    };
+    if CONTROL_TOKEN.is_none() {
+        return Err(anyhow!("Control: CONTROL_TOKEN is not set"));
+    }
+    let listener = TcpListener::bind(addr).await?;
+    let local = listener.local_addr()?;
+    if !local.ip().is_loopback() {
+        return Err(anyhow!("Control: {} is not a loopback address", local));
+    }
+    LazyLock::force(&STARTED);
+    info!(addr = %local, "control API listening");
+    http::serve("control", listener, move |request| {
+        respond(request, state.clone(), jito_client.clone())
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(authorization: Option<&str>, body: &str) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: "/sell".to_string(),
+            query: vec![],
+            headers: authorization
+                .map(|value| vec![("authorization".to_string(), value.to_string())])
+                .unwrap_or_default(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn tokens_match_only_when_equal() {
+        assert!(same_token("s3cret", "s3cret"));
+        assert!(!same_token("s3cres", "s3cret"));
+        assert!(!same_token("s3cre", "s3cret"));
+        assert!(!same_token("s3crets", "s3cret"));
+        assert!(!same_token("", "s3cret"));
+    }
+
+    #[test]
+    fn requests_need_the_bearer_token() {
+        assert!(authorized(&request(Some("Bearer s3cret"), ""), "s3cret"));
+        assert!(authorized(&request(Some("Bearer s3cret "), ""), "s3cret"));
+        assert!(!authorized(&request(Some("Bearer wrong!"), ""), "s3cret"));
+        assert!(!authorized(&request(Some("s3cret"), ""), "s3cret"));
+        assert!(!authorized(&request(Some("Basic s3cret"), ""), "s3cret"));
+        assert!(!authorized(&request(None, ""), "s3cret"));
+    }
+
+    #[test]
+    fn sells_take_a_share_within_one_to_a_hundred_percent() {
+        let sell = sell_request(&request(None, r#"{"mint":"m"}"#)).unwrap();
+        assert_eq!((sell.mint.as_str(), sell.pct), ("m", 100));
+        assert_eq!(
+            sell_request(&request(None, r#"{"mint":"m","pct":1}"#))
+                .unwrap()
+                .pct,
+            1
+        );
+        assert!(sell_request(&request(None, r#"{"mint":"m","pct":0}"#)).is_err());
+        assert!(sell_request(&request(None, r#"{"mint":"m","pct":101}"#)).is_err());
+        assert!(sell_request(&request(None, r#"{"mint":"m","pct":"all"}"#)).is_err());
+        assert!(sell_request(&request(None, "")).is_err());
+    }
+
+    // one test, the flags are process wide
+    #[test]
+    fn pausing_a_target_leaves_the_others_copying() {
+        set_paused(Some("a"), true);
+        assert!(paused("a"));
+        assert!(!paused("b"));
+
+        set_paused(None, true);
+        assert!(paused("a") && paused("b"));
+        set_paused(None, false);
+        assert!(paused("a"));
+        assert!(!paused("b"));
+
+        set_paused(Some("a"), false);
+        assert!(!paused("a"));
+        // resuming a target that was never paused changes nothing
+        set_paused(Some("b"), false);
+        assert!(!paused("b"));
+    }
+}
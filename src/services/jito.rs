@@ -11,14 +11,14 @@ use tokio::{
     time::{sleep, Instant},
 };
 
-use crate::common::utils::{import_env_var, log_message};
+use crate::common::{config::config, utils::log_message};
 
 pub static BLOCK_ENGINE_URL: LazyLock<String> =
-    LazyLock::new(|| import_env_var("JITO_BLOCK_ENGINE_URL"));
+    LazyLock::new(|| config().endpoints.jito_block_engine.clone());
 pub static TIP_STREAM_URL: LazyLock<String> =
-    LazyLock::new(|| import_env_var("JITO_TIP_STREAM_URL"));
+    LazyLock::new(|| config().endpoints.jito_tip_stream.clone());
 pub static TIP_PERCENTILE: LazyLock<String> =
-    LazyLock::new(|| import_env_var("JITO_TIP_PERCENTILE"));
+    LazyLock::new(|| config().endpoints.jito_tip_percentile.clone());
 
 pub static TIP_ACCOUNTS: LazyLock<RwLock<Vec<String>>> = LazyLock::new(|| RwLock::new(vec![]));
 
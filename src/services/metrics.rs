@@ -0,0 +1,362 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write as _},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+};
+
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::signature::Signature;
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::{
+    common::telemetry::{self, BUCKETS_MS},
+    engine::position,
+    services::http::{self, Request, Response},
+};
+
+/// Address the Prometheus endpoint listens on (`METRICS_ADDR`, e.g. `127.0.0.1:9184`), off when
+/// unset
+pub static METRICS_ADDR: LazyLock<Option<String>> =
+    LazyLock::new(|| std::env::var("METRICS_ADDR").ok());
+
+/// Upper bounds of the slot delay buckets
+const SLOT_BUCKETS: [u64; 10] = [0, 1, 2, 3, 4, 5, 8, 13, 21, 34];
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counter with one label
+pub struct LabeledCounter(Mutex<BTreeMap<String, u64>>);
+
+impl LabeledCounter {
+    const fn new() -> Self {
+        Self(Mutex::new(BTreeMap::new()))
+    }
+
+    pub fn inc(&self, label: &str) {
+        if let Ok(mut counts) = self.0.lock() {
+            *counts.entry(label.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    fn snapshot(&self) -> Vec<(String, u64)> {
+        self.0
+            .lock()
+            .map(|counts| counts.iter().map(|(k, v)| (k.clone(), *v)).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Stream notifications received
+pub static TRADES_SEEN: Counter = Counter::new();
+/// Target trades decoded, by venue
+pub static TRADES_PARSED: LabeledCounter = LabeledCounter::new();
+/// Target trades not copied, by reason
+pub static TRADES_SKIPPED: LabeledCounter = LabeledCounter::new();
+/// Copy swaps by outcome: `sent`, `landed` or `failed`
+pub static COPIES: LabeledCounter = LabeledCounter::new();
+pub static TIP_LAMPORTS: Counter = Counter::new();
+pub static PRIORITY_FEE_LAMPORTS: Counter = Counter::new();
+pub static NETWORK_FEE_LAMPORTS: Counter = Counter::new();
+/// Stream connections re-established after a drop
+pub static WS_RECONNECTS: Counter = Counter::new();
+/// Whether the stream is connected
+pub static STREAM_CONNECTED: AtomicBool = AtomicBool::new(false);
+/// Unix time of the last stream message, 0 before the first
+pub static LAST_MESSAGE_AT: AtomicI64 = AtomicI64::new(0);
+// slots between the target's trade and the copy, per SLOT_BUCKETS, with the overflow last
+static SLOT_DELAY: Mutex<([u64; SLOT_BUCKETS.len() + 1], u64)> =
+    Mutex::new(([0; SLOT_BUCKETS.len() + 1], 0));
+
+pub fn observe_slot_delay(slots: u64) {
+    let Ok(mut histogram) = SLOT_DELAY.lock() else {
+        return;
+    };
+    let bucket = SLOT_BUCKETS
+        .iter()
+        .position(|bound| slots <= *bound)
+        .unwrap_or(SLOT_BUCKETS.len());
+    histogram.0[bucket] += 1;
+    histogram.1 += slots;
+}
+
+/// Look up the slots the copy landed in and record how far behind the target it was, in the
+/// background.
+pub fn track_slot_delay(client: Arc<RpcClient>, target_slot: u64, signatures: Vec<String>) {
+    if target_slot == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let signatures: Vec<Signature> = signatures
+            .iter()
+            .filter_map(|signature| Signature::from_str(signature).ok())
+            .collect();
+        let Ok(statuses) = client.get_signature_statuses(&signatures).await else {
+            return;
+        };
+        let landed = statuses
+            .value
+            .iter()
+            .flatten()
+            .map(|status| status.slot)
+            .max();
+        if let Some(slot) = landed {
+            observe_slot_delay(slot.saturating_sub(target_slot));
+        }
+    });
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: impl Display) {
+    header(out, name, "counter", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn labeled(out: &mut String, name: &str, help: &str, label: &str, counter: &LabeledCounter) {
+    header(out, name, "counter", help);
+    for (value, count) in counter.snapshot() {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, value, count);
+    }
+}
+
+/// Every metric in the Prometheus text format.
+pub async fn render() -> String {
+    let mut out = String::new();
+    let sol = |counter: &Counter| counter.get() as f64 / LAMPORTS_PER_SOL;
+
+    counter(
+        &mut out,
+        "copybot_target_trades_seen_total",
+        "Stream notifications received",
+        TRADES_SEEN.get(),
+    );
+    labeled(
+        &mut out,
+        "copybot_target_trades_parsed_total",
+        "Target trades decoded",
+        "venue",
+        &TRADES_PARSED,
+    );
+    labeled(
+        &mut out,
+        "copybot_target_trades_skipped_total",
+        "Target trades not copied",
+        "reason",
+        &TRADES_SKIPPED,
+    );
+    labeled(
+        &mut out,
+        "copybot_copies_total",
+        "Copy swaps by outcome",
+        "status",
+        &COPIES,
+    );
+    counter(
+        &mut out,
+        "copybot_tip_sol_total",
+        "Jito tips paid",
+        sol(&TIP_LAMPORTS),
+    );
+    counter(
+        &mut out,
+        "copybot_priority_fee_sol_total",
+        "Priority fees paid",
+        sol(&PRIORITY_FEE_LAMPORTS),
+    );
+    counter(
+        &mut out,
+        "copybot_network_fee_sol_total",
+        "Signature fees paid",
+        sol(&NETWORK_FEE_LAMPORTS),
+    );
+    counter(
+        &mut out,
+        "copybot_ws_reconnects_total",
+        "Stream reconnections",
+        WS_RECONNECTS.get(),
+    );
+    header(
+        &mut out,
+        "copybot_stream_connected",
+        "gauge",
+        "Stream connected",
+    );
+    let connected = STREAM_CONNECTED.load(Ordering::Relaxed) as u8;
+    let _ = writeln!(out, "copybot_stream_connected {}", connected);
+    header(
+        &mut out,
+        "copybot_stream_last_message_timestamp_seconds",
+        "gauge",
+        "Unix time of the last stream message",
+    );
+    let last = LAST_MESSAGE_AT.load(Ordering::Relaxed);
+    let _ = writeln!(
+        out,
+        "copybot_stream_last_message_timestamp_seconds {}",
+        last
+    );
+
+    let name = "copybot_stage_latency_seconds";
+    header(&mut out, name, "histogram", "Latency of each copy stage");
+    for (stage, histogram) in telemetry::latency() {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS_MS.iter().zip(histogram.buckets.iter()) {
+            cumulative += count;
+            let le = *bound as f64 / 1000.0;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{stage=\"{}\",le=\"{}\"}} {}",
+                name, stage, le, cumulative
+            );
+        }
+        let (count, sum) = (histogram.count, histogram.sum.as_secs_f64());
+        let _ = writeln!(
+            out,
+            "{}_bucket{{stage=\"{}\",le=\"+Inf\"}} {}",
+            name, stage, count
+        );
+        let _ = writeln!(out, "{}_sum{{stage=\"{}\"}} {}", name, stage, sum);
+        let _ = writeln!(out, "{}_count{{stage=\"{}\"}} {}", name, stage, count);
+    }
+
+    let name = "copybot_slot_delay";
+    header(
+        &mut out,
+        name,
+        "histogram",
+        "Slots between the target's trade and the copy landing",
+    );
+    if let Ok(histogram) = SLOT_DELAY.lock() {
+        let (buckets, sum) = *histogram;
+        let mut cumulative = 0;
+        for (bound, count) in SLOT_BUCKETS.iter().zip(buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let count: u64 = buckets.iter().sum();
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+
+    let positions = position::all().await;
+    let mut exposure: BTreeMap<&str, u64> = BTreeMap::new();
+    for position in positions.iter() {
+        *exposure.entry(position.target.as_str()).or_insert(0) += position.cost_lamports;
+    }
+    header(
+        &mut out,
+        "copybot_open_positions",
+        "gauge",
+        "Open positions",
+    );
+    let _ = writeln!(out, "copybot_open_positions {}", positions.len());
+    header(
+        &mut out,
+        "copybot_exposure_sol",
+        "gauge",
+        "Cost of the open positions, by target",
+    );
+    for (target, lamports) in exposure {
+        let sol = lamports as f64 / LAMPORTS_PER_SOL;
+        let _ = writeln!(out, "copybot_exposure_sol{{target=\"{}\"}} {}", target, sol);
+    }
+    out
+}
+
+async fn respond(request: Request) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body: render().await,
+        },
+        ("GET", _) => Response::text(404, "not found\n"),
+        _ => Response::text(405, "method not allowed\n"),
+    }
+}
+
+/// Serve `GET /metrics` on `METRICS_ADDR`.
+pub async fn spawn_server() -> Result<()> {
+    let Some(addr) = METRICS_ADDR.as_deref() else {
+        return Ok(());
+    };
+    let listener = TcpListener::bind(addr).await?;
+    info!(addr = %listener.local_addr()?, "metrics server listening");
+    http::serve("metrics", listener, respond);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: vec![],
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn render_writes_counters_in_the_text_format() {
+        TRADES_SKIPPED.inc("render_test");
+        TRADES_SKIPPED.inc("render_test");
+        LAST_MESSAGE_AT.store(1_700_000_000, Ordering::Relaxed);
+        observe_slot_delay(3);
+
+        let out = render().await;
+        assert!(out.contains("# TYPE copybot_target_trades_seen_total counter\n"));
+        assert!(out.contains("copybot_target_trades_skipped_total{reason=\"render_test\"} 2\n"));
+        assert!(out.contains("copybot_stream_last_message_timestamp_seconds 1700000000\n"));
+        assert!(out.contains("copybot_slot_delay_bucket{le=\"+Inf\"}"));
+        // every sample line is a name, optional labels and a number
+        for line in out.lines().filter(|line| !line.starts_with('#')) {
+            let (_, value) = line.rsplit_once(' ').unwrap();
+            assert!(value.parse::<f64>().is_ok(), "{}", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics_are_served_on_get_only() {
+        let response = respond(request("GET", "/metrics")).await;
+        assert_eq!(response.status, 200);
+        assert!(response
+            .content_type
+            .starts_with("text/plain; version=0.0.4"));
+        assert!(response.body.contains("copybot_stream_connected"));
+        assert_eq!(respond(request("GET", "/other")).await.status, 404);
+        assert_eq!(respond(request("POST", "/metrics")).await.status, 405);
+    }
+}
@@ -1 +1,7 @@
+pub mod control;
+pub mod http;
 pub mod jito;
+pub mod metrics;
+pub mod recorder;
+pub mod store;
+pub mod trade_log;
@@ -0,0 +1,251 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::LazyLock,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, Utc};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use tracing::warn;
+
+use crate::common::utils::{env_list, env_or};
+
+// messages buffered before a gzip member is closed and flushed to disk
+const FLUSH_EVERY: usize = 100;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Directory raw stream messages are recorded to (`RECORD_DIR`), recording is off when unset
+pub static RECORD_DIR: LazyLock<Option<PathBuf>> =
+    LazyLock::new(|| std::env::var("RECORD_DIR").ok().map(PathBuf::from));
+/// Recordings to replay instead of connecting to the stream (`REPLAY`, comma separated)
+pub static REPLAY_FILES: LazyLock<Vec<String>> = LazyLock::new(|| env_list("REPLAY"));
+/// `REPLAY_PACE`, `original` or `fast` (default)
+pub static REPLAY_PACE: LazyLock<ReplayPace> =
+    LazyLock::new(|| env_or("REPLAY_PACE", ReplayPace::Fast));
+
+/// One raw stream message and when it was received
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    /// Unix time in milliseconds
+    pub received_at: i64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayPace {
+    /// Keep the gaps between messages as they were received
+    Original,
+    /// Feed messages back to back
+    Fast,
+}
+
+impl FromStr for ReplayPace {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "original" => Ok(ReplayPace::Original),
+            "fast" => Ok(ReplayPace::Fast),
+            _ => Err(anyhow!("unknown replay pace {}", s)),
+        }
+    }
+}
+
+// what the writer task is asked to do
+enum Record {
+    Message(RecordedMessage),
+    /// Write out the buffer now and confirm
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle to the background task writing the recording
+#[derive(Clone)]
+pub struct Recorder {
+    sender: UnboundedSender<Record>,
+    pub path: PathBuf,
+}
+
+impl Recorder {
+    /// Start recording to a new `stream-<time>.jsonl.gz` file in `dir`.
+    pub fn start(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "stream-{}.jsonl.gz",
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_loop(file, receiver));
+        Ok(Self { sender, path })
+    }
+
+    /// Queue a message for the recording, stamped with the current time.
+    pub fn record(&self, message: &str) {
+        let _ = self.sender.send(Record::Message(RecordedMessage {
+            received_at: Utc::now().timestamp_millis(),
+            message: message.to_string(),
+        }));
+    }
+
+    /// Write every message queued so far to disk, e.g. before shutting down.
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.sender.send(Record::Flush(done)).is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+// Each flush closes a gzip member, so a crash loses at most the messages still buffered and
+// the file stays readable as a multi-member gzip stream.
+async fn write_loop(mut file: File, mut receiver: UnboundedReceiver<Record>) {
+    let mut buffer: Vec<RecordedMessage> = vec![];
+    let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        let mut flushed = None;
+        let (closed, due) = tokio::select! {
+            record = receiver.recv() => match record {
+                Some(Record::Message(message)) => {
+                    buffer.push(message);
+                    (false, buffer.len() >= FLUSH_EVERY)
+                }
+                Some(Record::Flush(done)) => {
+                    flushed = Some(done);
+                    (false, true)
+                }
+                None => (true, true),
+            },
+            _ = ticker.tick() => (false, true),
+        };
+        if due && !buffer.is_empty() {
+            if let Err(e) = flush(&mut file, &buffer) {
+                warn!(error = %e, "recording not flushed");
+            }
+            buffer.clear();
+        }
+        if let Some(done) = flushed {
+            let _ = done.send(());
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+fn flush(file: &mut File, buffer: &[RecordedMessage]) -> Result<()> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for message in buffer {
+        serde_json::to_writer(&mut encoder, message)?;
+        encoder.write_all(b"\n")?;
+    }
+    file.write_all(&encoder.finish()?)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Read a recording back, oldest message first.
+pub fn read_recording(path: &Path) -> Result<Vec<RecordedMessage>> {
+    let reader = BufReader::new(MultiGzDecoder::new(File::open(path)?));
+    let mut messages = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        messages.push(serde_json::from_str(&line)?);
+    }
+    Ok(messages)
+}
+
+/// Feed recordings back through `handle` in order, at their original pace or as fast as
+/// possible. Returns how many messages were replayed.
+pub async fn replay<F, Fut>(paths: &[String], pace: ReplayPace, mut handle: F) -> Result<usize>
+where
+    F: FnMut(String) -> Fut,
+    Fut: std::future::Future<Output = ()>,
+{
+    let mut replayed = 0;
+    let mut previous: Option<i64> = None;
+    for path in paths {
+        for recorded in read_recording(Path::new(path))? {
+            if let (ReplayPace::Original, Some(previous)) = (pace, previous) {
+                let gap = (recorded.received_at - previous).max(0) as u64;
+                tokio::time::sleep(Duration::from_millis(gap)).await;
+            }
+            previous = Some(recorded.received_at);
+            handle(recorded.message).await;
+            replayed += 1;
+        }
+    }
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("recorder-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn flush_writes_what_is_buffered() {
+        let dir = dir("flush");
+        let recorder = Recorder::start(&dir).unwrap();
+        recorder.record("first");
+        recorder.record("second");
+        recorder.flush().await;
+        let messages: Vec<String> = read_recording(&recorder.path)
+            .unwrap()
+            .into_iter()
+            .map(|recorded| recorded.message)
+            .collect();
+        assert_eq!(messages, ["first", "second"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn flushes_append_gzip_members() {
+        let dir = dir("members");
+        let recorder = Recorder::start(&dir).unwrap();
+        recorder.record("first");
+        recorder.flush().await;
+        recorder.record("second");
+        recorder.flush().await;
+        recorder.flush().await;
+        assert_eq!(read_recording(&recorder.path).unwrap().len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replay_feeds_recordings_in_order() {
+        let dir = dir("replay");
+        let recorder = Recorder::start(&dir).unwrap();
+        for message in ["a", "b", "c"] {
+            recorder.record(message);
+        }
+        recorder.flush().await;
+        let path = recorder.path.display().to_string();
+        let mut seen = vec![];
+        let replayed = replay(&[path], ReplayPace::Fast, |message| {
+            seen.push(message);
+            async {}
+        })
+        .await
+        .unwrap();
+        assert_eq!(replayed, 3);
+        assert_eq!(seen, ["a", "b", "c"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
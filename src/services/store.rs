@@ -0,0 +1,683 @@
+use std::{
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    common::utils::env_or,
+    engine::{
+        paper::PAPER_CONFIG,
+        position::{Fill, Position, Venue},
+        swap::SwapDirection,
+    },
+};
+
+// trade journal, set once by `open`
+static STORE: OnceLock<Store> = OnceLock::new();
+
+/// Open the trade journal at `STORE_PATH` (default `./copy_trading.db`, or `./paper_trading.db`
+/// when paper trading). Called once at startup, before anything journals.
+pub fn open() -> Result<&'static Store> {
+    if let Some(store) = STORE.get() {
+        return Ok(store);
+    }
+    let default = if PAPER_CONFIG.enabled {
+        "./paper_trading.db"
+    } else {
+        "./copy_trading.db"
+    };
+    let path = env_or("STORE_PATH", default.to_string());
+    let store = Store::open(&path).map_err(|e| anyhow!("Store: failed to open {}: {}", path, e))?;
+    Ok(STORE.get_or_init(|| store))
+}
+
+/// The trade journal opened at startup.
+pub fn store() -> Result<&'static Store> {
+    STORE.get().ok_or(anyhow!("Store: not opened"))
+}
+
+// schema versions, applied in order and tracked in `PRAGMA user_version`
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE targets (
+        address TEXT PRIMARY KEY,
+        label TEXT,
+        active INTEGER NOT NULL DEFAULT 1,
+        added_at INTEGER NOT NULL
+    );
+    CREATE TABLE target_trades (
+        id INTEGER PRIMARY KEY,
+        signature TEXT NOT NULL UNIQUE,
+        target TEXT NOT NULL,
+        mint TEXT NOT NULL,
+        venue TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        seen_at INTEGER NOT NULL
+    );
+    CREATE INDEX target_trades_target ON target_trades (target, seen_at);
+    CREATE TABLE copy_attempts (
+        id INTEGER PRIMARY KEY,
+        target_signature TEXT,
+        target TEXT NOT NULL,
+        mint TEXT NOT NULL,
+        venue TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        requested INTEGER NOT NULL,
+        approved INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        error TEXT,
+        signatures TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX copy_attempts_target ON copy_attempts (target, created_at);
+    CREATE TABLE fills (
+        id INTEGER PRIMARY KEY,
+        attempt_id INTEGER REFERENCES copy_attempts (id),
+        target TEXT NOT NULL,
+        mint TEXT NOT NULL,
+        venue TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        token_amount INTEGER NOT NULL,
+        lamports INTEGER NOT NULL,
+        realized INTEGER,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE INDEX fills_mint ON fills (mint, timestamp);
+    CREATE TABLE positions (
+        mint TEXT PRIMARY KEY,
+        target TEXT NOT NULL,
+        venue TEXT NOT NULL,
+        pool_id TEXT,
+        token_amount INTEGER NOT NULL,
+        cost_lamports INTEGER NOT NULL,
+        opened_at INTEGER NOT NULL
+    );",
+    // 2: what landing each fill cost
+    "ALTER TABLE fills ADD COLUMN signatures TEXT;
+    ALTER TABLE fills ADD COLUMN network_fee INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE fills ADD COLUMN priority_fee INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE fills ADD COLUMN tip INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX fills_target ON fills (target, timestamp);",
+    // 3: which execution wallet made each fill
+    "ALTER TABLE fills ADD COLUMN wallet TEXT;",
+    // 4: holdings reconcile could not price, not looked up again on every startup
+    "CREATE TABLE unpriced_mints (
+        mint TEXT PRIMARY KEY,
+        token_amount INTEGER NOT NULL,
+        checked_at INTEGER NOT NULL
+    );",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AttemptStatus {
+    /// Stopped by the screener or the risk manager
+    Rejected,
+    Sent,
+    Landed,
+    Failed,
+}
+
+impl AttemptStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AttemptStatus::Rejected => "rejected",
+            AttemptStatus::Sent => "sent",
+            AttemptStatus::Landed => "landed",
+            AttemptStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Trade seen on a target wallet
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetTrade {
+    pub signature: String,
+    pub target: String,
+    pub mint: String,
+    pub venue: Venue,
+    pub direction: SwapDirection,
+    /// Lamports in on a buy, tokens in on a sell
+    pub amount: u64,
+    pub seen_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CopyAttempt {
+    pub id: i64,
+    pub target_signature: Option<String>,
+    pub target: String,
+    pub mint: String,
+    pub venue: String,
+    pub direction: String,
+    pub requested: u64,
+    pub approved: u64,
+    pub status: String,
+    pub error: Option<String>,
+    pub signatures: Vec<String>,
+    pub created_at: i64,
+}
+
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+fn direction_str(direction: SwapDirection) -> &'static str {
+    match direction {
+        SwapDirection::Buy => "buy",
+        SwapDirection::Sell => "sell",
+    }
+}
+
+fn parse_direction(direction: &str) -> rusqlite::Result<SwapDirection> {
+    match direction {
+        "buy" => Ok(SwapDirection::Buy),
+        "sell" => Ok(SwapDirection::Sell),
+        _ => Err(rusqlite::Error::InvalidColumnType(
+            0,
+            direction.to_string(),
+            rusqlite::types::Type::Text,
+        )),
+    }
+}
+
+fn parse_venue(venue: &str) -> rusqlite::Result<Venue> {
+    Venue::from_str(venue).map_err(|_| {
+        rusqlite::Error::InvalidColumnType(0, venue.to_string(), rusqlite::types::Type::Text)
+    })
+}
+
+impl Store {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")?;
+        let store = Self {
+            conn: Mutex::new(conn),
+        };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn.lock().map_err(|_| anyhow!("store lock poisoned"))
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let mut conn = self.conn()?;
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    pub fn upsert_target(&self, address: &str, label: Option<&str>, active: bool) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT INTO targets (address, label, active, added_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (address) DO UPDATE SET label = COALESCE(?2, label), active = ?3",
+            params![address, label, active, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_target_trade(&self, trade: &TargetTrade) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT OR IGNORE INTO target_trades
+             (signature, target, mint, venue, direction, amount, seen_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                trade.signature,
+                trade.target,
+                trade.mint,
+                trade.venue.to_string(),
+                direction_str(trade.direction),
+                trade.amount as i64,
+                trade.seen_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Record a copy attempt and return its id.
+    pub fn record_attempt(
+        &self,
+        target_signature: Option<&str>,
+        target: &str,
+        mint: &str,
+        venue: Venue,
+        direction: SwapDirection,
+        requested: u64,
+        approved: u64,
+        status: AttemptStatus,
+    ) -> Result<i64> {
+        let now = Utc::now().timestamp();
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO copy_attempts
+             (target_signature, target, mint, venue, direction, requested, approved, status,
+              created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)",
+            params![
+                target_signature,
+                target,
+                mint,
+                venue.to_string(),
+                direction_str(direction),
+                requested as i64,
+                approved as i64,
+                status.as_str(),
+                now
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn update_attempt(
+        &self,
+        id: i64,
+        status: AttemptStatus,
+        error: Option<&str>,
+        signatures: &[String],
+    ) -> Result<()> {
+        self.conn()?.execute(
+            "UPDATE copy_attempts SET status = ?2, error = ?3, signatures = ?4, updated_at = ?5
+             WHERE id = ?1",
+            params![
+                id,
+                status.as_str(),
+                error,
+                serde_json::to_string(signatures)?,
+                Utc::now().timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_fill(&self, fill: &Fill, attempt_id: Option<i64>) -> Result<i64> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO fills
+             (attempt_id, target, mint, venue, direction, token_amount, lamports, realized,
+              timestamp, signatures, network_fee, priority_fee, tip, wallet)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                attempt_id,
+                fill.target,
+                fill.mint,
+                fill.venue.to_string(),
+                direction_str(fill.direction),
+                fill.token_amount as i64,
+                fill.lamports as i64,
+                fill.realized,
+                fill.timestamp,
+                serde_json::to_string(&fill.signatures)?,
+                fill.network_fee as i64,
+                fill.priority_fee as i64,
+                fill.tip as i64,
+                fill.wallet
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Remember that `token_amount` of `mint` could not be priced.
+    pub fn mark_unpriced(&self, mint: &str, token_amount: u64) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT INTO unpriced_mints (mint, token_amount, checked_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (mint) DO UPDATE SET token_amount = ?2, checked_at = ?3",
+            params![mint, token_amount as i64, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// When the same holding of `mint` was last found unpriced, `None` if it never was or the
+    /// amount has changed since.
+    pub fn unpriced_at(&self, mint: &str, token_amount: u64) -> Result<Option<i64>> {
+        let conn = self.conn()?;
+        let checked_at = conn
+            .query_row(
+                "SELECT checked_at FROM unpriced_mints WHERE mint = ?1 AND token_amount = ?2",
+                params![mint, token_amount as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(checked_at)
+    }
+
+    pub fn save_position(&self, position: &Position) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT INTO positions
+             (mint, target, venue, pool_id, token_amount, cost_lamports, opened_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (mint) DO UPDATE SET target = ?2, venue = ?3, pool_id = ?4,
+             token_amount = ?5, cost_lamports = ?6",
+            params![
+                position.mint,
+                position.target,
+                position.venue.to_string(),
+                position.pool_id,
+                position.token_amount as i64,
+                position.cost_lamports as i64,
+                position.opened_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_position(&self, mint: &str) -> Result<()> {
+        self.conn()?
+            .execute("DELETE FROM positions WHERE mint = ?1", params![mint])?;
+        Ok(())
+    }
+
+    pub fn load_positions(&self) -> Result<Vec<Position>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT mint, target, venue, pool_id, token_amount, cost_lamports, opened_at
+             FROM positions",
+        )?;
+        let positions = stmt
+            .query_map([], |row| {
+                Ok(Position {
+                    mint: row.get(0)?,
+                    target: row.get(1)?,
+                    venue: parse_venue(&row.get::<_, String>(2)?)?,
+                    pool_id: row.get(3)?,
+                    token_amount: row.get::<_, i64>(4)? as u64,
+                    cost_lamports: row.get::<_, i64>(5)? as u64,
+                    opened_at: row.get(6)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(positions)
+    }
+
+    fn fill_from_row(row: &Row) -> rusqlite::Result<Fill> {
+        let signatures: Option<String> = row.get(8)?;
+        Ok(Fill {
+            target: row.get(0)?,
+            mint: row.get(1)?,
+            venue: parse_venue(&row.get::<_, String>(2)?)?,
+            direction: parse_direction(&row.get::<_, String>(3)?)?,
+            token_amount: row.get::<_, i64>(4)? as u64,
+            lamports: row.get::<_, i64>(5)? as u64,
+            realized: row.get(6)?,
+            timestamp: row.get(7)?,
+            signatures: signatures
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default(),
+            network_fee: row.get::<_, i64>(9)? as u64,
+            priority_fee: row.get::<_, i64>(10)? as u64,
+            tip: row.get::<_, i64>(11)? as u64,
+            wallet: row.get(12)?,
+        })
+    }
+
+    /// Fills since `since` (unix seconds), oldest first, optionally for one target or mint.
+    pub fn fills(&self, since: i64, target: Option<&str>, mint: Option<&str>) -> Result<Vec<Fill>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT target, mint, venue, direction, token_amount, lamports, realized, timestamp,
+                    signatures, network_fee, priority_fee, tip, wallet
+             FROM fills
+             WHERE timestamp >= ?1 AND (?2 IS NULL OR target = ?2) AND (?3 IS NULL OR mint = ?3)
+             ORDER BY timestamp, id",
+        )?;
+        let fills = stmt
+            .query_map(params![since, target, mint], Self::fill_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(fills)
+    }
+
+    pub fn recent_attempts(&self, limit: usize) -> Result<Vec<CopyAttempt>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, target_signature, target, mint, venue, direction, requested, approved,
+                    status, error, signatures, created_at
+             FROM copy_attempts ORDER BY id DESC LIMIT ?1",
+        )?;
+        let attempts = stmt
+            .query_map(params![limit as i64], |row| {
+                let signatures: Option<String> = row.get(10)?;
+                Ok(CopyAttempt {
+                    id: row.get(0)?,
+                    target_signature: row.get(1)?,
+                    target: row.get(2)?,
+                    mint: row.get(3)?,
+                    venue: row.get(4)?,
+                    direction: row.get(5)?,
+                    requested: row.get::<_, i64>(6)? as u64,
+                    approved: row.get::<_, i64>(7)? as u64,
+                    status: row.get(8)?,
+                    error: row.get(9)?,
+                    signatures: signatures
+                        .and_then(|s| serde_json::from_str(&s).ok())
+                        .unwrap_or_default(),
+                    created_at: row.get(11)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(attempts)
+    }
+
+    pub fn target_trades(&self, target: &str) -> Result<Vec<TargetTrade>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT signature, target, mint, venue, direction, amount, seen_at
+             FROM target_trades WHERE target = ?1 ORDER BY seen_at, id",
+        )?;
+        let trades = stmt
+            .query_map(params![target], |row| {
+                Ok(TargetTrade {
+                    signature: row.get(0)?,
+                    target: row.get(1)?,
+                    mint: row.get(2)?,
+                    venue: parse_venue(&row.get::<_, String>(3)?)?,
+                    direction: parse_direction(&row.get::<_, String>(4)?)?,
+                    amount: row.get::<_, i64>(5)? as u64,
+                    seen_at: row.get(6)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(trades)
+    }
+}
+
+/// Persist a position change, a position with nothing left is removed.
+pub fn persist_position(mint: &str, position: Option<&Position>) {
+    let res = match position {
+        Some(position) => store().and_then(|store| store.save_position(position)),
+        None => store().and_then(|store| store.delete_position(mint)),
+    };
+    if let Err(e) = res {
+        warn!(mint, error = %e, "position not journaled");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_memory() -> Store {
+        let store = Store {
+            conn: Mutex::new(Connection::open_in_memory().unwrap()),
+        };
+        store.migrate().unwrap();
+        store
+    }
+
+    fn user_version(store: &Store) -> usize {
+        store
+            .conn()
+            .unwrap()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    fn fill(direction: SwapDirection, timestamp: i64) -> Fill {
+        Fill {
+            mint: "mint".to_string(),
+            target: "target".to_string(),
+            venue: Venue::Raydium,
+            direction,
+            token_amount: 1_000,
+            lamports: 2_000_000,
+            realized: (direction == SwapDirection::Sell).then_some(-15_000),
+            timestamp,
+            signatures: vec!["sig1".to_string(), "sig2".to_string()],
+            network_fee: 5_000,
+            priority_fee: 7_000,
+            tip: 10_000,
+            wallet: Some("wallet".to_string()),
+        }
+    }
+
+    #[test]
+    fn a_version_one_journal_is_migrated_and_its_fills_read_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        conn.execute(
+            "INSERT INTO fills (target, mint, venue, direction, token_amount, lamports, realized,
+                                timestamp)
+             VALUES ('target', 'mint', 'pump', 'buy', 500, 1000000, NULL, 100)",
+            [],
+        )
+        .unwrap();
+        let store = Store {
+            conn: Mutex::new(conn),
+        };
+
+        store.migrate().unwrap();
+        assert_eq!(user_version(&store), MIGRATIONS.len());
+        // migrating again is a no-op
+        store.migrate().unwrap();
+        assert_eq!(user_version(&store), MIGRATIONS.len());
+
+        store
+            .record_fill(&fill(SwapDirection::Sell, 200), None)
+            .unwrap();
+        let fills = store.fills(0, None, None).unwrap();
+        assert_eq!(fills.len(), 2);
+        let old = &fills[0];
+        assert_eq!(
+            (old.venue, old.direction),
+            (Venue::Pump, SwapDirection::Buy)
+        );
+        assert_eq!((old.token_amount, old.lamports), (500, 1_000_000));
+        assert!(old.signatures.is_empty());
+        assert_eq!((old.network_fee, old.priority_fee, old.tip), (0, 0, 0));
+        assert_eq!(old.wallet, None);
+        let new = &fills[1];
+        assert_eq!(
+            (new.network_fee, new.priority_fee, new.tip),
+            (5_000, 7_000, 10_000)
+        );
+        assert_eq!(new.wallet.as_deref(), Some("wallet"));
+    }
+
+    #[test]
+    fn fills_round_trip_and_filter() {
+        let store = in_memory();
+        store
+            .record_fill(&fill(SwapDirection::Buy, 100), None)
+            .unwrap();
+        let mut other = fill(SwapDirection::Buy, 150);
+        other.target = "other".to_string();
+        other.mint = "other_mint".to_string();
+        store.record_fill(&other, None).unwrap();
+        store
+            .record_fill(&fill(SwapDirection::Sell, 200), None)
+            .unwrap();
+
+        let fills = store.fills(0, Some("target"), None).unwrap();
+        assert_eq!(fills.len(), 2);
+        let sell = &fills[1];
+        let expected = fill(SwapDirection::Sell, 200);
+        assert_eq!(
+            (
+                sell.mint.as_str(),
+                sell.target.as_str(),
+                sell.venue,
+                sell.direction
+            ),
+            ("mint", "target", Venue::Raydium, SwapDirection::Sell)
+        );
+        assert_eq!(
+            (
+                sell.token_amount,
+                sell.lamports,
+                sell.realized,
+                sell.timestamp
+            ),
+            (1_000, 2_000_000, Some(-15_000), 200)
+        );
+        assert_eq!(sell.signatures, expected.signatures);
+        assert_eq!(
+            (sell.network_fee, sell.priority_fee, sell.tip),
+            (5_000, 7_000, 10_000)
+        );
+        assert_eq!(sell.wallet, expected.wallet);
+
+        assert_eq!(store.fills(150, None, None).unwrap().len(), 2);
+        assert_eq!(store.fills(0, None, Some("other_mint")).unwrap().len(), 1);
+        assert!(store.fills(201, None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unpriced_holdings_are_remembered_by_amount() {
+        let store = in_memory();
+        assert_eq!(store.unpriced_at("mint", 10).unwrap(), None);
+        store.mark_unpriced("mint", 10).unwrap();
+        assert!(store.unpriced_at("mint", 10).unwrap().is_some());
+        assert_eq!(store.unpriced_at("mint", 11).unwrap(), None);
+        store.mark_unpriced("mint", 11).unwrap();
+        assert_eq!(store.unpriced_at("mint", 10).unwrap(), None);
+        assert!(store.unpriced_at("mint", 11).unwrap().is_some());
+    }
+
+    #[test]
+    fn positions_are_saved_updated_and_deleted() {
+        let store = in_memory();
+        let mut position = Position {
+            mint: "mint".to_string(),
+            target: "target".to_string(),
+            venue: Venue::Pump,
+            pool_id: None,
+            token_amount: 1_000,
+            cost_lamports: 2_000_000,
+            opened_at: 100,
+        };
+        store.save_position(&position).unwrap();
+        position.venue = Venue::Raydium;
+        position.pool_id = Some("pool".to_string());
+        position.token_amount = 400;
+        position.cost_lamports = 800_000;
+        position.opened_at = 300;
+        store.save_position(&position).unwrap();
+
+        let positions = store.load_positions().unwrap();
+        assert_eq!(positions.len(), 1);
+        let saved = &positions[0];
+        assert_eq!(
+            (saved.venue, saved.pool_id.as_deref()),
+            (Venue::Raydium, Some("pool"))
+        );
+        assert_eq!((saved.token_amount, saved.cost_lamports), (400, 800_000));
+        // an update keeps when the position was opened
+        assert_eq!(saved.opened_at, 100);
+
+        store.delete_position("mint").unwrap();
+        assert!(store.load_positions().unwrap().is_empty());
+    }
+}
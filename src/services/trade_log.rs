@@ -0,0 +1,372 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        LazyLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde::Serialize;
+use tracing::{error, warn};
+
+use crate::{
+    common::utils::env_or,
+    engine::{position::Venue, swap::SwapDirection},
+};
+
+pub static TRADE_LOG_CONFIG: LazyLock<TradeLogConfig> = LazyLock::new(TradeLogConfig::from_env);
+// the writer thread is started by the first record
+static TRADE_LOG: LazyLock<Sender<Message>> = LazyLock::new(start);
+
+const ACTIVE_FILE: &str = "trades.jsonl";
+const ROTATED_PREFIX: &str = "trades-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Hourly,
+    Daily,
+    /// Rotate on size only
+    Never,
+}
+
+impl FromStr for Rotation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "hourly" => Ok(Rotation::Hourly),
+            "daily" => Ok(Rotation::Daily),
+            "never" => Ok(Rotation::Never),
+            _ => Err(anyhow!("unknown trade log rotation {}", s)),
+        }
+    }
+}
+
+impl Rotation {
+    // files written in different periods are rotated apart
+    fn period(&self, time: DateTime<Local>) -> String {
+        match self {
+            Rotation::Hourly => time.format("%Y%m%d%H").to_string(),
+            Rotation::Daily => time.format("%Y%m%d").to_string(),
+            Rotation::Never => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TradeLogConfig {
+    pub dir: PathBuf,
+    /// Size the active file is rotated at, in bytes
+    pub max_bytes: u64,
+    pub rotation: Rotation,
+    /// Rotated files kept, the oldest are deleted
+    pub keep: usize,
+}
+
+impl TradeLogConfig {
+    pub fn from_env() -> Self {
+        Self {
+            dir: PathBuf::from(env_or("TRADE_LOG_DIR", "./logs".to_string())),
+            max_bytes: env_or("TRADE_LOG_MAX_MB", 50u64) * 1024 * 1024,
+            rotation: env_or("TRADE_LOG_ROTATION", Rotation::Daily),
+            keep: env_or("TRADE_LOG_KEEP", 14),
+        }
+    }
+
+    /// File the records are currently appended to.
+    pub fn active_path(&self) -> PathBuf {
+        self.dir.join(ACTIVE_FILE)
+    }
+}
+
+/// What a record is about
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    /// Trade of a target seen on the stream
+    TargetTrade {
+        target: String,
+        signature: String,
+        mint: String,
+        venue: Venue,
+        direction: SwapDirection,
+        amount: u64,
+    },
+    /// Outcome of one copy swap
+    Copy {
+        target: String,
+        /// The target's trade
+        target_signature: String,
+        mint: String,
+        venue: Venue,
+        direction: SwapDirection,
+        amount: u64,
+        wallet: String,
+        landed: bool,
+        signatures: Vec<String>,
+        error: Option<String>,
+        latency_ms: u64,
+    },
+    /// Outcome of one sell made outside a copy swap: an exit rule, a sell or panic from the
+    /// control API, or a pre-signed exit (also recorded as the copy it was fired for)
+    Exit {
+        mint: String,
+        venue: Venue,
+        /// Why it sold, e.g. the exit rule or `panic`
+        reason: String,
+        amount: u64,
+        wallet: String,
+        landed: bool,
+        signatures: Vec<String>,
+        error: Option<String>,
+    },
+    /// Free-form message from the engine
+    Note { message: String },
+}
+
+impl Event {
+    /// One line for a human reading the log.
+    pub fn summary(&self) -> String {
+        match self {
+            Event::TargetTrade {
+                target,
+                signature,
+                mint,
+                venue,
+                direction,
+                amount,
+            } => format!(
+                "target {} {:?} {} on {} for {}, {}",
+                target, direction, mint, venue, amount, signature
+            ),
+            Event::Copy {
+                mint,
+                venue,
+                direction,
+                amount,
+                wallet,
+                landed: true,
+                signatures,
+                latency_ms,
+                ..
+            } => format!(
+                "copy {:?} {} on {} for {} from {} landed in {} ms, {}",
+                direction,
+                mint,
+                venue,
+                amount,
+                wallet,
+                latency_ms,
+                signatures.join(", ")
+            ),
+            Event::Copy {
+                mint,
+                venue,
+                direction,
+                amount,
+                wallet,
+                error,
+                ..
+            } => format!(
+                "copy {:?} {} on {} for {} from {} failed: {}",
+                direction,
+                mint,
+                venue,
+                amount,
+                wallet,
+                error.as_deref().unwrap_or("unknown error")
+            ),
+            Event::Exit {
+                mint,
+                venue,
+                reason,
+                amount,
+                wallet,
+                landed: true,
+                signatures,
+                ..
+            } => format!(
+                "exit {} on {} for {} from {} ({}) landed, {}",
+                mint,
+                venue,
+                amount,
+                wallet,
+                reason,
+                signatures.join(", ")
+            ),
+            Event::Exit {
+                mint,
+                venue,
+                reason,
+                amount,
+                wallet,
+                error,
+                ..
+            } => format!(
+                "exit {} on {} for {} from {} ({}) failed: {}",
+                mint,
+                venue,
+                amount,
+                wallet,
+                reason,
+                error.as_deref().unwrap_or("unknown error")
+            ),
+            Event::Note { message } => message.clone(),
+        }
+    }
+}
+
+/// One JSON line of the trade log
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    /// RFC 3339, UTC
+    pub time: String,
+    #[serde(flatten)]
+    pub event: Event,
+    pub summary: String,
+}
+
+/// Queue a record for the trade log. Never blocks on the file.
+pub fn record(event: Event) {
+    let record = Record {
+        time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        summary: event.summary(),
+        event,
+    };
+    let _ = TRADE_LOG.send(Message::Record(record));
+}
+
+/// Wait, at most `timeout`, for the queued records to reach the file. For commands that exit
+/// right after logging.
+pub fn flush(timeout: Duration) {
+    let (done, flushed) = mpsc::channel();
+    if TRADE_LOG.send(Message::Flush(done)).is_ok() {
+        let _ = flushed.recv_timeout(timeout);
+    }
+}
+
+enum Message {
+    Record(Record),
+    // acknowledged once everything queued before it is written
+    Flush(Sender<()>),
+}
+
+/// Queue a free-form message.
+pub fn note(message: &str) {
+    record(Event::Note {
+        message: message.trim().to_string(),
+    });
+}
+
+fn start() -> Sender<Message> {
+    let (sender, receiver) = mpsc::channel();
+    let config = TRADE_LOG_CONFIG.clone();
+    thread::spawn(move || write_loop(&config, receiver));
+    sender
+}
+
+// Handle to the active file, and what decides when it rotates
+struct Active {
+    writer: BufWriter<File>,
+    size: u64,
+    period: String,
+}
+
+fn open(config: &TradeLogConfig) -> io::Result<Active> {
+    fs::create_dir_all(&config.dir)?;
+    let path = config.active_path();
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    let metadata = file.metadata()?;
+    let written: DateTime<Local> = metadata.modified()?.into();
+    Ok(Active {
+        writer: BufWriter::new(file),
+        size: metadata.len(),
+        period: config.rotation.period(written),
+    })
+}
+
+// Move the active file aside as `trades-<time>.jsonl` and delete the oldest beyond `keep`.
+fn rotate(config: &TradeLogConfig) -> io::Result<()> {
+    let rotated = config.dir.join(format!(
+        "{}{}.jsonl",
+        ROTATED_PREFIX,
+        Local::now().format("%Y%m%d-%H%M%S%.3f")
+    ));
+    fs::rename(config.active_path(), rotated)?;
+    let mut old: Vec<PathBuf> = fs::read_dir(&config.dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_rotated(path))
+        .collect();
+    old.sort();
+    let excess = old.len().saturating_sub(config.keep);
+    for path in old.into_iter().take(excess) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn is_rotated(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(ROTATED_PREFIX) && name.ends_with(".jsonl"))
+}
+
+// Writes what is queued and flushes once the queue is drained, so a burst of records costs one
+// flush. A failed rotation keeps appending to the active file.
+fn write_loop(config: &TradeLogConfig, receiver: Receiver<Message>) {
+    let mut active = match open(config) {
+        Ok(active) => active,
+        Err(e) => {
+            error!(dir = %config.dir.display(), error = %e, "trade log not opened");
+            return;
+        }
+    };
+    while let Ok(first) = receiver.recv() {
+        let mut waiting = vec![];
+        for message in std::iter::once(first).chain(receiver.try_iter()) {
+            let record = match message {
+                Message::Record(record) => record,
+                Message::Flush(done) => {
+                    waiting.push(done);
+                    continue;
+                }
+            };
+            let Ok(mut line) = serde_json::to_vec(&record) else {
+                continue;
+            };
+            line.push(b'\n');
+            let period = config.rotation.period(Local::now());
+            let full = active.size + line.len() as u64 > config.max_bytes;
+            if active.size > 0 && (full || period != active.period) {
+                let rotated = active
+                    .writer
+                    .flush()
+                    .and_then(|_| rotate(config))
+                    .and_then(|_| open(config));
+                match rotated {
+                    Ok(next) => active = next,
+                    Err(e) => warn!(error = %e, "trade log not rotated"),
+                }
+                active.period = period;
+            }
+            if let Err(e) = active.writer.write_all(&line) {
+                warn!(error = %e, "trade log record not written");
+                continue;
+            }
+            active.size += line.len() as u64;
+        }
+        if let Err(e) = active.writer.flush() {
+            warn!(error = %e, "trade log not flushed");
+        }
+        for done in waiting {
+            let _ = done.send(());
+        }
+    }
+}